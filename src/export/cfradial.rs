@@ -0,0 +1,362 @@
+use std::collections::BTreeSet;
+
+use crate::export::iso8601;
+use crate::export::netcdf::{NcData, NcFile};
+use crate::volume::{Moment, MomentData, Radial, Volume};
+
+// CF/Radial 1.4 export to NetCDF-3 (64-bit offset). Rays are written in
+// collection order with one `time` entry per radial; moments are packed as
+// shorts using the Message 31 scale and offset, so the stored integers are
+// the raw gate codes and readers recover the same physical values.
+
+const STRING_LENGTH: usize = 32;
+const FILL_VALUE: i16 = -32768;
+
+pub fn field_name(moment: Moment) -> &'static str {
+    match moment {
+        Moment::Reflectivity => "DBZ",
+        Moment::Velocity => "VEL",
+        Moment::SpectrumWidth => "WIDTH",
+        Moment::DifferentialReflectivity => "ZDR",
+        Moment::DifferentialPhase => "PHIDP",
+        Moment::CorrelationCoefficient => "RHOHV",
+        Moment::ClutterFilterPower => "CFP",
+    }
+}
+
+fn standard_name(moment: Moment) -> Option<&'static str> {
+    match moment {
+        Moment::Reflectivity => Some("equivalent_reflectivity_factor"),
+        Moment::Velocity => Some("radial_velocity_of_scatterers_away_from_instrument"),
+        Moment::SpectrumWidth => Some("doppler_spectrum_width"),
+        Moment::DifferentialReflectivity => Some("log_differential_reflectivity_hv"),
+        Moment::DifferentialPhase => Some("differential_phase_hv"),
+        Moment::CorrelationCoefficient => Some("cross_correlation_ratio_hv"),
+        Moment::ClutterFilterPower => None,
+    }
+}
+
+fn long_name(moment: Moment) -> &'static str {
+    match moment {
+        Moment::Reflectivity => "equivalent_reflectivity_factor",
+        Moment::Velocity => "radial_velocity",
+        Moment::SpectrumWidth => "spectrum_width",
+        Moment::DifferentialReflectivity => "differential_reflectivity",
+        Moment::DifferentialPhase => "differential_phase",
+        Moment::CorrelationCoefficient => "cross_correlation_ratio",
+        Moment::ClutterFilterPower => "clutter_filter_power_removed",
+    }
+}
+
+pub fn write_cfradial(volume: &Volume, fp: &str) -> anyhow::Result<()> {
+    cfradial_file(volume)?.write(fp)
+}
+
+// Builds the CF/Radial data set in memory.
+pub fn cfradial_file(volume: &Volume) -> anyhow::Result<NcFile> {
+    let rays: Vec<&Radial> = volume
+        .sweeps
+        .iter()
+        .flat_map(|s| s.radials.iter())
+        .collect();
+    if rays.is_empty() {
+        anyhow::bail!("Volume has no radials to export");
+    }
+    let site = volume.require_site()?;
+    // Whole seconds, so ray times are offsets from the reference time written
+    // in the units string.
+    let start = volume.start_time().unwrap_or_default().floor();
+    let end = volume
+        .sweeps
+        .iter()
+        .filter_map(|s| s.end_time())
+        .reduce(f64::max)
+        .unwrap_or(start);
+
+    let moments: BTreeSet<Moment> = rays
+        .iter()
+        .flat_map(|r| r.moments.iter().map(|m| m.moment))
+        .collect();
+    // The range axis follows reflectivity where present; other moments are
+    // placed on it by nearest gate.
+    let reference = rays
+        .iter()
+        .find_map(|r| r.moment(Moment::Reflectivity))
+        .or_else(|| rays.iter().find_map(|r| r.moments.first()))
+        .ok_or_else(|| anyhow::anyhow!("Volume has no moment data to export"))?;
+    let first_gate = reference.first_gate_range();
+    let gate_spacing = reference.gate_spacing();
+    let n_range = rays
+        .iter()
+        .flat_map(|r| r.moments.iter())
+        .map(|m| m.gates_on_axis(first_gate, gate_spacing))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let sweeps: Vec<_> = volume
+        .sweeps
+        .iter()
+        .filter(|s| !s.radials.is_empty())
+        .collect();
+
+    let mut nc = NcFile::new();
+    nc.add_dim("time", rays.len());
+    nc.add_dim("range", n_range);
+    nc.add_dim("sweep", sweeps.len());
+    nc.add_dim("string_length", STRING_LENGTH);
+
+    nc.add_attribute("Conventions", "CF/Radial instrument_parameters");
+    nc.add_attribute("version", "1.4");
+    nc.add_attribute("title", "NEXRAD Level II volume");
+    nc.add_attribute("institution", "");
+    nc.add_attribute("references", "");
+    nc.add_attribute("source", volume.header.volumename.trim());
+    nc.add_attribute("history", "");
+    nc.add_attribute("comment", "");
+    nc.add_attribute("instrument_name", volume.header.icao.trim());
+    nc.add_attribute("time_coverage_start", iso8601(start).as_str());
+    nc.add_attribute("time_coverage_end", iso8601(end).as_str());
+    nc.add_attribute("platform_is_mobile", "false");
+    // VOL block calibration and scan metadata beyond the CF/Radial core.
+    nc.add_attribute("vcp_number", NcData::Int(vec![site.vcp_number as i32]));
+    nc.add_attribute(
+        "calibration_constant",
+        NcData::Float(vec![site.calibration_constant]),
+    );
+    nc.add_attribute(
+        "horizontal_shv_tx_power",
+        NcData::Float(vec![site.horizontal_shv_tx_power]),
+    );
+    nc.add_attribute(
+        "vertical_shv_tx_power",
+        NcData::Float(vec![site.vertical_shv_tx_power]),
+    );
+    nc.add_attribute(
+        "system_differential_reflectivity",
+        NcData::Float(vec![site.system_differential_reflectivity]),
+    );
+    nc.add_attribute(
+        "initial_system_differential_phase",
+        NcData::Float(vec![site.initial_system_differential_phase]),
+    );
+
+    let volume_number = volume
+        .header
+        .volumename
+        .rsplit('.')
+        .next()
+        .and_then(|n| n.trim().parse::<i32>().ok())
+        .unwrap_or(0);
+    nc.add_variable("volume_number", &[], NcData::Int(vec![volume_number]))?;
+    nc.add_variable("platform_type", &["string_length"], padded("fixed"))?;
+    nc.add_variable("primary_axis", &["string_length"], padded("axis_z"))?;
+    nc.add_variable("instrument_type", &["string_length"], padded("radar"))?;
+    nc.add_variable(
+        "time_coverage_start",
+        &["string_length"],
+        padded(&iso8601(start)),
+    )?;
+    nc.add_variable(
+        "time_coverage_end",
+        &["string_length"],
+        padded(&iso8601(end)),
+    )?;
+
+    nc.add_variable("latitude", &[], NcData::Double(vec![site.latitude as f64]))?
+        .attribute("long_name", "latitude")
+        .attribute("units", "degrees_north");
+    nc.add_variable(
+        "longitude",
+        &[],
+        NcData::Double(vec![site.longitude as f64]),
+    )?
+    .attribute("long_name", "longitude")
+    .attribute("units", "degrees_east");
+    nc.add_variable(
+        "altitude",
+        &[],
+        NcData::Double(vec![site.site_height as f64 + site.feedhorn_height as f64]),
+    )?
+    .attribute("long_name", "altitude")
+    .attribute("units", "meters")
+    .attribute("positive", "up");
+    nc.add_variable(
+        "altitude_agl",
+        &[],
+        NcData::Double(vec![site.feedhorn_height as f64]),
+    )?
+    .attribute("long_name", "altitude_above_ground_level")
+    .attribute("units", "meters");
+
+    let mut sweep_mode = Vec::with_capacity(sweeps.len() * STRING_LENGTH);
+    let mut start_index = Vec::with_capacity(sweeps.len());
+    let mut end_index = Vec::with_capacity(sweeps.len());
+    let mut ray = 0;
+    for sweep in sweeps.iter() {
+        if let NcData::Char(mode) = padded("azimuth_surveillance") {
+            sweep_mode.extend(mode);
+        }
+        start_index.push(ray as i32);
+        ray += sweep.radials.len();
+        end_index.push(ray as i32 - 1);
+    }
+    nc.add_variable(
+        "sweep_number",
+        &["sweep"],
+        NcData::Int((0..sweeps.len() as i32).collect()),
+    )?
+    .attribute("long_name", "sweep_index_number_0_based");
+    nc.add_variable(
+        "sweep_mode",
+        &["sweep", "string_length"],
+        NcData::Char(sweep_mode),
+    )?
+    .attribute("long_name", "scan_mode_for_sweep");
+    nc.add_variable(
+        "fixed_angle",
+        &["sweep"],
+        NcData::Float(sweeps.iter().map(|s| s.elevation_angle).collect()),
+    )?
+    .attribute("long_name", "ray_target_fixed_angle")
+    .attribute("units", "degrees");
+    nc.add_variable(
+        "sweep_start_ray_index",
+        &["sweep"],
+        NcData::Int(start_index),
+    )?
+    .attribute("long_name", "index_of_first_ray_in_sweep");
+    nc.add_variable("sweep_end_ray_index", &["sweep"], NcData::Int(end_index))?
+        .attribute("long_name", "index_of_last_ray_in_sweep");
+
+    nc.add_variable(
+        "time",
+        &["time"],
+        NcData::Double(rays.iter().map(|r| r.time() - start).collect()),
+    )?
+    .attribute("standard_name", "time")
+    .attribute("long_name", "time_in_seconds_since_volume_start")
+    .attribute(
+        "units",
+        format!("seconds since {}", iso8601(start)).as_str(),
+    )
+    .attribute("calendar", "gregorian");
+    nc.add_variable(
+        "range",
+        &["range"],
+        NcData::Float(
+            (0..n_range)
+                .map(|g| first_gate + g as f32 * gate_spacing)
+                .collect(),
+        ),
+    )?
+    .attribute("standard_name", "projection_range_coordinate")
+    .attribute("long_name", "range_to_measurement_volume")
+    .attribute("units", "meters")
+    .attribute("axis", "radial_range_coordinate")
+    .attribute("spacing_is_constant", "true")
+    .attribute(
+        "meters_to_center_of_first_gate",
+        NcData::Float(vec![first_gate]),
+    )
+    .attribute("meters_between_gates", NcData::Float(vec![gate_spacing]));
+    nc.add_variable(
+        "azimuth",
+        &["time"],
+        NcData::Float(rays.iter().map(|r| r.header.azimuth_angle).collect()),
+    )?
+    .attribute("standard_name", "ray_azimuth_angle")
+    .attribute("long_name", "azimuth_angle_from_true_north")
+    .attribute("units", "degrees")
+    .attribute("axis", "radial_azimuth_coordinate");
+    nc.add_variable(
+        "elevation",
+        &["time"],
+        NcData::Float(rays.iter().map(|r| r.header.elevation_angle).collect()),
+    )?
+    .attribute("standard_name", "ray_elevation_angle")
+    .attribute("long_name", "elevation_angle_from_horizontal_plane")
+    .attribute("units", "degrees")
+    .attribute("axis", "radial_elevation_coordinate");
+
+    nc.add_variable(
+        "nyquist_velocity",
+        &["time"],
+        NcData::Float(
+            rays.iter()
+                .map(|r| r.nyquist_velocity().unwrap_or(f32::NAN))
+                .collect(),
+        ),
+    )?
+    .attribute("long_name", "unambiguous_doppler_velocity")
+    .attribute("units", "meters per second")
+    .attribute("meta_group", "instrument_parameters");
+    nc.add_variable(
+        "unambiguous_range",
+        &["time"],
+        NcData::Float(
+            rays.iter()
+                .map(|r| {
+                    r.radial
+                        .as_ref()
+                        .map_or(f32::NAN, |b| b.unambiguous_range * 1000.0)
+                })
+                .collect(),
+        ),
+    )?
+    .attribute("long_name", "unambiguous_range")
+    .attribute("units", "meters")
+    .attribute("meta_group", "instrument_parameters");
+
+    for &moment in moments.iter() {
+        let header = &rays
+            .iter()
+            .find_map(|r| r.moment(moment))
+            .expect("moment was collected from these rays")
+            .header;
+        let scale_factor = 1.0 / header.scale;
+        let add_offset = -header.offset / header.scale;
+
+        let mut data = vec![FILL_VALUE; rays.len() * n_range];
+        for (row, radial) in data.chunks_mut(n_range).zip(rays.iter()) {
+            if let Some(m) = radial.moment(moment) {
+                pack_ray(row, m, first_gate, gate_spacing, scale_factor, add_offset);
+            }
+        }
+        let variable = nc
+            .add_variable(field_name(moment), &["time", "range"], NcData::Short(data))?
+            .attribute("long_name", long_name(moment));
+        if let Some(standard) = standard_name(moment) {
+            variable.attribute("standard_name", standard);
+        }
+        variable
+            .attribute("units", moment.units())
+            .attribute("_FillValue", NcData::Short(vec![FILL_VALUE]))
+            .attribute("scale_factor", NcData::Float(vec![scale_factor]))
+            .attribute("add_offset", NcData::Float(vec![add_offset]))
+            .attribute("coordinates", "elevation azimuth range");
+    }
+
+    Ok(nc)
+}
+
+// Re-encodes a ray onto the output range axis by nearest gate.
+fn pack_ray(
+    row: &mut [i16],
+    moment: &MomentData,
+    first_gate: f32,
+    gate_spacing: f32,
+    scale_factor: f32,
+    add_offset: f32,
+) {
+    for (g, out) in row.iter_mut().enumerate() {
+        if let Some(value) = moment.value_at_range(first_gate + g as f32 * gate_spacing) {
+            *out = ((value - add_offset) / scale_factor).round() as i16;
+        }
+    }
+}
+
+fn padded(text: &str) -> NcData {
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.resize(STRING_LENGTH, 0);
+    NcData::Char(bytes)
+}
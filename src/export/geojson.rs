@@ -0,0 +1,83 @@
+use crate::grid::contour::{Contour, Ring};
+
+// GeoJSON (RFC 7946) export of contours: one Feature per threshold whose
+// geometry is a MultiPolygon of the region at or above the threshold, with
+// the threshold and any caller properties (product, units, time) attached.
+
+pub fn contours_geojson(contours: &[Contour], properties: &[(&str, &str)]) -> String {
+    let features: Vec<String> = contours
+        .iter()
+        .map(|contour| {
+            let mut props = vec![format!("\"threshold\":{}", json_number(contour.threshold as f64))];
+            props.extend(
+                properties
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", json_string(key), json_string(value))),
+            );
+            let polygons: Vec<String> = contour
+                .polygons
+                .iter()
+                .map(|polygon| {
+                    let rings: Vec<String> = std::iter::once(&polygon.exterior)
+                        .chain(polygon.holes.iter())
+                        .map(ring_json)
+                        .collect();
+                    format!("[{}]", rings.join(","))
+                })
+                .collect();
+            format!(
+                "{{\"type\":\"Feature\",\"properties\":{{{}}},\"geometry\":{{\"type\":\"MultiPolygon\",\"coordinates\":[{}]}}}}",
+                props.join(","),
+                polygons.join(",")
+            )
+        })
+        .collect();
+    format!(
+        "{{\"type\":\"FeatureCollection\",\"features\":[{}]}}\n",
+        features.join(",\n")
+    )
+}
+
+pub fn write_contours_geojson(
+    contours: &[Contour],
+    properties: &[(&str, &str)],
+    fp: &str,
+) -> anyhow::Result<()> {
+    std::fs::write(fp, contours_geojson(contours, properties))?;
+    Ok(())
+}
+
+// Positions to 1e-6 degrees, about 10 cm.
+fn ring_json(ring: &Ring) -> String {
+    let positions: Vec<String> = ring
+        .iter()
+        .map(|&(lon, lat)| format!("[{:.6},{:.6}]", lon, lat))
+        .collect();
+    format!("[{}]", positions.join(","))
+}
+
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,401 @@
+use crate::export::{iso8601, xml_escape};
+use crate::grid::GriddedField;
+
+// GeoTIFF export of lat/lon gridded fields. Every band is a 32-bit float
+// plane on the shared GridSpec, georeferenced as EPSG:4326 with the tie point
+// at the north-west corner of the grid. NaN cells are written as NODATA and
+// band names, units and any extra metadata go in a GDAL_METADATA block so
+// QGIS and GDAL show them per band.
+
+pub const NODATA: f32 = -9999.0;
+
+const TAG_IMAGE_WIDTH: u16 = 256;
+const TAG_IMAGE_LENGTH: u16 = 257;
+const TAG_BITS_PER_SAMPLE: u16 = 258;
+const TAG_COMPRESSION: u16 = 259;
+const TAG_PHOTOMETRIC: u16 = 262;
+const TAG_STRIP_OFFSETS: u16 = 273;
+const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+const TAG_ROWS_PER_STRIP: u16 = 278;
+const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+const TAG_PLANAR_CONFIGURATION: u16 = 284;
+const TAG_EXTRA_SAMPLES: u16 = 338;
+const TAG_SAMPLE_FORMAT: u16 = 339;
+const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+const TAG_MODEL_TIEPOINT: u16 = 33922;
+const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GDAL_METADATA: u16 = 42112;
+const TAG_GDAL_NODATA: u16 = 42113;
+
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_DOUBLE: u16 = 12;
+
+#[derive(Debug, Clone)]
+pub struct GeoTiffBand<'a> {
+    pub field: &'a GriddedField,
+    pub name: String,
+    pub units: String,
+    // Extra per-band items, e.g. ("moment", "REF") or ("height", "3000").
+    pub metadata: Vec<(String, String)>,
+}
+
+impl<'a> GeoTiffBand<'a> {
+    pub fn new(field: &'a GriddedField, name: &str, units: &str) -> GeoTiffBand<'a> {
+        GeoTiffBand {
+            field,
+            name: name.to_string(),
+            units: units.to_string(),
+            metadata: Vec::new(),
+        }
+    }
+}
+
+pub fn write_geotiff(bands: &[GeoTiffBand], fp: &str) -> anyhow::Result<()> {
+    std::fs::write(fp, encode_geotiff(bands)?)?;
+    Ok(())
+}
+
+pub fn encode_geotiff(bands: &[GeoTiffBand]) -> anyhow::Result<Vec<u8>> {
+    let spec = bands
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No bands to export"))?
+        .field
+        .spec;
+    if bands.iter().any(|b| b.field.spec != spec) {
+        anyhow::bail!("All GeoTIFF bands must share one grid");
+    }
+    if spec.n_rows == 0 || spec.n_cols == 0 {
+        anyhow::bail!("Grid has no cells to export");
+    }
+    let n_bands = bands.len();
+    let band_bytes = spec.n_rows * spec.n_cols * 4;
+    if 8 + n_bands * band_bytes > u32::MAX as usize / 2 {
+        anyhow::bail!("Grid is too large for a classic TIFF");
+    }
+
+    // Header, then one strip per band (planar configuration 2), then the IFD.
+    let mut out = Vec::with_capacity(8 + n_bands * band_bytes + 1024);
+    out.extend_from_slice(b"II*\0");
+    out.extend_from_slice(&[0; 4]);
+    let mut strip_offsets = Vec::with_capacity(n_bands);
+    for band in bands.iter() {
+        strip_offsets.push(out.len() as u32);
+        for row in 0..spec.n_rows {
+            for col in 0..spec.n_cols {
+                let value = band.field.get(row, col);
+                let value = if value.is_finite() { value } else { NODATA };
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    let geo_keys: Vec<u16> = vec![
+        1, 1, 0, 4, // directory version, revision, key count
+        1024, 0, 1, 2, // GTModelType: geographic
+        1025, 0, 1, 1, // GTRasterType: pixel is area
+        2048, 0, 1, 4326, // GeographicType: WGS 84
+        2054, 0, 1, 9102, // GeogAngularUnits: degree
+    ];
+    let mut entries = vec![
+        Entry::long(TAG_IMAGE_WIDTH, &[spec.n_cols as u32]),
+        Entry::long(TAG_IMAGE_LENGTH, &[spec.n_rows as u32]),
+        Entry::short(TAG_BITS_PER_SAMPLE, &vec![32; n_bands]),
+        Entry::short(TAG_COMPRESSION, &[1]),
+        Entry::short(TAG_PHOTOMETRIC, &[1]),
+        Entry::long(TAG_STRIP_OFFSETS, &strip_offsets),
+        Entry::short(TAG_SAMPLES_PER_PIXEL, &[n_bands as u16]),
+        Entry::long(TAG_ROWS_PER_STRIP, &[spec.n_rows as u32]),
+        Entry::long(TAG_STRIP_BYTE_COUNTS, &vec![band_bytes as u32; n_bands]),
+        Entry::short(TAG_PLANAR_CONFIGURATION, &[2]),
+    ];
+    if n_bands > 1 {
+        entries.push(Entry::short(TAG_EXTRA_SAMPLES, &vec![0; n_bands - 1]));
+    }
+    entries.push(Entry::short(TAG_SAMPLE_FORMAT, &vec![3; n_bands]));
+    entries.push(Entry::double(
+        TAG_MODEL_PIXEL_SCALE,
+        &[spec.lon_step, spec.lat_step, 0.0],
+    ));
+    entries.push(Entry::double(
+        TAG_MODEL_TIEPOINT,
+        &[0.0, 0.0, 0.0, spec.west, spec.north, 0.0],
+    ));
+    entries.push(Entry::short(TAG_GEO_KEY_DIRECTORY, &geo_keys));
+    entries.push(Entry::ascii(TAG_GDAL_METADATA, &gdal_metadata(bands)));
+    entries.push(Entry::ascii(TAG_GDAL_NODATA, &NODATA.to_string()));
+
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let ifd_offset = out.len();
+    out[4..8].copy_from_slice(&(ifd_offset as u32).to_le_bytes());
+    // Values that do not fit in an entry follow the IFD.
+    let mut extra_offset = ifd_offset + 2 + entries.len() * 12 + 4;
+    let mut extra = Vec::new();
+    out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries.iter() {
+        out.extend_from_slice(&entry.tag.to_le_bytes());
+        out.extend_from_slice(&entry.kind.to_le_bytes());
+        out.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.bytes.len() <= 4 {
+            let mut inline = [0; 4];
+            inline[..entry.bytes.len()].copy_from_slice(&entry.bytes);
+            out.extend_from_slice(&inline);
+        } else {
+            out.extend_from_slice(&(extra_offset as u32).to_le_bytes());
+            extra.extend_from_slice(&entry.bytes);
+            if entry.bytes.len() % 2 == 1 {
+                extra.push(0);
+            }
+            extra_offset = ifd_offset + 2 + entries.len() * 12 + 4 + extra.len();
+        }
+    }
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&extra);
+    Ok(out)
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    bytes: Vec<u8>,
+}
+
+impl Entry {
+    fn short(tag: u16, values: &[u16]) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_SHORT,
+            count: values.len() as u32,
+            bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn long(tag: u16, values: &[u32]) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_LONG,
+            count: values.len() as u32,
+            bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn double(tag: u16, values: &[f64]) -> Entry {
+        Entry {
+            tag,
+            kind: TYPE_DOUBLE,
+            count: values.len() as u32,
+            bytes: values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Entry {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        Entry {
+            tag,
+            kind: TYPE_ASCII,
+            count: bytes.len() as u32,
+            bytes,
+        }
+    }
+}
+
+// GDAL's XML metadata: the band name becomes its description and the units
+// its unit type; the valid time and extra items are plain band metadata.
+fn gdal_metadata(bands: &[GeoTiffBand]) -> String {
+    let mut xml = String::from("<GDALMetadata>\n");
+    for (sample, band) in bands.iter().enumerate() {
+        let mut item = |name: &str, role: Option<&str>, value: &str| {
+            xml.push_str(&format!(
+                "  <Item name=\"{}\" sample=\"{}\"",
+                xml_escape(name),
+                sample
+            ));
+            if let Some(role) = role {
+                xml.push_str(&format!(" role=\"{}\"", role));
+            }
+            xml.push_str(&format!(">{}</Item>\n", xml_escape(value)));
+        };
+        item("DESCRIPTION", Some("description"), &band.name);
+        item("UNITTYPE", Some("unittype"), &band.units);
+        item("time", None, &iso8601(band.field.time));
+        for (key, value) in band.metadata.iter() {
+            item(key, None, value);
+        }
+    }
+    xml.push_str("</GDALMetadata>");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::GridSpec;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    // (tag, type, count, value bytes) for each IFD entry, resolving values
+    // stored out of line.
+    fn read_ifd(tiff: &[u8]) -> Vec<(u16, u16, u32, Vec<u8>)> {
+        let ifd = u32_at(tiff, 4) as usize;
+        assert_eq!(ifd % 2, 0, "IFD must start on a word boundary");
+        let n = u16_at(tiff, ifd) as usize;
+        let ifd_end = ifd + 2 + n * 12 + 4;
+        assert_eq!(u32_at(tiff, ifd_end - 4), 0, "single IFD");
+        (0..n)
+            .map(|i| {
+                let entry = ifd + 2 + i * 12;
+                let (tag, kind, count) = (
+                    u16_at(tiff, entry),
+                    u16_at(tiff, entry + 2),
+                    u32_at(tiff, entry + 4),
+                );
+                let size = match kind {
+                    TYPE_ASCII => 1,
+                    TYPE_SHORT => 2,
+                    TYPE_LONG => 4,
+                    TYPE_DOUBLE => 8,
+                    other => panic!("unexpected type {}", other),
+                } * count as usize;
+                let value = if size <= 4 {
+                    tiff[entry + 8..entry + 8 + size].to_vec()
+                } else {
+                    let offset = u32_at(tiff, entry + 8) as usize;
+                    assert_eq!(offset % 2, 0, "tag {} value is not word aligned", tag);
+                    assert!(offset >= ifd_end, "tag {} value overlaps the IFD", tag);
+                    assert!(offset + size <= tiff.len(), "tag {} runs past the end", tag);
+                    tiff[offset..offset + size].to_vec()
+                };
+                (tag, kind, count, value)
+            })
+            .collect()
+    }
+
+    fn longs(value: &[u8]) -> Vec<u32> {
+        value
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    fn doubles(value: &[u8]) -> Vec<f64> {
+        value
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn encode_geotiff_reads_back() {
+        let spec = GridSpec {
+            north: 36.5,
+            west: -98.5,
+            lat_step: 0.25,
+            lon_step: 0.5,
+            n_rows: 3,
+            n_cols: 4,
+        };
+        let mut reflectivity = GriddedField::filled(spec, 1_700_000_000.0, 0.0);
+        let mut velocity = reflectivity.clone();
+        for row in 0..3 {
+            for col in 0..4 {
+                reflectivity.data[row][col] = (row * 4 + col) as f32;
+                velocity.data[row][col] = -(col as f32);
+            }
+        }
+        reflectivity.data[1][2] = f32::NAN;
+        let bands = [
+            GeoTiffBand::new(&reflectivity, "Reflectivity", "dBZ"),
+            GeoTiffBand::new(&velocity, "Velocity & <shear>", "m/s"),
+        ];
+        let tiff = encode_geotiff(&bands).unwrap();
+        assert_eq!(&tiff[..4], b"II*\0");
+
+        let entries = read_ifd(&tiff);
+        let tags: Vec<u16> = entries.iter().map(|e| e.0).collect();
+        assert!(
+            tags.windows(2).all(|w| w[0] < w[1]),
+            "tags out of order: {:?}",
+            tags
+        );
+        let entry = |tag: u16| {
+            entries
+                .iter()
+                .find(|e| e.0 == tag)
+                .unwrap_or_else(|| panic!("missing tag {}", tag))
+        };
+
+        assert_eq!(longs(&entry(TAG_IMAGE_WIDTH).3), [4]);
+        assert_eq!(longs(&entry(TAG_IMAGE_LENGTH).3), [3]);
+        assert_eq!(u16_at(&entry(TAG_SAMPLES_PER_PIXEL).3, 0), 2);
+        assert_eq!(u16_at(&entry(TAG_PLANAR_CONFIGURATION).3, 0), 2);
+        assert_eq!(entry(TAG_SAMPLE_FORMAT).2, 2);
+        assert_eq!(
+            doubles(&entry(TAG_MODEL_TIEPOINT).3),
+            [0.0, 0.0, 0.0, -98.5, 36.5, 0.0]
+        );
+        assert_eq!(doubles(&entry(TAG_MODEL_PIXEL_SCALE).3), [0.5, 0.25, 0.0]);
+        assert_eq!(&entry(TAG_GDAL_NODATA).3[..], b"-9999\0");
+        let metadata = String::from_utf8(entry(TAG_GDAL_METADATA).3.clone()).unwrap();
+        assert!(
+            metadata.contains("sample=\"1\" role=\"description\">Velocity &amp; &lt;shear&gt;<")
+        );
+
+        // Each band is one strip, row-major from the north-west corner.
+        let offsets = longs(&entry(TAG_STRIP_OFFSETS).3);
+        let counts = longs(&entry(TAG_STRIP_BYTE_COUNTS).3);
+        assert_eq!(counts, [48, 48]);
+        for (band, (&offset, &count)) in bands.iter().zip(offsets.iter().zip(counts.iter())) {
+            let strip = &tiff[offset as usize..(offset + count) as usize];
+            let values: Vec<f32> = strip
+                .chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()))
+                .collect();
+            let expected: Vec<f32> = band
+                .field
+                .data
+                .iter()
+                .flatten()
+                .map(|v| if v.is_finite() { *v } else { NODATA })
+                .collect();
+            assert_eq!(values, expected);
+        }
+        assert_eq!(
+            f32::from_le_bytes(
+                tiff[offsets[0] as usize + 24..offsets[0] as usize + 28]
+                    .try_into()
+                    .unwrap()
+            ),
+            NODATA
+        );
+    }
+
+    #[test]
+    fn bands_must_share_a_grid() {
+        let spec = GridSpec {
+            north: 36.5,
+            west: -98.5,
+            lat_step: 0.25,
+            lon_step: 0.5,
+            n_rows: 3,
+            n_cols: 4,
+        };
+        let a = GriddedField::filled(spec, 0.0, 1.0);
+        let b = GriddedField::filled(GridSpec { n_cols: 5, ..spec }, 0.0, 1.0);
+        assert!(encode_geotiff(&[]).is_err());
+        assert!(
+            encode_geotiff(&[GeoTiffBand::new(&a, "a", ""), GeoTiffBand::new(&b, "b", "")])
+                .is_err()
+        );
+    }
+}
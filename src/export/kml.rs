@@ -0,0 +1,151 @@
+use std::io::{Cursor, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::export::{iso8601, xml_escape};
+use crate::grid::{grid_field, GridSpec, GriddedField};
+use crate::render::colors::ColorTable;
+use crate::render::{ppi_title, render_grid};
+use crate::volume::{Moment, Sweep, Volume};
+
+// KMZ overlays for Google Earth. The field is drawn one pixel per grid cell
+// on a lat/lon grid, so the image maps linearly onto the GroundOverlay's
+// LatLonBox; sweeps are first gridded around the radar. The archive holds
+// doc.kml, the PNG overlay and, when a volume is given, a placemark at the
+// radar site from its VOL block.
+
+const OVERLAY_IMAGE: &str = "overlay.png";
+
+#[derive(Debug, Clone)]
+pub struct KmzOptions {
+    // Overlay pixel size in metres.
+    pub resolution: f64,
+    // Ground range covered around the radar in metres; defaults to the end
+    // of the sweep.
+    pub max_range: Option<f32>,
+}
+
+impl Default for KmzOptions {
+    fn default() -> Self {
+        KmzOptions {
+            resolution: 500.0,
+            max_range: None,
+        }
+    }
+}
+
+pub fn write_sweep_kmz(
+    volume: &Volume,
+    sweep: &Sweep,
+    moment: Moment,
+    table: &ColorTable,
+    opts: &KmzOptions,
+    fp: &str,
+) -> anyhow::Result<()> {
+    let site = volume.require_site()?;
+    let field = sweep
+        .field(moment)
+        .ok_or_else(|| anyhow::anyhow!("Sweep has no {} data", moment.block_name()))?;
+    let max_range = opts
+        .max_range
+        .unwrap_or_else(|| field.gate_range(field.n_gates()));
+    let (lat, lon) = (site.latitude as f64, site.longitude as f64);
+    let spec = GridSpec::centred(lat, lon, max_range as f64, opts.resolution);
+    let grid = grid_field(&field, lat, lon, spec);
+    let name = ppi_title(&volume.header, sweep.elevation_angle, table);
+    write_kmz(&grid, table, &name, Some(volume), fp)
+}
+
+pub fn write_kmz(
+    field: &GriddedField,
+    table: &ColorTable,
+    name: &str,
+    volume: Option<&Volume>,
+    fp: &str,
+) -> anyhow::Result<()> {
+    std::fs::write(fp, encode_kmz(field, table, name, volume)?)?;
+    Ok(())
+}
+
+pub fn encode_kmz(
+    field: &GriddedField,
+    table: &ColorTable,
+    name: &str,
+    volume: Option<&Volume>,
+) -> anyhow::Result<Vec<u8>> {
+    let image = render_grid(field, table).encode_png()?;
+    let kml = kml_document(field, name, volume, OVERLAY_IMAGE);
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // Google Earth opens the first .kml entry in the archive.
+    zip.start_file(
+        "doc.kml",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    zip.write_all(kml.as_bytes())?;
+    zip.start_file(
+        OVERLAY_IMAGE,
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(&image)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+// KML with a GroundOverlay of `image_href` covering the grid and, when a
+// volume is given, a placemark for its radar.
+pub fn kml_document(
+    field: &GriddedField,
+    name: &str,
+    volume: Option<&Volume>,
+    image_href: &str,
+) -> String {
+    let spec = field.spec;
+    let name = xml_escape(name);
+    let mut kml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <Document>\n",
+    );
+    kml.push_str(&format!("  <name>{}</name>\n", name));
+    kml.push_str(&format!(
+        "  <GroundOverlay>\n\
+         \x20   <name>{}</name>\n\
+         \x20   <TimeStamp><when>{}</when></TimeStamp>\n\
+         \x20   <Icon><href>{}</href></Icon>\n\
+         \x20   <LatLonBox>\n\
+         \x20     <north>{:.6}</north>\n\
+         \x20     <south>{:.6}</south>\n\
+         \x20     <east>{:.6}</east>\n\
+         \x20     <west>{:.6}</west>\n\
+         \x20   </LatLonBox>\n\
+         \x20 </GroundOverlay>\n",
+        name,
+        iso8601(field.time),
+        xml_escape(image_href),
+        spec.north,
+        spec.south(),
+        spec.east(),
+        spec.west,
+    ));
+    if let Some((volume, site)) = volume.and_then(|v| v.site().map(|s| (v, s))) {
+        let icao = xml_escape(volume.header.icao.trim());
+        kml.push_str(&format!(
+            "  <Placemark>\n\
+             \x20   <name>{}</name>\n\
+             \x20   <description>{} radar, VCP {}, {:.4}, {:.4}, site height {} m</description>\n\
+             \x20   <Point><coordinates>{:.6},{:.6},0</coordinates></Point>\n\
+             \x20 </Placemark>\n",
+            icao,
+            icao,
+            site.vcp_number,
+            site.latitude,
+            site.longitude,
+            site.site_height,
+            site.longitude,
+            site.latitude,
+        ));
+    }
+    kml.push_str("</Document>\n</kml>\n");
+    kml
+}
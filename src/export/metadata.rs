@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::export::iso8601;
+use crate::messages::{
+    ClutterFilterMapMetadata, ElevationDataBlock, GenericMomentHeader, MessageHeader,
+    RadialDataBlock, VolumeDataBlock, VolumeHeader,
+};
+use crate::reader::{
+    decompress_nexrad_file, read_clutter_filter_map_from_records, read_message_header,
+    read_volume_header, read_volume_headers_from_records, split_messages,
+};
+use crate::volume::Sweep;
+
+// Everything in an Archive II file except the gate data: the volume header,
+// the header of every message, the site and a summary of each sweep from the
+// Message 31 blocks, and the Message 15 clutter filter map. It is plain serde
+// data, dumped as JSON or as MessagePack with field names so both formats
+// carry the same keys. Times are ISO 8601 UTC.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMetadata {
+    pub volume_header: VolumeHeader,
+    pub start_time: Option<String>,
+    // Messages per MessageType, plus headers that could not be decoded.
+    pub message_counts: BTreeMap<String, usize>,
+    pub undecoded_messages: usize,
+    pub messages: Vec<MessageHeader>,
+    pub site: Option<VolumeDataBlock>,
+    pub sweeps: Vec<SweepMetadata>,
+    pub clutter_filter_map: Option<ClutterFilterMapMetadata>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepMetadata {
+    pub elevation_number: i8,
+    pub elevation_angle: f32,
+    pub n_radials: usize,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    // From the first radial carrying each block.
+    pub elevation: Option<ElevationDataBlock>,
+    pub radial: Option<RadialDataBlock>,
+    pub moments: Vec<GenericMomentHeader>,
+}
+
+impl SweepMetadata {
+    pub fn from_sweep(sweep: &Sweep) -> SweepMetadata {
+        let mut moments: Vec<GenericMomentHeader> = Vec::new();
+        for radial in sweep.radials.iter() {
+            for data in radial.moments.iter() {
+                if !moments.iter().any(|m| m.name == data.header.name) {
+                    moments.push(data.header.clone());
+                }
+            }
+        }
+        SweepMetadata {
+            elevation_number: sweep.elevation_number,
+            elevation_angle: sweep.elevation_angle,
+            n_radials: sweep.radials.len(),
+            start_time: sweep.start_time().map(iso8601),
+            end_time: sweep.end_time().map(iso8601),
+            elevation: sweep.radials.iter().find_map(|r| r.elevation.clone()),
+            radial: sweep.radials.iter().find_map(|r| r.radial.clone()),
+            moments,
+        }
+    }
+}
+
+pub fn file_metadata(header: VolumeHeader, records: &[Vec<u8>]) -> anyhow::Result<FileMetadata> {
+    let mut messages = Vec::new();
+    let mut message_counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut undecoded_messages = 0;
+    for record in records.iter() {
+        for message in split_messages(record) {
+            match read_message_header(message.to_vec()) {
+                Ok(message_header) => {
+                    *message_counts
+                        .entry(format!("{:?}", message_header.message_type))
+                        .or_default() += 1;
+                    messages.push(message_header);
+                }
+                Err(_) => undecoded_messages += 1,
+            }
+        }
+    }
+
+    let volume = read_volume_headers_from_records(header, records)?;
+    Ok(FileMetadata {
+        start_time: volume.start_time().map(iso8601),
+        message_counts,
+        undecoded_messages,
+        messages,
+        site: volume.site().cloned(),
+        sweeps: volume
+            .sweeps
+            .iter()
+            .map(SweepMetadata::from_sweep)
+            .collect(),
+        clutter_filter_map: read_clutter_filter_map_from_records(records)?,
+        volume_header: volume.header,
+    })
+}
+
+pub fn read_file_metadata(fp: &str) -> anyhow::Result<FileMetadata> {
+    let header = read_volume_header(fp)?;
+    let records = decompress_nexrad_file(fp)?;
+    file_metadata(header, &records)
+}
+
+pub fn metadata_json(metadata: &FileMetadata) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(metadata)?)
+}
+
+pub fn metadata_msgpack(metadata: &FileMetadata) -> anyhow::Result<Vec<u8>> {
+    Ok(rmp_serde::to_vec_named(metadata)?)
+}
+
+// Writes JSON, or MessagePack for .msgpack/.mpk paths.
+pub fn write_metadata(metadata: &FileMetadata, fp: &str) -> anyhow::Result<()> {
+    let extension = fp
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let bytes = match extension.as_str() {
+        "json" => metadata_json(metadata)?.into_bytes(),
+        "msgpack" | "mpk" => metadata_msgpack(metadata)?,
+        _ => anyhow::bail!(
+            "Unknown metadata format for {}; use .json, .msgpack or .mpk",
+            fp
+        ),
+    };
+    std::fs::write(fp, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{AzimuthSegment, ElevationSegment, MessageType, RangeZone};
+    use crate::volume::{synthetic, Moment};
+
+    fn metadata() -> FileMetadata {
+        let volume = synthetic::volume(
+            &[0.5],
+            10,
+            30.0,
+            &[Moment::Reflectivity, Moment::Velocity],
+            |_, _, _, _| 20.0,
+        );
+        let message = MessageHeader {
+            messagesize: 1_200,
+            rda_redundant_channel: 0,
+            message_type: MessageType::DigitalRadarDataGenericFormat,
+            id_seq_no: 1,
+            julian_date: 20_000,
+            ms_from_midnight: 0,
+            n_segments: 1,
+            message_segment_no: 1,
+        };
+        let zone = RangeZone {
+            range_zone_num: 1,
+            opcode: 1,
+            endrange: 511,
+        };
+        FileMetadata {
+            volume_header: volume.header.clone(),
+            start_time: volume.start_time().map(iso8601),
+            message_counts: BTreeMap::from([("DigitalRadarDataGenericFormat".to_string(), 1)]),
+            undecoded_messages: 0,
+            messages: vec![message],
+            site: volume.site().cloned(),
+            sweeps: vec![SweepMetadata::from_sweep(&volume.sweeps[0])],
+            clutter_filter_map: Some(ClutterFilterMapMetadata {
+                map_generation_date: 20_000,
+                map_generation_time: 0,
+                num_elevation_segments: 1,
+                elevation_segments: vec![ElevationSegment {
+                    azimuth_segments: vec![AzimuthSegment {
+                        num_rangezones: 1,
+                        range_zones: vec![zone],
+                    }],
+                }],
+            }),
+        }
+    }
+
+    // Every key path in a document, with array elements under "[]".
+    fn keys(value: &serde_json::Value, path: &str, out: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    let path = format!("{}.{}", path, key);
+                    out.push(path.clone());
+                    keys(value, &path, out);
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    keys(item, &format!("{}[]", path), out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn json_and_msgpack_carry_the_same_keys() {
+        let metadata = metadata();
+        let json: serde_json::Value =
+            serde_json::from_str(&metadata_json(&metadata).unwrap()).unwrap();
+        let msgpack: serde_json::Value =
+            rmp_serde::from_slice(&metadata_msgpack(&metadata).unwrap()).unwrap();
+
+        let (mut json_keys, mut msgpack_keys) = (Vec::new(), Vec::new());
+        keys(&json, "", &mut json_keys);
+        keys(&msgpack, "", &mut msgpack_keys);
+        json_keys.sort();
+        json_keys.dedup();
+        msgpack_keys.sort();
+        msgpack_keys.dedup();
+        assert_eq!(json_keys, msgpack_keys);
+        for key in [
+            ".sweeps[].moments[].scale",
+            ".clutter_filter_map.elevation_segments[].azimuth_segments[].range_zones[].opcode",
+            ".messages[].message_type",
+            ".site.feedhorn_height",
+        ] {
+            assert!(json_keys.iter().any(|k| k == key), "{}", key);
+        }
+    }
+}
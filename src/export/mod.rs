@@ -0,0 +1,57 @@
+pub mod cfradial;
+pub mod geojson;
+pub mod geotiff;
+pub mod kml;
+pub mod metadata;
+pub mod netcdf;
+pub mod odim;
+pub mod table;
+
+// Epoch seconds as an ISO 8601 UTC timestamp, e.g. 2024-05-20T21:03:17Z.
+pub fn iso8601(epoch: f64) -> String {
+    let seconds = epoch.floor() as i64;
+    let days = seconds.div_euclid(86400);
+    let time = seconds.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Epoch seconds as compact UTC ("YYYYMMDD", "HHMMSS") strings.
+pub fn compact_date_time(epoch: f64) -> (String, String) {
+    let seconds = epoch.floor() as i64;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    (
+        format!("{:04}{:02}{:02}", year, month, day),
+        format!("{:02}{:02}{:02}", time / 3600, time % 3600 / 60, time % 60),
+    )
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Gregorian (year, month, day) for days since 1970-01-01 (Hinnant's algorithm).
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
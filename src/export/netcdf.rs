@@ -0,0 +1,361 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// A minimal in-memory NetCDF-3 writer producing the 64-bit offset format
+// (CDF-2). Only fixed-size variables are supported; everything is buffered
+// and written in one pass once the header layout is known.
+
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+
+#[derive(Debug, Clone)]
+pub enum NcData {
+    Char(Vec<u8>),
+    Short(Vec<i16>),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl NcData {
+    fn type_code(&self) -> u32 {
+        match self {
+            NcData::Char(_) => 2,
+            NcData::Short(_) => 3,
+            NcData::Int(_) => 4,
+            NcData::Float(_) => 5,
+            NcData::Double(_) => 6,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            NcData::Char(v) => v.len(),
+            NcData::Short(v) => v.len(),
+            NcData::Int(v) => v.len(),
+            NcData::Float(v) => v.len(),
+            NcData::Double(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            NcData::Char(v) => v.clone(),
+            NcData::Short(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            NcData::Int(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            NcData::Float(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+            NcData::Double(v) => v.iter().flat_map(|x| x.to_be_bytes()).collect(),
+        }
+    }
+
+    // Variable data is padded with the type's default fill value.
+    fn fill_bytes(&self) -> Vec<u8> {
+        match self {
+            NcData::Char(_) => vec![0],
+            NcData::Short(_) => (-32767_i16).to_be_bytes().to_vec(),
+            _ => vec![0],
+        }
+    }
+}
+
+impl From<&str> for NcData {
+    fn from(value: &str) -> Self {
+        NcData::Char(value.as_bytes().to_vec())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NcVariable {
+    pub name: String,
+    pub dims: Vec<usize>,
+    pub attributes: Vec<(String, NcData)>,
+    pub data: NcData,
+}
+
+impl NcVariable {
+    pub fn attribute(&mut self, name: &str, value: impl Into<NcData>) -> &mut NcVariable {
+        self.attributes.push((name.to_string(), value.into()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NcFile {
+    pub dims: Vec<(String, usize)>,
+    pub attributes: Vec<(String, NcData)>,
+    pub variables: Vec<NcVariable>,
+}
+
+impl NcFile {
+    pub fn new() -> NcFile {
+        NcFile::default()
+    }
+
+    pub fn add_dim(&mut self, name: &str, len: usize) {
+        self.dims.push((name.to_string(), len));
+    }
+
+    pub fn add_attribute(&mut self, name: &str, value: impl Into<NcData>) {
+        self.attributes.push((name.to_string(), value.into()));
+    }
+
+    // Adds a variable over the named dimensions; `data` must fill them exactly.
+    pub fn add_variable(
+        &mut self,
+        name: &str,
+        dims: &[&str],
+        data: NcData,
+    ) -> anyhow::Result<&mut NcVariable> {
+        let dims = dims
+            .iter()
+            .map(|d| {
+                self.dims.iter().position(|(n, _)| n == d).ok_or_else(|| {
+                    anyhow::anyhow!("Variable {} uses undefined dimension {}", name, d)
+                })
+            })
+            .collect::<anyhow::Result<Vec<usize>>>()?;
+        let expected: usize = dims.iter().map(|&d| self.dims[d].1).product();
+        if data.len() != expected {
+            anyhow::bail!(
+                "Variable {} has {} values but its dimensions hold {}",
+                name,
+                data.len(),
+                expected
+            );
+        }
+        self.variables.push(NcVariable {
+            name: name.to_string(),
+            dims,
+            attributes: Vec::new(),
+            data,
+        });
+        Ok(self.variables.last_mut().expect("just pushed"))
+    }
+
+    pub fn write(&self, fp: &str) -> anyhow::Result<()> {
+        // The header length does not depend on the begin offsets (always
+        // eight bytes in CDF-2), so lay it out once with placeholders.
+        let header_len = self.header(&vec![0; self.variables.len()]).len() as u64;
+        let mut begins = Vec::with_capacity(self.variables.len());
+        let mut offset = header_len;
+        for v in self.variables.iter() {
+            begins.push(offset);
+            offset += padded_len(v.data.len() * type_size(&v.data)) as u64;
+        }
+
+        let mut out = BufWriter::new(File::create(fp)?);
+        out.write_all(&self.header(&begins))?;
+        for v in self.variables.iter() {
+            let bytes = v.data.bytes();
+            out.write_all(&bytes)?;
+            let fill = v.data.fill_bytes();
+            let padding: Vec<u8> = fill
+                .iter()
+                .cycle()
+                .take(padded_len(bytes.len()) - bytes.len())
+                .copied()
+                .collect();
+            out.write_all(&padding)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn header(&self, begins: &[u64]) -> Vec<u8> {
+        let mut h = b"CDF\x02".to_vec();
+        put_u32(&mut h, 0); // numrecs, no record dimension
+
+        if self.dims.is_empty() {
+            put_u32(&mut h, 0);
+            put_u32(&mut h, 0);
+        } else {
+            put_u32(&mut h, NC_DIMENSION);
+            put_u32(&mut h, self.dims.len() as u32);
+            for (name, len) in self.dims.iter() {
+                put_name(&mut h, name);
+                put_u32(&mut h, *len as u32);
+            }
+        }
+
+        put_attributes(&mut h, &self.attributes);
+
+        if self.variables.is_empty() {
+            put_u32(&mut h, 0);
+            put_u32(&mut h, 0);
+        } else {
+            put_u32(&mut h, NC_VARIABLE);
+            put_u32(&mut h, self.variables.len() as u32);
+            for (v, begin) in self.variables.iter().zip(begins.iter()) {
+                put_name(&mut h, &v.name);
+                put_u32(&mut h, v.dims.len() as u32);
+                for &d in v.dims.iter() {
+                    put_u32(&mut h, d as u32);
+                }
+                put_attributes(&mut h, &v.attributes);
+                put_u32(&mut h, v.data.type_code());
+                let vsize = padded_len(v.data.len() * type_size(&v.data));
+                put_u32(&mut h, vsize.min(u32::MAX as usize) as u32);
+                h.extend(begin.to_be_bytes());
+            }
+        }
+        h
+    }
+}
+
+fn type_size(data: &NcData) -> usize {
+    match data {
+        NcData::Char(_) => 1,
+        NcData::Short(_) => 2,
+        NcData::Int(_) | NcData::Float(_) => 4,
+        NcData::Double(_) => 8,
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+fn put_u32(h: &mut Vec<u8>, value: u32) {
+    h.extend(value.to_be_bytes());
+}
+
+// Names and header attribute values are padded with zero bytes.
+fn put_name(h: &mut Vec<u8>, name: &str) {
+    put_u32(h, name.len() as u32);
+    h.extend(name.as_bytes());
+    h.resize(padded_len(h.len()), 0);
+}
+
+fn put_attributes(h: &mut Vec<u8>, attributes: &[(String, NcData)]) {
+    if attributes.is_empty() {
+        put_u32(h, 0);
+        put_u32(h, 0);
+        return;
+    }
+    put_u32(h, NC_ATTRIBUTE);
+    put_u32(h, attributes.len() as u32);
+    for (name, value) in attributes {
+        put_name(h, name);
+        put_u32(h, value.type_code());
+        put_u32(h, value.len() as u32);
+        h.extend(value.bytes());
+        h.resize(padded_len(h.len()), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Cursor<'a> {
+        bytes: &'a [u8],
+        at: usize,
+    }
+
+    impl Cursor<'_> {
+        fn u32(&mut self) -> u32 {
+            let value = u32::from_be_bytes(self.bytes[self.at..self.at + 4].try_into().unwrap());
+            self.at += 4;
+            value
+        }
+
+        fn u64(&mut self) -> u64 {
+            let value = u64::from_be_bytes(self.bytes[self.at..self.at + 8].try_into().unwrap());
+            self.at += 8;
+            value
+        }
+
+        // Checks the zero padding up to the next 4-byte boundary.
+        fn padded(&mut self, len: usize) -> &[u8] {
+            let start = self.at;
+            self.at = start + padded_len(len);
+            assert!(self.bytes[start + len..self.at].iter().all(|&b| b == 0));
+            &self.bytes[start..start + len]
+        }
+
+        fn name(&mut self) -> String {
+            let len = self.u32() as usize;
+            String::from_utf8(self.padded(len).to_vec()).unwrap()
+        }
+
+        fn attributes(&mut self) -> Vec<(String, u32, Vec<u8>)> {
+            let (tag, count) = (self.u32(), self.u32());
+            assert_eq!(tag, if count == 0 { 0 } else { NC_ATTRIBUTE });
+            (0..count)
+                .map(|_| {
+                    let name = self.name();
+                    let (code, n) = (self.u32(), self.u32() as usize);
+                    let size = [0, 1, 1, 2, 4, 4, 8][code as usize];
+                    (name, code, self.padded(n * size).to_vec())
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn write_reads_back() {
+        let mut nc = NcFile::new();
+        nc.add_dim("x", 3);
+        nc.add_dim("time", 2);
+        nc.add_attribute("title", "odd");
+        nc.add_variable("ref", &["time", "x"], NcData::Short(vec![1, 2, 3, 4, 5, 6]))
+            .unwrap()
+            .attribute("units", "dBZ");
+        nc.add_variable("az", &["x"], NcData::Float(vec![0.5, 1.5, 2.5]))
+            .unwrap();
+        nc.add_variable("flag", &["x"], NcData::Char(b"abc".to_vec()))
+            .unwrap();
+        nc.add_variable("time", &["time"], NcData::Double(vec![10.0, 20.0]))
+            .unwrap();
+
+        let fp = std::env::temp_dir().join(format!("netcdf_{}.nc", std::process::id()));
+        let fp = fp.to_str().unwrap();
+        nc.write(fp).unwrap();
+        let bytes = std::fs::read(fp).unwrap();
+        std::fs::remove_file(fp).unwrap();
+
+        let mut h = Cursor {
+            bytes: &bytes,
+            at: 0,
+        };
+        assert_eq!(&bytes[..4], b"CDF\x02");
+        h.at = 4;
+        assert_eq!(h.u32(), 0); // numrecs
+
+        assert_eq!((h.u32(), h.u32()), (NC_DIMENSION, 2));
+        assert_eq!((h.name(), h.u32()), ("x".to_string(), 3));
+        assert_eq!((h.name(), h.u32()), ("time".to_string(), 2));
+
+        let attributes = h.attributes();
+        assert_eq!(attributes, vec![("title".to_string(), 2, b"odd".to_vec())]);
+
+        assert_eq!((h.u32(), h.u32()), (NC_VARIABLE, 4));
+        let mut layout = Vec::new();
+        for v in nc.variables.iter() {
+            assert_eq!(h.name(), v.name);
+            let n_dims = h.u32() as usize;
+            let dims: Vec<usize> = (0..n_dims).map(|_| h.u32() as usize).collect();
+            assert_eq!(dims, v.dims);
+            assert_eq!(h.attributes().len(), v.attributes.len());
+            assert_eq!(h.u32(), v.data.type_code());
+            let (vsize, begin) = (h.u32() as usize, h.u64() as usize);
+            layout.push((begin, vsize, v.data.bytes()));
+        }
+
+        // Data starts right after the header, each variable where its begin
+        // says, padded to its vsize, with nothing left over.
+        let mut offset = h.at;
+        for (begin, vsize, data) in layout {
+            assert_eq!(begin, offset);
+            assert_eq!(vsize, padded_len(data.len()));
+            assert_eq!(&bytes[begin..begin + data.len()], &data[..]);
+            offset += vsize;
+        }
+        assert_eq!(offset, bytes.len());
+    }
+}
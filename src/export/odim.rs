@@ -0,0 +1,336 @@
+use hdf5_pure::{AttrValue, FileBuilder, GroupBuilder};
+
+use crate::export::compact_date_time;
+use crate::volume::{Moment, MomentData, Radial, Sweep, Volume};
+
+// OPERA ODIM_H5 2.3 polar volume export. Every sweep becomes a
+// /datasetN group with its rays ordered clockwise from north and one dataM
+// group per moment. Gates keep their Message 31 raw codes: gain and offset
+// come from the block scale and offset, 0 is undetect (below threshold) and 1
+// is nodata (range folded, or padding beyond the end of a short ray).
+
+const ODIM_VERSION: &str = "H5rad 2.3";
+const ODIM_CONVENTIONS: &str = "ODIM_H5/V2_3";
+const UNDETECT: u16 = 0;
+const NODATA: u16 = 1;
+
+// ODIM quantity for each Message 31 moment block. The single-polarization
+// names carry the H suffix because WSR-88D moments come from the horizontal
+// channel; CFP, the power removed by the clutter filter, is ODIM's CCORH.
+pub fn quantity(moment: Moment) -> &'static str {
+    match moment {
+        Moment::Reflectivity => "DBZH",
+        Moment::Velocity => "VRADH",
+        Moment::SpectrumWidth => "WRADH",
+        Moment::DifferentialReflectivity => "ZDR",
+        Moment::DifferentialPhase => "PHIDP",
+        Moment::CorrelationCoefficient => "RHOHV",
+        Moment::ClutterFilterPower => "CCORH",
+    }
+}
+
+pub fn moment_for_quantity(quantity: &str) -> Option<Moment> {
+    match quantity {
+        "DBZH" => Some(Moment::Reflectivity),
+        "VRADH" => Some(Moment::Velocity),
+        "WRADH" => Some(Moment::SpectrumWidth),
+        "ZDR" => Some(Moment::DifferentialReflectivity),
+        "PHIDP" => Some(Moment::DifferentialPhase),
+        "RHOHV" => Some(Moment::CorrelationCoefficient),
+        "CCORH" => Some(Moment::ClutterFilterPower),
+        _ => None,
+    }
+}
+
+pub fn write_odim(volume: &Volume, fp: &str) -> anyhow::Result<()> {
+    let site = volume.require_site()?;
+    let start = volume
+        .start_time()
+        .ok_or_else(|| anyhow::anyhow!("Volume has no radials to export"))?;
+    let icao = volume.header.icao.trim();
+    let (date, time) = compact_date_time(start);
+
+    let mut file = FileBuilder::new();
+    file.set_attr("Conventions", text(ODIM_CONVENTIONS));
+
+    let mut what = file.create_group("what");
+    what.set_attr("object", text("PVOL"));
+    what.set_attr("version", text(ODIM_VERSION));
+    what.set_attr("date", text(&date));
+    what.set_attr("time", text(&time));
+    // RAD and WMO are OPERA and WMO identifiers, which NEXRAD sites do not
+    // have; the ICAO goes in NOD as a lowercase node name.
+    what.set_attr(
+        "source",
+        text(&format!("NOD:{},PLC:{}", icao.to_ascii_lowercase(), icao)),
+    );
+    file.add_group(what.finish());
+
+    let mut location = file.create_group("where");
+    location.set_attr("lon", AttrValue::F64(site.longitude as f64));
+    location.set_attr("lat", AttrValue::F64(site.latitude as f64));
+    location.set_attr(
+        "height",
+        AttrValue::F64(site.site_height as f64 + site.feedhorn_height as f64),
+    );
+    file.add_group(location.finish());
+
+    let mut how = file.create_group("how");
+    how.set_attr("software", text(env!("CARGO_PKG_NAME")));
+    how.set_attr("sw_version", text(env!("CARGO_PKG_VERSION")));
+    how.set_attr("system", text("NEXRAD"));
+    how.set_attr("scan_count", AttrValue::I64(volume.sweeps.len() as i64));
+    file.add_group(how.finish());
+
+    let mut index = 0;
+    for sweep in volume.sweeps.iter().filter(|s| !s.radials.is_empty()) {
+        index += 1;
+        let dataset = file.create_group(&format!("dataset{}", index));
+        file.add_group(sweep_group(sweep, dataset)?.finish());
+    }
+
+    file.write(fp)?;
+    Ok(())
+}
+
+fn sweep_group(sweep: &Sweep, mut dataset: GroupBuilder) -> anyhow::Result<GroupBuilder> {
+    let mut rays: Vec<(usize, &Radial)> = sweep.radials.iter().enumerate().collect();
+    rays.sort_by(|a, b| {
+        a.1.header
+            .azimuth_angle
+            .total_cmp(&b.1.header.azimuth_angle)
+    });
+    let first_collected = rays
+        .iter()
+        .position(|(i, _)| *i == 0)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no radials", sweep.elevation_number))?;
+    let collected: Vec<usize> = rays.iter().map(|(i, _)| *i).collect();
+    let rays: Vec<&Radial> = rays.into_iter().map(|(_, r)| r).collect();
+
+    let mut moments: Vec<Moment> = rays
+        .iter()
+        .flat_map(|r| r.moments.iter().map(|m| m.moment))
+        .collect();
+    moments.sort();
+    moments.dedup();
+    // ODIM keeps one range geometry per dataset, taken from the first moment.
+    let reference = rays
+        .iter()
+        .find_map(|r| moments.first().and_then(|&m| r.moment(m)))
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no moment data", sweep.elevation_number))?;
+    let rstart = reference.first_gate_range() - reference.gate_spacing() / 2.0;
+    let rscale = reference.gate_spacing();
+    let nbins = rays
+        .iter()
+        .flat_map(|r| r.moments.iter())
+        .filter(|m| {
+            m.gate_spacing() == rscale && m.first_gate_range() == reference.first_gate_range()
+        })
+        .map(|m| m.gates.len())
+        .max()
+        .unwrap_or(0);
+
+    let start = sweep.start_time().unwrap_or_default();
+    let end = sweep.end_time().unwrap_or(start);
+    let (start_date, start_time) = compact_date_time(start);
+    let (end_date, end_time) = compact_date_time(end);
+
+    let mut what = dataset.create_group("what");
+    what.set_attr("product", text("SCAN"));
+    what.set_attr("startdate", text(&start_date));
+    what.set_attr("starttime", text(&start_time));
+    what.set_attr("enddate", text(&end_date));
+    what.set_attr("endtime", text(&end_time));
+    dataset.add_group(what.finish());
+
+    let mut location = dataset.create_group("where");
+    location.set_attr("elangle", AttrValue::F64(sweep.elevation_angle as f64));
+    location.set_attr("nbins", AttrValue::I64(nbins as i64));
+    location.set_attr("rstart", AttrValue::F64(rstart as f64 / 1000.0));
+    location.set_attr("rscale", AttrValue::F64(rscale as f64));
+    location.set_attr("nrays", AttrValue::I64(rays.len() as i64));
+    location.set_attr("a1gate", AttrValue::I64(first_collected as i64));
+    dataset.add_group(location.finish());
+
+    // Per-ray azimuth limits assume contiguous rays of equal width.
+    let half_width = 180.0 / rays.len() as f64;
+    let mut how = dataset.create_group("how");
+    if let Some(nyquist) = sweep.nyquist_velocity() {
+        how.set_attr("NI", AttrValue::F64(nyquist as f64));
+    }
+    how.set_attr(
+        "startazA",
+        AttrValue::F64Array(
+            rays.iter()
+                .map(|r| (r.header.azimuth_angle as f64 - half_width).rem_euclid(360.0))
+                .collect(),
+        ),
+    );
+    how.set_attr(
+        "stopazA",
+        AttrValue::F64Array(
+            rays.iter()
+                .map(|r| (r.header.azimuth_angle as f64 + half_width).rem_euclid(360.0))
+                .collect(),
+        ),
+    );
+    how.set_attr(
+        "elangles",
+        AttrValue::F64Array(
+            rays.iter()
+                .map(|r| r.header.elevation_angle as f64)
+                .collect(),
+        ),
+    );
+    how.set_attr(
+        "startazT",
+        AttrValue::F64Array(rays.iter().map(|r| r.time()).collect()),
+    );
+    let stop_times = ray_stop_times(&sweep.radials);
+    how.set_attr(
+        "stopazT",
+        AttrValue::F64Array(collected.iter().map(|&i| stop_times[i]).collect()),
+    );
+    dataset.add_group(how.finish());
+
+    for (m, &moment) in moments.iter().enumerate() {
+        let group = dataset.create_group(&format!("data{}", m + 1));
+        dataset.add_group(moment_group(&rays, moment, group, nbins, reference)?.finish());
+    }
+    Ok(dataset)
+}
+
+// Each ray ends when the next one in collection order starts; the last ray
+// is given the duration of the one before it.
+fn ray_stop_times(radials: &[Radial]) -> Vec<f64> {
+    let times: Vec<f64> = radials.iter().map(|r| r.time()).collect();
+    let mut stop: Vec<f64> = times.windows(2).map(|w| w[1]).collect();
+    if let Some(&last) = times.last() {
+        let duration = match times.len() {
+            n if n >= 2 => last - times[n - 2],
+            _ => 0.0,
+        };
+        stop.push(last + duration);
+    }
+    stop
+}
+
+fn moment_group(
+    rays: &[&Radial],
+    moment: Moment,
+    mut group: GroupBuilder,
+    nbins: usize,
+    reference: &MomentData,
+) -> anyhow::Result<GroupBuilder> {
+    let header = &rays
+        .iter()
+        .find_map(|r| r.moment(moment))
+        .ok_or_else(|| anyhow::anyhow!("No ray carries {}", quantity(moment)))?
+        .header;
+
+    let mut what = group.create_group("what");
+    what.set_attr("quantity", text(quantity(moment)));
+    what.set_attr("gain", AttrValue::F64(1.0 / header.scale as f64));
+    what.set_attr(
+        "offset",
+        AttrValue::F64(-header.offset as f64 / header.scale as f64),
+    );
+    what.set_attr("nodata", AttrValue::F64(NODATA as f64));
+    what.set_attr("undetect", AttrValue::F64(UNDETECT as f64));
+    group.add_group(what.finish());
+
+    // Rays on a different gate geometry are resampled by nearest gate.
+    let gates: Vec<u16> = rays
+        .iter()
+        .flat_map(|r| {
+            let mut row = vec![NODATA; nbins];
+            if let Some(m) = r.moment(moment) {
+                for (g, out) in row.iter_mut().enumerate() {
+                    let range = reference.first_gate_range() + g as f32 * reference.gate_spacing();
+                    if let Some(source) = m.gate_at_range(range) {
+                        *out = m.gates[source];
+                    }
+                }
+            }
+            row
+        })
+        .collect();
+
+    let shape = [rays.len() as u64, nbins as u64];
+    let chunks = [rays.len().clamp(1, 360) as u64, nbins.max(1) as u64];
+    let data = group.create_dataset("data");
+    if header.data_word_size == 8 {
+        let bytes: Vec<u8> = gates.iter().map(|&g| g.min(u8::MAX as u16) as u8).collect();
+        data.with_u8_data(&bytes);
+    } else {
+        data.with_u16_data(&gates);
+    }
+    data.with_shape(&shape)
+        .with_chunks(&chunks)
+        .with_deflate(6)
+        .set_attr("CLASS", text("IMAGE"))
+        .set_attr("IMAGE_VERSION", text("1.2"));
+    Ok(group)
+}
+
+// Fixed-length, null-terminated ASCII string attribute as ODIM requires.
+fn text(value: &str) -> AttrValue {
+    AttrValue::ascii_string_sized(value, value.len() as u32 + 1)
+        .unwrap_or_else(|_| AttrValue::AsciiString(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        DigitalRadarDataGenericFormatHeader, DigitalRadarDataGenericFormatHeaderRaw,
+    };
+
+    fn radial(collection_time: i32) -> Radial {
+        let mut header = DigitalRadarDataGenericFormatHeader::from(
+            DigitalRadarDataGenericFormatHeaderRaw::default(),
+        );
+        header.modified_julian_date = 19_000;
+        header.collection_time = collection_time;
+        Radial {
+            header,
+            volume: None,
+            elevation: None,
+            radial: None,
+            moments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn quantities_round_trip() {
+        let moments = [
+            Moment::Reflectivity,
+            Moment::Velocity,
+            Moment::SpectrumWidth,
+            Moment::DifferentialReflectivity,
+            Moment::DifferentialPhase,
+            Moment::CorrelationCoefficient,
+            Moment::ClutterFilterPower,
+        ];
+        for moment in moments {
+            assert_eq!(moment_for_quantity(quantity(moment)), Some(moment));
+        }
+        assert_eq!(moment_for_quantity("TH"), None);
+    }
+
+    #[test]
+    fn stop_times_follow_the_next_ray() {
+        assert!(ray_stop_times(&[]).is_empty());
+
+        let single = radial(1_000);
+        assert_eq!(ray_stop_times(&[single.clone()]), vec![single.time()]);
+
+        let rays: Vec<Radial> = [1_000, 1_250, 1_500, 1_800].map(radial).to_vec();
+        let start = rays[0].time();
+        let stop = ray_stop_times(&rays);
+        assert_eq!(stop.len(), rays.len());
+        for (t, expected) in stop.iter().zip([0.25, 0.5, 0.8, 1.1]) {
+            assert!((t - start - expected).abs() < 1e-6, "{:?}", stop);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    Float32Builder, Float64Builder, Int16Builder, TimestampMillisecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::export::cfradial::field_name;
+use crate::geometry::gate_location;
+use crate::volume::{Moment, MomentData, Volume};
+
+// Long-format gate table: one row per gate with its sweep, beam angles,
+// range, location and collection time, then one nullable float column per
+// moment in physical units. Thresholded and range-folded gates are null.
+// Each sweep becomes one record batch (one Parquet row group).
+
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    // Drop gates where every moment is null.
+    pub skip_empty: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions { skip_empty: true }
+    }
+}
+
+pub fn gate_schema(moments: &BTreeSet<Moment>) -> SchemaRef {
+    let mut fields = vec![
+        Field::new("sweep", DataType::Int16, false),
+        Field::new("elevation", DataType::Float32, false),
+        Field::new("azimuth", DataType::Float32, false),
+        Field::new("range", DataType::Float32, false),
+        Field::new("latitude", DataType::Float64, false),
+        Field::new("longitude", DataType::Float64, false),
+        Field::new("height", DataType::Float32, false),
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            false,
+        ),
+    ];
+    fields.extend(
+        moments
+            .iter()
+            .map(|&m| Field::new(field_name(m), DataType::Float32, true)),
+    );
+    Arc::new(Schema::new(fields))
+}
+
+// Flattens the volume into one record batch per non-empty sweep.
+pub fn gate_table(
+    volume: &Volume,
+    opts: &TableOptions,
+) -> anyhow::Result<(SchemaRef, Vec<RecordBatch>)> {
+    let site = volume.require_site()?;
+    let site_height = site.site_height as f64 + site.feedhorn_height as f64;
+    let moments: BTreeSet<Moment> = volume
+        .sweeps
+        .iter()
+        .flat_map(|s| s.radials.iter())
+        .flat_map(|r| r.moments.iter().map(|m| m.moment))
+        .collect();
+    if moments.is_empty() {
+        anyhow::bail!("Volume has no moment data to export");
+    }
+    let schema = gate_schema(&moments);
+
+    let mut batches = Vec::new();
+    for sweep in volume.sweeps.iter().filter(|s| !s.radials.is_empty()) {
+        let mut sweep_col = Int16Builder::new();
+        let mut elevation = Float32Builder::new();
+        let mut azimuth = Float32Builder::new();
+        let mut range = Float32Builder::new();
+        let mut latitude = Float64Builder::new();
+        let mut longitude = Float64Builder::new();
+        let mut height = Float32Builder::new();
+        let mut time = TimestampMillisecondBuilder::new().with_timezone("UTC");
+        let mut values: Vec<Float32Builder> =
+            moments.iter().map(|_| Float32Builder::new()).collect();
+
+        for radial in sweep.radials.iter() {
+            // Rows follow the ray's reflectivity gates where present; other
+            // moments are placed on them by nearest gate.
+            let Some(reference) = radial
+                .moment(Moment::Reflectivity)
+                .or_else(|| radial.moments.first())
+            else {
+                continue;
+            };
+            let first_gate = reference.first_gate_range();
+            let gate_spacing = reference.gate_spacing();
+            let n_gates = radial
+                .moments
+                .iter()
+                .map(|m| m.gates_on_axis(first_gate, gate_spacing))
+                .max()
+                .unwrap_or(0);
+            let ray_moments: Vec<Option<&MomentData>> =
+                moments.iter().map(|&m| radial.moment(m)).collect();
+            let az = radial.header.azimuth_angle;
+            let el = radial.header.elevation_angle;
+            let millis = (radial.time() * 1000.0).round() as i64;
+
+            for g in 0..n_gates {
+                let r = first_gate + g as f32 * gate_spacing;
+                let gate_values: Vec<Option<f32>> = ray_moments
+                    .iter()
+                    .map(|m| m.and_then(|m| m.value_at_range(r)))
+                    .collect();
+                if opts.skip_empty && gate_values.iter().all(Option::is_none) {
+                    continue;
+                }
+                let (lat, lon, h) = gate_location(
+                    site.latitude as f64,
+                    site.longitude as f64,
+                    site_height,
+                    az as f64,
+                    el as f64,
+                    r as f64,
+                );
+                sweep_col.append_value(sweep.elevation_number as i16);
+                elevation.append_value(el);
+                azimuth.append_value(az);
+                range.append_value(r);
+                latitude.append_value(lat);
+                longitude.append_value(lon);
+                height.append_value(h as f32);
+                time.append_value(millis);
+                for (builder, value) in values.iter_mut().zip(gate_values) {
+                    builder.append_option(value);
+                }
+            }
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(sweep_col.finish()),
+            Arc::new(elevation.finish()),
+            Arc::new(azimuth.finish()),
+            Arc::new(range.finish()),
+            Arc::new(latitude.finish()),
+            Arc::new(longitude.finish()),
+            Arc::new(height.finish()),
+            Arc::new(time.finish()),
+        ];
+        columns.extend(values.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        if batch.num_rows() > 0 {
+            batches.push(batch);
+        }
+    }
+
+    Ok((schema, batches))
+}
+
+pub fn write_arrow_ipc(volume: &Volume, fp: &str, opts: &TableOptions) -> anyhow::Result<()> {
+    let (schema, batches) = gate_table(volume, opts)?;
+    let mut writer = FileWriter::try_new(File::create(fp)?, &schema)?;
+    for batch in batches.iter() {
+        writer.write(batch)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+pub fn write_parquet(volume: &Volume, fp: &str, opts: &TableOptions) -> anyhow::Result<()> {
+    let (schema, batches) = gate_table(volume, opts)?;
+    let props = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(fp)?, schema, Some(props))?;
+    for batch in batches.iter() {
+        writer.write(batch)?;
+        // Keep each sweep in its own row group.
+        writer.flush()?;
+    }
+    writer.close()?;
+    Ok(())
+}
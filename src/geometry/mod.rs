@@ -0,0 +1,86 @@
+// Beam propagation and site-relative geolocation using the standard 4/3 earth
+// radius model. Ranges and heights are in metres, angles in degrees.
+
+pub const EARTH_RADIUS: f64 = 6_371_000.0;
+pub const EFFECTIVE_EARTH_RADIUS: f64 = EARTH_RADIUS * 4.0 / 3.0;
+
+// Height of the beam centre above the radar, not above sea level.
+pub fn beam_height(slant_range: f64, elevation: f64) -> f64 {
+    let re = EFFECTIVE_EARTH_RADIUS;
+    let el = elevation.to_radians();
+    (slant_range.powi(2) + re.powi(2) + 2.0 * slant_range * re * el.sin()).sqrt() - re
+}
+
+// Distance along the earth's surface to the point under the beam.
+pub fn ground_range(slant_range: f64, elevation: f64) -> f64 {
+    let re = EFFECTIVE_EARTH_RADIUS;
+    let el = elevation.to_radians();
+    let h = beam_height(slant_range, elevation);
+    re * (slant_range * el.cos() / (re + h)).asin()
+}
+
+// Slant range at which a beam at `elevation` reaches `ground` distance; the
+// inverse of `ground_range`.
+pub fn slant_range(ground: f64, elevation: f64) -> f64 {
+    let re = EFFECTIVE_EARTH_RADIUS;
+    let el = elevation.to_radians();
+    let theta = ground / re;
+    re * theta.sin() / (el + theta).cos()
+}
+
+// Slant range at which a beam at `elevation` is `height` above the radar; the
+// inverse of `beam_height`.
+pub fn slant_range_at_height(height: f64, elevation: f64) -> f64 {
+    let re = EFFECTIVE_EARTH_RADIUS;
+    let b = 2.0 * re * elevation.to_radians().sin();
+    let c = -(height * height + 2.0 * height * re);
+    (-b + (b * b - 4.0 * c).sqrt()) / 2.0
+}
+
+// Point at `distance` along the surface from (lat, lon) on the given bearing.
+pub fn destination(lat: f64, lon: f64, bearing: f64, distance: f64) -> (f64, f64) {
+    let phi1 = lat.to_radians();
+    let lambda1 = lon.to_radians();
+    let theta = bearing.to_radians();
+    let delta = distance / EARTH_RADIUS;
+
+    let phi2 = (phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos()).asin();
+    let lambda2 = lambda1
+        + (theta.sin() * delta.sin() * phi1.cos()).atan2(delta.cos() - phi1.sin() * phi2.sin());
+    (
+        phi2.to_degrees(),
+        (lambda2.to_degrees() + 540.0).rem_euclid(360.0) - 180.0,
+    )
+}
+
+// Great-circle distance and initial bearing from the first point to the second.
+pub fn distance_bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> (f64, f64) {
+    let phi1 = lat1.to_radians();
+    let phi2 = lat2.to_radians();
+    let dphi = phi2 - phi1;
+    let dlambda = (lon2 - lon1).to_radians();
+
+    let a = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    let distance = 2.0 * EARTH_RADIUS * a.sqrt().atan2((1.0 - a).sqrt());
+    let y = dlambda.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlambda.cos();
+    (distance, (y.atan2(x).to_degrees() + 360.0) % 360.0)
+}
+
+// Gate centre location as (lat, lon, height above sea level).
+pub fn gate_location(
+    site_lat: f64,
+    site_lon: f64,
+    site_height: f64,
+    azimuth: f64,
+    elevation: f64,
+    slant_range: f64,
+) -> (f64, f64, f64) {
+    let (lat, lon) = destination(
+        site_lat,
+        site_lon,
+        azimuth,
+        ground_range(slant_range, elevation),
+    );
+    (lat, lon, site_height + beam_height(slant_range, elevation))
+}
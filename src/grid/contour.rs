@@ -0,0 +1,423 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::geometry::{destination, ground_range};
+use crate::grid::GriddedField;
+use crate::volume::{angle_difference, PolarField};
+
+// Filled contours by marching squares. For each threshold the region where
+// the field is at or above it is outlined, with the grid padded by missing
+// values so every ring closes, and polar sweeps wrapping round in azimuth.
+// Saddle cells are resolved with the mean of their corners. Rings nested an
+// even number of times are exteriors and odd ones are holes of the ring
+// directly around them; exteriors run counter-clockwise and holes clockwise,
+// as GeoJSON expects. Coordinates are (longitude, latitude).
+
+pub type Ring = Vec<(f64, f64)>;
+
+#[derive(Debug, Clone)]
+pub struct ContourPolygon {
+    pub exterior: Ring,
+    pub holes: Vec<Ring>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Contour {
+    pub threshold: f32,
+    pub polygons: Vec<ContourPolygon>,
+}
+
+pub fn contour_grid(field: &GriddedField, thresholds: &[f32]) -> Vec<Contour> {
+    let spec = field.spec;
+    let value = |row: i64, col: i64| {
+        if row < 0 || col < 0 {
+            return f32::NAN;
+        }
+        field.get(row as usize, col as usize)
+    };
+    let to_lon_lat = |row: f64, col: f64| {
+        (
+            spec.west + (col + 0.5) * spec.lon_step,
+            spec.north - (row + 0.5) * spec.lat_step,
+        )
+    };
+    thresholds
+        .iter()
+        .map(|&threshold| {
+            let rings = trace_rings(&value, spec.n_rows, spec.n_cols, false, threshold);
+            Contour {
+                threshold,
+                polygons: assemble(rings, &to_lon_lat),
+            }
+        })
+        .collect()
+}
+
+// Contours traced on the sweep's own radials and gates, then placed on the
+// ground along the 4/3 earth beam.
+pub fn contour_sweep(
+    field: &PolarField,
+    site_lat: f64,
+    site_lon: f64,
+    thresholds: &[f32],
+) -> Vec<Contour> {
+    let n_radials = field.n_radials();
+    if n_radials == 0 {
+        return thresholds
+            .iter()
+            .map(|&threshold| Contour {
+                threshold,
+                polygons: Vec::new(),
+            })
+            .collect();
+    }
+    let value = |row: i64, col: i64| {
+        if col < 0 {
+            return f32::NAN;
+        }
+        field.get(row.rem_euclid(n_radials as i64) as usize, col as usize)
+    };
+    let to_lon_lat = |row: f64, col: f64| {
+        let i = row.floor();
+        let f = (row - i) as f32;
+        let a0 = field.azimuths[(i as i64).rem_euclid(n_radials as i64) as usize];
+        let a1 = field.azimuths[(i as i64 + 1).rem_euclid(n_radials as i64) as usize];
+        let azimuth = (a0 + f * angle_difference(a1, a0)).rem_euclid(360.0);
+        let range = (field.first_gate as f64 + col * field.gate_spacing as f64).max(0.0);
+        let (lat, lon) = destination(
+            site_lat,
+            site_lon,
+            azimuth as f64,
+            ground_range(range, field.elevation as f64),
+        );
+        (lon, lat)
+    };
+    thresholds
+        .iter()
+        .map(|&threshold| {
+            let rings = trace_rings(&value, n_radials, field.n_gates(), true, threshold);
+            Contour {
+                threshold,
+                polygons: assemble(rings, &to_lon_lat),
+            }
+        })
+        .collect()
+}
+
+// A crossing on a grid edge: horizontal edges join (row, col) to
+// (row, col + 1) and vertical edges join (row, col) to (row + 1, col).
+type Edge = (bool, i64, i64);
+
+// Closed rings in fractional (row, col) node coordinates.
+fn trace_rings(
+    value: &dyn Fn(i64, i64) -> f32,
+    n_rows: usize,
+    n_cols: usize,
+    wrap_rows: bool,
+    threshold: f32,
+) -> Vec<Vec<(f64, f64)>> {
+    let inside = |v: f32| v >= threshold;
+    let canonical = |(vertical, row, col): Edge| -> Edge {
+        if wrap_rows {
+            (vertical, row.rem_euclid(n_rows as i64), col)
+        } else {
+            (vertical, row, col)
+        }
+    };
+    let rows = if wrap_rows {
+        0..n_rows as i64
+    } else {
+        -1..n_rows as i64
+    };
+
+    let mut links: BTreeMap<Edge, Vec<Edge>> = BTreeMap::new();
+    for r in rows {
+        for c in -1..n_cols as i64 {
+            let corners = [
+                value(r, c),
+                value(r, c + 1),
+                value(r + 1, c + 1),
+                value(r + 1, c),
+            ];
+            let case = corners
+                .iter()
+                .fold(0, |acc, &v| (acc << 1) | inside(v) as u8);
+            let top = canonical((false, r, c));
+            let bottom = canonical((false, r + 1, c));
+            let left = canonical((true, r, c));
+            let right = canonical((true, r, c + 1));
+            let centre_inside = || {
+                corners.iter().all(|v| v.is_finite()) && inside(corners.iter().sum::<f32>() / 4.0)
+            };
+            let segments: &[(Edge, Edge)] = match case {
+                1 | 14 => &[(left, bottom)],
+                2 | 13 => &[(bottom, right)],
+                3 | 12 => &[(left, right)],
+                4 | 11 => &[(top, right)],
+                6 | 9 => &[(top, bottom)],
+                7 | 8 => &[(top, left)],
+                5 if centre_inside() => &[(top, left), (bottom, right)],
+                5 => &[(top, right), (left, bottom)],
+                10 if centre_inside() => &[(top, right), (left, bottom)],
+                10 => &[(top, left), (bottom, right)],
+                _ => &[],
+            };
+            for &(a, b) in segments {
+                links.entry(a).or_default().push(b);
+                links.entry(b).or_default().push(a);
+            }
+        }
+    }
+
+    let point = |(vertical, row, col): Edge| {
+        let (r1, c1) = if vertical {
+            (row + 1, col)
+        } else {
+            (row, col + 1)
+        };
+        let (va, vb) = (value(row, col), value(r1, c1));
+        let t = if va.is_finite() && vb.is_finite() && va != vb {
+            ((threshold - va) / (vb - va)).clamp(0.0, 1.0) as f64
+        } else {
+            0.5
+        };
+        if vertical {
+            (row as f64 + t, col as f64)
+        } else {
+            (row as f64, col as f64 + t)
+        }
+    };
+
+    let mut rings = Vec::new();
+    let mut visited: BTreeSet<Edge> = BTreeSet::new();
+    for &start in links.keys() {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut ring = Vec::new();
+        let mut current = start;
+        loop {
+            visited.insert(current);
+            ring.push(point(current));
+            let next = links[&current]
+                .iter()
+                .copied()
+                .find(|e| !visited.contains(e));
+            match next {
+                Some(edge) => current = edge,
+                None => break,
+            }
+        }
+        if ring.len() >= 3 {
+            rings.push(ring);
+        }
+    }
+    rings
+}
+
+// Georeferences rings and groups them into polygons by nesting depth.
+fn assemble(
+    rings: Vec<Vec<(f64, f64)>>,
+    to_lon_lat: &dyn Fn(f64, f64) -> (f64, f64),
+) -> Vec<ContourPolygon> {
+    let rings: Vec<Ring> = rings
+        .into_iter()
+        .filter_map(|ring| {
+            let mut ring: Ring = ring.iter().map(|&(r, c)| to_lon_lat(r, c)).collect();
+            // Crossings at a corner exactly on the threshold coincide.
+            ring.dedup();
+            while ring.len() > 1 && ring.first() == ring.last() {
+                ring.pop();
+            }
+            if ring.len() < 3 {
+                return None;
+            }
+            ring.push(ring[0]);
+            Some(ring)
+        })
+        .collect();
+    let bounds: Vec<[f64; 4]> = rings.iter().map(bounding_box).collect();
+    let containers: Vec<Vec<usize>> = (0..rings.len())
+        .map(|i| {
+            let p = rings[i][0];
+            (0..rings.len())
+                .filter(|&j| {
+                    let b = bounds[j];
+                    j != i
+                        && p.0 >= b[0]
+                        && p.0 <= b[2]
+                        && p.1 >= b[1]
+                        && p.1 <= b[3]
+                        && contains(&rings[j], p)
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut polygons = Vec::new();
+    let mut index = vec![usize::MAX; rings.len()];
+    for (i, ring) in rings.iter().enumerate() {
+        if containers[i].len().is_multiple_of(2) {
+            index[i] = polygons.len();
+            polygons.push(ContourPolygon {
+                exterior: oriented(ring.clone(), true),
+                holes: Vec::new(),
+            });
+        }
+    }
+    for (i, ring) in rings.iter().enumerate() {
+        let depth = containers[i].len();
+        if !depth.is_multiple_of(2) {
+            // The parent is the container one level further out.
+            if let Some(&parent) = containers[i]
+                .iter()
+                .find(|&&j| containers[j].len() == depth - 1)
+            {
+                polygons[index[parent]]
+                    .holes
+                    .push(oriented(ring.clone(), false));
+            }
+        }
+    }
+    polygons
+}
+
+fn signed_area(ring: &Ring) -> f64 {
+    ring.windows(2)
+        .map(|w| w[0].0 * w[1].1 - w[1].0 * w[0].1)
+        .sum::<f64>()
+        / 2.0
+}
+
+fn oriented(mut ring: Ring, counter_clockwise: bool) -> Ring {
+    if (signed_area(&ring) > 0.0) != counter_clockwise {
+        ring.reverse();
+    }
+    ring
+}
+
+fn bounding_box(ring: &Ring) -> [f64; 4] {
+    ring.iter()
+        .fold([f64::MAX, f64::MAX, f64::MIN, f64::MIN], |b, &(x, y)| {
+            [b[0].min(x), b[1].min(y), b[2].max(x), b[3].max(y)]
+        })
+}
+
+// Even-odd point in polygon test.
+fn contains(ring: &Ring, (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::GridSpec;
+
+    fn grid(rows: &[&[f32]]) -> GriddedField {
+        let spec = GridSpec {
+            north: 10.0,
+            west: 0.0,
+            lat_step: 1.0,
+            lon_step: 1.0,
+            n_rows: rows.len(),
+            n_cols: rows[0].len(),
+        };
+        let mut field = GriddedField::filled(spec, 0.0, f32::NAN);
+        field.data = rows.iter().map(|r| r.to_vec()).collect();
+        field
+    }
+
+    fn assert_closed(ring: &Ring) {
+        assert!(ring.len() >= 4);
+        assert_eq!(ring.first(), ring.last());
+    }
+
+    #[test]
+    fn ring_with_hole() {
+        let field = grid(&[
+            &[0.0, 0.0, 0.0, 0.0, 0.0],
+            &[0.0, 50.0, 50.0, 50.0, 0.0],
+            &[0.0, 50.0, 0.0, 50.0, 0.0],
+            &[0.0, 50.0, 50.0, 50.0, 0.0],
+            &[0.0, 0.0, 0.0, 0.0, 0.0],
+        ]);
+        let contours = contour_grid(&field, &[25.0]);
+        assert_eq!(contours.len(), 1);
+        let polygons = &contours[0].polygons;
+        assert_eq!(polygons.len(), 1);
+        let polygon = &polygons[0];
+        assert_eq!(polygon.holes.len(), 1);
+        assert_closed(&polygon.exterior);
+        assert_closed(&polygon.holes[0]);
+
+        // Exteriors counter-clockwise, holes clockwise. Crossings sit halfway
+        // between cell centres, so the exterior spans 3 cells and the hole 1,
+        // both as diamonds at the corners.
+        let exterior = signed_area(&polygon.exterior);
+        let hole = signed_area(&polygon.holes[0]);
+        assert!(exterior > 0.0 && hole < 0.0);
+        assert!((exterior - 8.5).abs() < 1e-9, "{}", exterior);
+        assert!((hole + 0.5).abs() < 1e-9, "{}", hole);
+
+        // The hole lies inside the exterior around the centre cell (lon 2.5, lat 7.5).
+        assert!(contains(&polygon.exterior, polygon.holes[0][0]));
+        assert!(contains(&polygon.holes[0], (2.5, 7.5)));
+    }
+
+    #[test]
+    fn island_in_hole_is_its_own_polygon() {
+        let mut rows = vec![vec![0.0_f32; 9]; 9];
+        for (r, row) in rows.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                let ring = r.min(c).min(8 - r).min(8 - c);
+                *value = match ring {
+                    1 | 4 => 50.0,
+                    _ => 0.0,
+                };
+            }
+        }
+        let rows: Vec<&[f32]> = rows.iter().map(|r| r.as_slice()).collect();
+        let polygons = &contour_grid(&grid(&rows), &[25.0])[0].polygons;
+        assert_eq!(polygons.len(), 2);
+        let holes: Vec<usize> = polygons.iter().map(|p| p.holes.len()).collect();
+        assert!(holes.contains(&1) && holes.contains(&0));
+        assert!(polygons.iter().all(|p| signed_area(&p.exterior) > 0.0));
+    }
+
+    #[test]
+    fn saddles_follow_the_centre_value() {
+        // Diagonal corners above the threshold: a low centre keeps them
+        // apart, a high one joins them.
+        let saddle = |low: f32| {
+            grid(&[
+                &[0.0, 0.0, 0.0, 0.0],
+                &[0.0, 50.0, low, 0.0],
+                &[0.0, low, 50.0, 0.0],
+                &[0.0, 0.0, 0.0, 0.0],
+            ])
+        };
+        let apart = &contour_grid(&saddle(0.0), &[30.0])[0].polygons;
+        assert_eq!(apart.len(), 2);
+        assert!(apart.iter().all(|p| p.holes.is_empty()));
+
+        let joined = &contour_grid(&saddle(20.0), &[30.0])[0].polygons;
+        assert_eq!(joined.len(), 1);
+        assert!(joined[0].holes.is_empty());
+        assert!(signed_area(&joined[0].exterior) > 0.0);
+    }
+
+    #[test]
+    fn missing_values_close_rings_at_the_grid_edge() {
+        let field = grid(&[&[50.0, 50.0], &[50.0, f32::NAN]]);
+        let polygons = &contour_grid(&field, &[25.0])[0].polygons;
+        assert_eq!(polygons.len(), 1);
+        assert_closed(&polygons[0].exterior);
+        assert!(contour_grid(&field, &[60.0])[0].polygons.is_empty());
+    }
+}
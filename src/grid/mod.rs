@@ -0,0 +1,182 @@
+pub mod contour;
+pub mod mosaic;
+
+use crate::geometry::{distance_bearing, slant_range, EARTH_RADIUS};
+use crate::volume::{angle_difference, Moment, PolarField, Volume};
+
+// Regular latitude/longitude grids. Rows run north to south and columns west
+// to east, so row 0 is the northern edge as in most image formats.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridSpec {
+    // Outer edges of the grid in degrees.
+    pub north: f64,
+    pub west: f64,
+    pub lat_step: f64,
+    pub lon_step: f64,
+    pub n_rows: usize,
+    pub n_cols: usize,
+}
+
+impl GridSpec {
+    // A square grid of roughly `spacing` metres centred on (lat, lon) and
+    // extending `half_width` metres each way.
+    pub fn centred(lat: f64, lon: f64, half_width: f64, spacing: f64) -> GridSpec {
+        let lat_step = (spacing / EARTH_RADIUS).to_degrees();
+        let lon_step = lat_step / lat.to_radians().cos();
+        let n = (2.0 * half_width / spacing).ceil() as usize;
+        GridSpec {
+            north: lat + n as f64 / 2.0 * lat_step,
+            west: lon - n as f64 / 2.0 * lon_step,
+            lat_step,
+            lon_step,
+            n_rows: n,
+            n_cols: n,
+        }
+    }
+
+    // A grid of roughly `spacing` metres covering the given bounds in degrees.
+    pub fn bounded(south: f64, north: f64, west: f64, east: f64, spacing: f64) -> GridSpec {
+        let lat_step = (spacing / EARTH_RADIUS).to_degrees();
+        let lon_step = lat_step / ((south + north) / 2.0).to_radians().cos();
+        GridSpec {
+            north,
+            west,
+            lat_step,
+            lon_step,
+            n_rows: ((north - south) / lat_step).ceil().max(1.0) as usize,
+            n_cols: ((east - west) / lon_step).ceil().max(1.0) as usize,
+        }
+    }
+
+    pub fn south(&self) -> f64 {
+        self.north - self.n_rows as f64 * self.lat_step
+    }
+
+    pub fn east(&self) -> f64 {
+        self.west + self.n_cols as f64 * self.lon_step
+    }
+
+    pub fn cell_centre(&self, row: usize, col: usize) -> (f64, f64) {
+        (
+            self.north - (row as f64 + 0.5) * self.lat_step,
+            self.west + (col as f64 + 0.5) * self.lon_step,
+        )
+    }
+
+    pub fn cell_at(&self, lat: f64, lon: f64) -> Option<(usize, usize)> {
+        let row = ((self.north - lat) / self.lat_step).floor();
+        let col = ((lon - self.west) / self.lon_step).floor();
+        if row < 0.0 || col < 0.0 || row as usize >= self.n_rows || col as usize >= self.n_cols {
+            return None;
+        }
+        Some((row as usize, col as usize))
+    }
+
+    // Approximate cell size in metres (east-west, north-south) at the grid centre.
+    pub fn cell_size(&self) -> (f64, f64) {
+        let centre_lat = (self.north + self.south()) / 2.0;
+        let dy = EARTH_RADIUS * self.lat_step.to_radians();
+        (
+            dy * self.lon_step / self.lat_step * centre_lat.to_radians().cos(),
+            dy,
+        )
+    }
+}
+
+// A gridded field as [row][col], NaN where there is no data.
+#[derive(Debug, Clone)]
+pub struct GriddedField {
+    pub spec: GridSpec,
+    pub time: f64,
+    pub data: Vec<Vec<f32>>,
+}
+
+impl GriddedField {
+    pub fn filled(spec: GridSpec, time: f64, value: f32) -> GriddedField {
+        GriddedField {
+            spec,
+            time,
+            data: vec![vec![value; spec.n_cols]; spec.n_rows],
+        }
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f32 {
+        self.data
+            .get(row)
+            .and_then(|r| r.get(col))
+            .copied()
+            .unwrap_or(f32::NAN)
+    }
+}
+
+// Nearest-gate remapping of a sweep onto a grid. Cells beyond the last gate
+// or in an azimuth gap wider than one radial are left NaN.
+pub fn grid_field(
+    field: &PolarField,
+    site_lat: f64,
+    site_lon: f64,
+    spec: GridSpec,
+) -> GriddedField {
+    let mut grid = GriddedField::filled(
+        spec,
+        field
+            .times
+            .iter()
+            .cloned()
+            .reduce(f64::min)
+            .unwrap_or_default(),
+        f32::NAN,
+    );
+    if field.n_radials() == 0 {
+        return grid;
+    }
+
+    // Nearest radial for every tenth of a degree.
+    let tolerance = 360.0 / field.n_radials() as f32;
+    let lookup: Vec<Option<usize>> = (0..3600)
+        .map(|i| {
+            let azimuth = i as f32 / 10.0;
+            field
+                .radial_at_azimuth(azimuth)
+                .filter(|&r| angle_difference(field.azimuths[r], azimuth).abs() <= tolerance)
+        })
+        .collect();
+
+    for (row, values) in grid.data.iter_mut().enumerate() {
+        for (col, value) in values.iter_mut().enumerate() {
+            let (lat, lon) = spec.cell_centre(row, col);
+            let (ground, bearing) = distance_bearing(site_lat, site_lon, lat, lon);
+            let range = slant_range(ground, field.elevation as f64) as f32;
+            let Some(gate) = field.gate_at_range(range) else {
+                continue;
+            };
+            let bin = ((bearing * 10.0).round() as usize) % 3600;
+            if let Some(radial) = lookup[bin] {
+                *value = field.get(radial, gate);
+            }
+        }
+    }
+    grid
+}
+
+// Column maximum reflectivity over every sweep of the volume.
+pub fn composite_reflectivity(volume: &Volume, spec: GridSpec) -> anyhow::Result<GriddedField> {
+    let site = volume.require_site()?;
+    let mut composite =
+        GriddedField::filled(spec, volume.start_time().unwrap_or_default(), f32::NAN);
+    for sweep in volume.sweeps_with(Moment::Reflectivity) {
+        let Some(field) = sweep.field(Moment::Reflectivity) else {
+            continue;
+        };
+        let gridded = grid_field(&field, site.latitude as f64, site.longitude as f64, spec);
+        for (row, other) in composite.data.iter_mut().zip(gridded.data.iter()) {
+            for (value, &v) in row.iter_mut().zip(other.iter()) {
+                if value.is_nan() || v > *value {
+                    *value = v;
+                }
+            }
+        }
+    }
+    Ok(composite)
+}
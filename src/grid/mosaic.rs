@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::geometry::{beam_height, distance_bearing, slant_range};
+use crate::grid::{grid_field, GridSpec, GriddedField};
+use crate::products::{db_to_linear, linear_to_db};
+use crate::volume::{Moment, Volume};
+
+// Multi-radar mosaics. Each site contributes its volume nearest the target
+// time; per cell, every site's sample is weighted by a Gaussian fall-off in
+// range and in height away from the requested level, times a per-site
+// quality index, and the samples are averaged.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MosaicProduct {
+    // Column maximum, weighted by the height of the lowest beam.
+    Composite,
+    // Constant altitude PPI at a height above sea level in metres.
+    Cappi { height: f32 },
+    // Lowest sweep with data at each cell.
+    LowestAltitude,
+}
+
+#[derive(Debug, Clone)]
+pub struct MosaicOptions {
+    pub moment: Moment,
+    pub product: MosaicProduct,
+    pub max_range: f32,    // metres
+    pub range_scale: f32,  // metres
+    pub height_scale: f32, // metres
+    // Defaults to the latest volume start time over all sites.
+    pub target_time: Option<f64>,
+    pub max_time_difference: f64, // seconds
+    // Quality index in [0, 1] by ICAO; sites not listed get 1.
+    pub site_quality: HashMap<String, f32>,
+}
+
+impl Default for MosaicOptions {
+    fn default() -> Self {
+        MosaicOptions {
+            moment: Moment::Reflectivity,
+            product: MosaicProduct::Composite,
+            max_range: 230_000.0,
+            range_scale: 100_000.0,
+            height_scale: 2_000.0,
+            target_time: None,
+            max_time_difference: 600.0,
+            site_quality: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mosaic {
+    pub field: GriddedField,
+    // Sum of the weights behind each cell; zero where no site reached it.
+    pub weight: GriddedField,
+    // Number of sites contributing to each cell.
+    pub coverage: Vec<Vec<u8>>,
+    pub sites: Vec<String>,
+}
+
+pub fn mosaic(
+    volumes: &[Volume],
+    spec: GridSpec,
+    options: &MosaicOptions,
+) -> anyhow::Result<Mosaic> {
+    let target = options
+        .target_time
+        .or_else(|| {
+            volumes
+                .iter()
+                .filter_map(|v| v.start_time())
+                .reduce(f64::max)
+        })
+        .ok_or_else(|| anyhow::anyhow!("No volumes with radial data to mosaic"))?;
+    let selected = nearest_in_time(volumes, target, options.max_time_difference);
+    if selected.is_empty() {
+        anyhow::bail!(
+            "No volume lies within {} s of the target time",
+            options.max_time_difference
+        );
+    }
+
+    let linear = options.moment == Moment::Reflectivity;
+    let mut sum = vec![vec![0.0_f64; spec.n_cols]; spec.n_rows];
+    let mut weight = GriddedField::filled(spec, target, 0.0);
+    let mut coverage = vec![vec![0_u8; spec.n_cols]; spec.n_rows];
+    let mut sites = Vec::new();
+
+    for (icao, volume) in selected {
+        let Some(site) = volume.site() else {
+            continue;
+        };
+        let quality = options.site_quality.get(&icao).copied().unwrap_or(1.0);
+        if quality <= 0.0 {
+            continue;
+        }
+        let (site_lat, site_lon) = (site.latitude as f64, site.longitude as f64);
+        let site_height = site.site_height as f32;
+
+        let mut tilts: Vec<(f32, GriddedField)> = volume
+            .sweeps_with(options.moment)
+            .filter_map(|s| s.field(options.moment))
+            .map(|f| (f.elevation, grid_field(&f, site_lat, site_lon, spec)))
+            .collect();
+        tilts.sort_by(|a, b| a.0.total_cmp(&b.0));
+        tilts.dedup_by(|b, a| (a.0 - b.0).abs() < 0.2);
+        if tilts.is_empty() {
+            continue;
+        }
+        sites.push(icao);
+
+        for row in 0..spec.n_rows {
+            for col in 0..spec.n_cols {
+                let (lat, lon) = spec.cell_centre(row, col);
+                let (ground, _) = distance_bearing(site_lat, site_lon, lat, lon);
+                if ground > options.max_range as f64 {
+                    continue;
+                }
+                // (value, height above sea level) of every tilt at this cell.
+                let samples: Vec<(f32, f32)> = tilts
+                    .iter()
+                    .map(|(elevation, grid)| {
+                        let range = slant_range(ground, *elevation as f64);
+                        let height = site_height + beam_height(range, *elevation as f64) as f32;
+                        (grid.data[row][col], height)
+                    })
+                    .collect();
+                let Some((value, height_offset)) =
+                    select_sample(&samples, options.product, site_height)
+                else {
+                    continue;
+                };
+
+                let w = quality
+                    * (-(ground as f32 / options.range_scale).powi(2)).exp()
+                    * (-(height_offset / options.height_scale).powi(2)).exp();
+                if w <= 0.0 {
+                    continue;
+                }
+                let v = if linear { db_to_linear(value) } else { value };
+                sum[row][col] += (w * v) as f64;
+                weight.data[row][col] += w;
+                coverage[row][col] = coverage[row][col].saturating_add(1);
+            }
+        }
+    }
+
+    let mut field = GriddedField::filled(spec, target, f32::NAN);
+    for ((out, sums), weights) in field
+        .data
+        .iter_mut()
+        .zip(sum.iter())
+        .zip(weight.data.iter())
+    {
+        for ((value, &s), &w) in out.iter_mut().zip(sums.iter()).zip(weights.iter()) {
+            if w > 0.0 {
+                let v = (s / w as f64) as f32;
+                *value = if linear { linear_to_db(v) } else { v };
+            }
+        }
+    }
+
+    Ok(Mosaic {
+        field,
+        weight,
+        coverage,
+        sites,
+    })
+}
+
+// The volume of each site whose start time is closest to `target`, keyed by
+// ICAO and dropping sites with nothing inside the window.
+pub fn nearest_in_time(
+    volumes: &[Volume],
+    target: f64,
+    max_difference: f64,
+) -> BTreeMap<String, &Volume> {
+    let mut selected: BTreeMap<String, (&Volume, f64)> = BTreeMap::new();
+    for volume in volumes {
+        let Some(start) = volume.start_time() else {
+            continue;
+        };
+        let difference = (start - target).abs();
+        if difference > max_difference {
+            continue;
+        }
+        let icao = volume.header.icao.trim().to_string();
+        match selected.get(&icao) {
+            Some((_, best)) if *best <= difference => {}
+            _ => {
+                selected.insert(icao, (volume, difference));
+            }
+        }
+    }
+    selected.into_iter().map(|(k, (v, _))| (k, v)).collect()
+}
+
+// Picks the value representing a column for the product, together with the
+// height offset used for weighting: the distance from the CAPPI level, or
+// otherwise the height of the chosen beam above the radar.
+fn select_sample(
+    samples: &[(f32, f32)],
+    product: MosaicProduct,
+    site_height: f32,
+) -> Option<(f32, f32)> {
+    let lowest = samples.first()?.1;
+    match product {
+        MosaicProduct::Composite => samples
+            .iter()
+            .filter(|(v, _)| !v.is_nan())
+            .map(|(v, _)| *v)
+            .reduce(f32::max)
+            .map(|v| (v, lowest - site_height)),
+        MosaicProduct::Cappi { height } => samples
+            .iter()
+            .filter(|(v, _)| !v.is_nan())
+            .min_by(|a, b| (a.1 - height).abs().total_cmp(&(b.1 - height).abs()))
+            .map(|(v, h)| (*v, (h - height).abs())),
+        MosaicProduct::LowestAltitude => samples
+            .iter()
+            .find(|(v, _)| !v.is_nan())
+            .map(|(v, h)| (*v, h - site_height)),
+    }
+}
@@ -0,0 +1,9 @@
+pub mod export;
+pub mod geometry;
+pub mod grid;
+pub mod messages;
+pub mod products;
+pub mod qc;
+pub mod reader;
+pub mod render;
+pub mod volume;
@@ -10,14 +10,12 @@ use std::{
     iter,
 };
 
-pub mod messages;
-pub mod reader;
-use crate::messages::{
+use rust_radar_acc::messages::{
     ClutterFilterMapMetadata, MessageHeader, MessageHeaderRaw, RawClutterFilterMapMetadata,
     VolumeHeader, VolumeHeaderRaw, DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE,
     MESSAGE_HEADER_SIZE,
 };
-use crate::reader::{
+use rust_radar_acc::reader::{
     decompress_nexrad_file, read_data_header, read_message_header, read_volume_header,
 };
 
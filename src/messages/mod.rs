@@ -1,4 +1,5 @@
 use packed_struct::prelude::*;
+use serde::{Deserialize, Serialize};
 // EACH WORD IS 4 BYTES; a halfword is 2 bytes.
 
 pub const HALFWORD_SIZE: usize = 2;
@@ -52,7 +53,7 @@ impl MessageHeaderRaw {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MessageHeader {
     pub messagesize: i16,
     pub rda_redundant_channel: i8,
@@ -71,7 +72,7 @@ impl TryFrom<MessageHeaderRaw> for MessageHeader {
         Ok(MessageHeader {
             messagesize: i16::from_be_bytes(value.messagesize),
             rda_redundant_channel: i8::from_be_bytes(value.rda_redundant_channel),
-            message_type: collate_message_type(i8::from_be_bytes(value.message_type)).unwrap(),
+            message_type: collate_message_type(i8::from_be_bytes(value.message_type))?,
             id_seq_no: i16::from_be_bytes(value.id_seq_no),
             julian_date: i16::from_be_bytes(value.julian_date),
             ms_from_midnight: i32::from_be_bytes(value.ms_from_midnight),
@@ -81,7 +82,7 @@ impl TryFrom<MessageHeaderRaw> for MessageHeader {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MessageType {
     DigitalRadarData,
     RDAStatusData,
@@ -139,7 +140,7 @@ pub fn collate_message_type(message_header_type: i8) -> Result<MessageType, std:
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VolumeHeader {
     pub volumename: String,
     pub date: i32,
@@ -179,6 +180,7 @@ impl RawClutterFilterMapMetadata {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClutterFilterMapMetadata {
     pub map_generation_date: i16,
     pub map_generation_time: i16,
@@ -186,6 +188,45 @@ pub struct ClutterFilterMapMetadata {
     pub elevation_segments: Vec<ElevationSegment>,
 }
 
+impl TryFrom<RawClutterFilterMapMetadata> for ClutterFilterMapMetadata {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(value: RawClutterFilterMapMetadata) -> Result<Self, Self::Error> {
+        let mut segments: Vec<ElevationSegment> = Vec::new();
+        for eseg in value.elevation_segments.into_iter() {
+            segments.push(ElevationSegment::try_from(eseg)?);
+        }
+        Ok(ClutterFilterMapMetadata {
+            map_generation_date: i16::from_be_bytes(value.map_generation_date),
+            map_generation_time: i16::from_be_bytes(value.map_generation_time),
+            num_elevation_segments: i16::from_be_bytes(value.num_elevation_segments),
+            elevation_segments: segments,
+        })
+    }
+}
+
+// Range zone op codes in the clutter filter map.
+pub const CLUTTER_OPCODE_BYPASS_FILTER: i16 = 0;
+pub const CLUTTER_OPCODE_BYPASS_MAP_IN_CONTROL: i16 = 1;
+pub const CLUTTER_OPCODE_FORCE_FILTER: i16 = 2;
+
+impl ClutterFilterMapMetadata {
+    // Op code in force at `range_km` along the 1 degree azimuth segment
+    // containing `azimuth`.
+    pub fn opcode_at(&self, elevation_segment: usize, azimuth: f32, range_km: f32) -> Option<i16> {
+        let segment = self.elevation_segments.get(elevation_segment)?;
+        let index = (azimuth.rem_euclid(360.0) as usize).min(359);
+        segment
+            .azimuth_segments
+            .get(index)?
+            .range_zones
+            .iter()
+            .find(|zone| range_km <= zone.endrange as f32)
+            .map(|zone| zone.opcode)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ElevationSegment {
     pub azimuth_segments: Vec<AzimuthSegment>,
 }
@@ -244,6 +285,7 @@ impl TryFrom<RawAzimuthSegment> for AzimuthSegment {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AzimuthSegment {
     pub num_rangezones: i16,
     pub range_zones: Vec<RangeZone>,
@@ -292,7 +334,7 @@ impl TryFrom<RawRangeZone> for RangeZone {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct RangeZone {
     pub range_zone_num: i16,
     pub opcode: i16,
@@ -309,7 +351,7 @@ impl RangeZone {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DigitalRadarDataGenericFormatHeader {
     pub radar_identifier: String,
     pub collection_time: i32,
@@ -371,9 +413,7 @@ pub struct DigitalRadarDataGenericFormatHeaderRaw {
 impl From<DigitalRadarDataGenericFormatHeaderRaw> for DigitalRadarDataGenericFormatHeader {
     fn from(value: DigitalRadarDataGenericFormatHeaderRaw) -> Self {
         DigitalRadarDataGenericFormatHeader {
-            radar_identifier: std::str::from_utf8(&value.radar_identifier)
-                .unwrap()
-                .to_string(),
+            radar_identifier: String::from_utf8_lossy(&value.radar_identifier).into_owned(),
             collection_time: i32::from_be_bytes(value.collection_time),
             modified_julian_date: i16::from_be_bytes(value.modified_julian_date),
             azimuth_number: i16::from_be_bytes(value.azimuth_number),
@@ -444,8 +484,194 @@ impl Default for DigitalRadarDataGenericFormatHeaderRaw {
     }
 }
 
-#[derive(PackedStruct, Debug)]
+#[derive(PackedStruct, Debug, Serialize, Deserialize)]
 #[packed_struct(endian = "msb")]
 pub struct Message31DataBlock {
     pub block_type: i16,
 }
+
+// Message 31 data blocks. Each block is located through one of the pointers
+// in the generic format header (offsets are from the start of the header).
+pub const DATA_BLOCK_NAME_SIZE: usize = 4;
+pub const VOLUME_DATA_BLOCK_SIZE: usize = 44;
+pub const ELEVATION_DATA_BLOCK_SIZE: usize = 12;
+pub const RADIAL_DATA_BLOCK_SIZE: usize = 28;
+pub const GENERIC_MOMENT_HEADER_SIZE: usize = 28;
+
+#[derive(Default, Debug)]
+pub struct VolumeDataBlockRaw {
+    pub block_name: [u8; 4],
+    pub lrtup: [u8; 2],
+    pub version_major: [u8; 1],
+    pub version_minor: [u8; 1],
+    pub latitude: [u8; 4],
+    pub longitude: [u8; 4],
+    pub site_height: [u8; 2],
+    pub feedhorn_height: [u8; 2],
+    pub calibration_constant: [u8; 4],
+    pub horizontal_shv_tx_power: [u8; 4],
+    pub vertical_shv_tx_power: [u8; 4],
+    pub system_differential_reflectivity: [u8; 4],
+    pub initial_system_differential_phase: [u8; 4],
+    pub vcp_number: [u8; 2],
+    pub processing_status: [u8; 2],
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VolumeDataBlock {
+    pub lrtup: u16,
+    pub version_major: u8,
+    pub version_minor: u8,
+    pub latitude: f32,
+    pub longitude: f32,
+    pub site_height: i16,     // metres above sea level
+    pub feedhorn_height: u16, // metres above ground
+    pub calibration_constant: f32,
+    pub horizontal_shv_tx_power: f32,
+    pub vertical_shv_tx_power: f32,
+    pub system_differential_reflectivity: f32,
+    pub initial_system_differential_phase: f32,
+    pub vcp_number: u16,
+    pub processing_status: u16,
+}
+
+impl From<VolumeDataBlockRaw> for VolumeDataBlock {
+    fn from(value: VolumeDataBlockRaw) -> Self {
+        VolumeDataBlock {
+            lrtup: u16::from_be_bytes(value.lrtup),
+            version_major: value.version_major[0],
+            version_minor: value.version_minor[0],
+            latitude: f32::from_be_bytes(value.latitude),
+            longitude: f32::from_be_bytes(value.longitude),
+            site_height: i16::from_be_bytes(value.site_height),
+            feedhorn_height: u16::from_be_bytes(value.feedhorn_height),
+            calibration_constant: f32::from_be_bytes(value.calibration_constant),
+            horizontal_shv_tx_power: f32::from_be_bytes(value.horizontal_shv_tx_power),
+            vertical_shv_tx_power: f32::from_be_bytes(value.vertical_shv_tx_power),
+            system_differential_reflectivity: f32::from_be_bytes(
+                value.system_differential_reflectivity,
+            ),
+            initial_system_differential_phase: f32::from_be_bytes(
+                value.initial_system_differential_phase,
+            ),
+            vcp_number: u16::from_be_bytes(value.vcp_number),
+            processing_status: u16::from_be_bytes(value.processing_status),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct ElevationDataBlockRaw {
+    pub block_name: [u8; 4],
+    pub lrtup: [u8; 2],
+    pub atmospheric_attenuation: [u8; 2],
+    pub calibration_constant: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ElevationDataBlock {
+    pub lrtup: u16,
+    pub atmospheric_attenuation: f32, // dB/km
+    pub calibration_constant: f32,
+}
+
+impl From<ElevationDataBlockRaw> for ElevationDataBlock {
+    fn from(value: ElevationDataBlockRaw) -> Self {
+        ElevationDataBlock {
+            lrtup: u16::from_be_bytes(value.lrtup),
+            atmospheric_attenuation: i16::from_be_bytes(value.atmospheric_attenuation) as f32
+                * 0.001,
+            calibration_constant: f32::from_be_bytes(value.calibration_constant),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct RadialDataBlockRaw {
+    pub block_name: [u8; 4],
+    pub lrtup: [u8; 2],
+    pub unambiguous_range: [u8; 2],
+    pub horizontal_noise_level: [u8; 4],
+    pub vertical_noise_level: [u8; 4],
+    pub nyquist_velocity: [u8; 2],
+    pub radial_flags: [u8; 2],
+    pub horizontal_calibration_constant: [u8; 4],
+    pub vertical_calibration_constant: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RadialDataBlock {
+    pub lrtup: u16,
+    pub unambiguous_range: f32, // km
+    pub horizontal_noise_level: f32,
+    pub vertical_noise_level: f32,
+    pub nyquist_velocity: f32, // m/s
+    pub radial_flags: u16,
+    pub horizontal_calibration_constant: f32,
+    pub vertical_calibration_constant: f32,
+}
+
+impl From<RadialDataBlockRaw> for RadialDataBlock {
+    fn from(value: RadialDataBlockRaw) -> Self {
+        RadialDataBlock {
+            lrtup: u16::from_be_bytes(value.lrtup),
+            unambiguous_range: i16::from_be_bytes(value.unambiguous_range) as f32 * 0.1,
+            horizontal_noise_level: f32::from_be_bytes(value.horizontal_noise_level),
+            vertical_noise_level: f32::from_be_bytes(value.vertical_noise_level),
+            nyquist_velocity: i16::from_be_bytes(value.nyquist_velocity) as f32 * 0.01,
+            radial_flags: u16::from_be_bytes(value.radial_flags),
+            horizontal_calibration_constant: f32::from_be_bytes(
+                value.horizontal_calibration_constant,
+            ),
+            vertical_calibration_constant: f32::from_be_bytes(value.vertical_calibration_constant),
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct GenericMomentHeaderRaw {
+    pub block_name: [u8; 4],
+    pub reserved: [u8; 4],
+    pub n_gates: [u8; 2],
+    pub first_gate_range: [u8; 2],
+    pub gate_spacing: [u8; 2],
+    pub threshold: [u8; 2],
+    pub snr_threshold: [u8; 2],
+    pub control_flags: [u8; 1],
+    pub data_word_size: [u8; 1],
+    pub scale: [u8; 4],
+    pub offset: [u8; 4],
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenericMomentHeader {
+    pub name: String,
+    pub n_gates: u16,
+    pub first_gate_range: i16, // metres
+    pub gate_spacing: i16,     // metres
+    pub threshold: i16,
+    pub snr_threshold: i16,
+    pub control_flags: u8,
+    pub data_word_size: u8,
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl TryFrom<GenericMomentHeaderRaw> for GenericMomentHeader {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(value: GenericMomentHeaderRaw) -> Result<Self, Self::Error> {
+        Ok(GenericMomentHeader {
+            name: std::str::from_utf8(&value.block_name[1..])?.to_string(),
+            n_gates: u16::from_be_bytes(value.n_gates),
+            first_gate_range: i16::from_be_bytes(value.first_gate_range),
+            gate_spacing: i16::from_be_bytes(value.gate_spacing),
+            threshold: i16::from_be_bytes(value.threshold),
+            snr_threshold: i16::from_be_bytes(value.snr_threshold),
+            control_flags: value.control_flags[0],
+            data_word_size: value.data_word_size[0],
+            scale: f32::from_be_bytes(value.scale),
+            offset: f32::from_be_bytes(value.offset),
+        })
+    }
+}
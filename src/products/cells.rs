@@ -0,0 +1,498 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::geometry::{beam_height, destination, ground_range};
+use crate::products::db_to_linear;
+use crate::volume::{Moment, PolarField, Volume};
+
+// Storm cell identification and tracking along the lines of SCIT (Johnson et
+// al. 1998). Each sweep is segmented into 2-D components at a ladder of
+// reflectivity thresholds, keeping the highest-threshold cores; components on
+// adjacent sweeps are stacked into 3-D cells; cells are matched between
+// volumes against their forecast positions.
+
+#[derive(Debug, Clone)]
+pub struct CellOptions {
+    pub thresholds: Vec<f32>, // dBZ
+    pub min_area: f32,        // km^2
+    // Horizontal search radii (metres) tried in turn when stacking components.
+    pub vertical_search_radii: Vec<f32>,
+    pub min_components: usize,
+}
+
+impl Default for CellOptions {
+    fn default() -> Self {
+        CellOptions {
+            thresholds: vec![30.0, 35.0, 40.0, 45.0, 50.0, 55.0, 60.0],
+            min_area: 10.0,
+            vertical_search_radii: vec![5_000.0, 7_500.0, 10_000.0],
+            min_components: 2,
+        }
+    }
+}
+
+// A 2-D reflectivity feature on one sweep. Positions are metres east (x) and
+// north (y) of the radar, heights metres above sea level.
+#[derive(Debug, Clone)]
+pub struct CellComponent {
+    pub elevation: f32,
+    pub threshold: f32,
+    pub x: f32,
+    pub y: f32,
+    pub height: f32,
+    pub area: f32, // km^2
+    pub max_reflectivity: f32,
+    pub mass: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct StormCell {
+    pub time: f64,
+    pub x: f32,
+    pub y: f32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub base: f32,
+    pub top: f32,
+    pub max_reflectivity: f32,
+    pub max_reflectivity_height: f32,
+    pub vil: f32, // kg/m^2
+    pub components: Vec<CellComponent>,
+}
+
+pub fn identify_cells(volume: &Volume, options: &CellOptions) -> anyhow::Result<Vec<StormCell>> {
+    let site = volume.require_site()?;
+    let site_height = site.site_height as f32;
+
+    let mut levels: Vec<(f32, f64, Vec<CellComponent>)> = volume
+        .sweeps_with(Moment::Reflectivity)
+        .filter_map(|sweep| {
+            let field = sweep.field(Moment::Reflectivity)?;
+            let components = sweep_components(&field, site_height, options);
+            Some((sweep.elevation_angle, sweep.start_time()?, components))
+        })
+        .collect();
+    levels.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // Split cuts repeat an elevation; keep the first sweep at each angle.
+    levels.dedup_by(|b, a| (a.0 - b.0).abs() < 0.2);
+
+    let time = levels.first().map_or(0.0, |l| l.1);
+    let mut used: Vec<Vec<bool>> = levels.iter().map(|l| vec![false; l.2.len()]).collect();
+    let mut cells = Vec::new();
+
+    for start in 0..levels.len() {
+        for c in 0..levels[start].2.len() {
+            if used[start][c] {
+                continue;
+            }
+            used[start][c] = true;
+            let mut stack = vec![levels[start].2[c].clone()];
+
+            for level in start + 1..levels.len() {
+                let last = stack.last().expect("stack starts non-empty");
+                let next = options.vertical_search_radii.iter().find_map(|&radius| {
+                    levels[level]
+                        .2
+                        .iter()
+                        .enumerate()
+                        .filter(|(i, _)| !used[level][*i])
+                        .map(|(i, other)| (i, (other.x - last.x).hypot(other.y - last.y)))
+                        .filter(|(_, d)| *d <= radius)
+                        .min_by(|a, b| a.1.total_cmp(&b.1))
+                        .map(|(i, _)| i)
+                });
+                let Some(i) = next else { break };
+                used[level][i] = true;
+                stack.push(levels[level].2[i].clone());
+            }
+
+            if stack.len() >= options.min_components {
+                cells.push(build_cell(
+                    stack,
+                    time,
+                    site.latitude as f64,
+                    site.longitude as f64,
+                ));
+            }
+        }
+    }
+
+    Ok(cells)
+}
+
+fn build_cell(components: Vec<CellComponent>, time: f64, lat: f64, lon: f64) -> StormCell {
+    let total_mass: f32 = components.iter().map(|c| c.mass).sum();
+    let x = components.iter().map(|c| c.x * c.mass).sum::<f32>() / total_mass;
+    let y = components.iter().map(|c| c.y * c.mass).sum::<f32>() / total_mass;
+    let (latitude, longitude) = destination(
+        lat,
+        lon,
+        (x as f64).atan2(y as f64).to_degrees(),
+        (x as f64).hypot(y as f64),
+    );
+    let strongest = components
+        .iter()
+        .max_by(|a, b| a.max_reflectivity.total_cmp(&b.max_reflectivity))
+        .expect("cells have at least one component");
+
+    StormCell {
+        time,
+        x,
+        y,
+        latitude,
+        longitude,
+        base: components
+            .iter()
+            .map(|c| c.height)
+            .fold(f32::INFINITY, f32::min),
+        top: components
+            .iter()
+            .map(|c| c.height)
+            .fold(f32::NEG_INFINITY, f32::max),
+        max_reflectivity: strongest.max_reflectivity,
+        max_reflectivity_height: strongest.height,
+        vil: cell_vil(&components),
+        components,
+    }
+}
+
+// Cell-based VIL from the component maxima, with reflectivity capped at 56 dBZ
+// to limit hail contamination.
+pub fn cell_vil(components: &[CellComponent]) -> f32 {
+    let mut sorted: Vec<&CellComponent> = components.iter().collect();
+    sorted.sort_by(|a, b| a.height.total_cmp(&b.height));
+    sorted
+        .windows(2)
+        .map(|w| {
+            let z0 = db_to_linear(w[0].max_reflectivity.min(56.0));
+            let z1 = db_to_linear(w[1].max_reflectivity.min(56.0));
+            3.44e-6 * ((z0 + z1) / 2.0).powf(4.0 / 7.0) * (w[1].height - w[0].height)
+        })
+        .sum()
+}
+
+// Components of one sweep: connected regions above each threshold, processed
+// from the highest threshold down so a lower region is only kept when it
+// does not contain a stronger core.
+pub fn sweep_components(
+    field: &PolarField,
+    site_height: f32,
+    options: &CellOptions,
+) -> Vec<CellComponent> {
+    let n_radials = field.n_radials();
+    let n_gates = field.n_gates();
+    if n_radials == 0 || n_gates == 0 {
+        return Vec::new();
+    }
+    let azimuth_step = (360.0 / n_radials as f32).to_radians();
+    let elevation = field.elevation as f64;
+
+    let mut thresholds = options.thresholds.clone();
+    thresholds.sort_by(|a, b| b.total_cmp(a));
+    let mut claimed: HashSet<(usize, usize)> = HashSet::new();
+    let mut components = Vec::new();
+
+    for threshold in thresholds {
+        let mut visited = vec![vec![false; n_gates]; n_radials];
+        for r0 in 0..n_radials {
+            for g0 in 0..n_gates {
+                let z = field.data[r0][g0];
+                if visited[r0][g0] || z.is_nan() || z < threshold {
+                    continue;
+                }
+                let mut gates = Vec::new();
+                let mut queue = VecDeque::from([(r0, g0)]);
+                visited[r0][g0] = true;
+                while let Some((r, g)) = queue.pop_front() {
+                    gates.push((r, g));
+                    let mut next = vec![
+                        ((r + 1) % n_radials, g),
+                        ((r + n_radials - 1) % n_radials, g),
+                    ];
+                    if g + 1 < n_gates {
+                        next.push((r, g + 1));
+                    }
+                    if g > 0 {
+                        next.push((r, g - 1));
+                    }
+                    for (nr, ng) in next {
+                        if !visited[nr][ng] && field.data[nr][ng] >= threshold {
+                            visited[nr][ng] = true;
+                            queue.push_back((nr, ng));
+                        }
+                    }
+                }
+
+                // Regions around a retained core keep claiming gates so the
+                // next threshold down also defers to the core.
+                if gates.iter().any(|gate| claimed.contains(gate)) {
+                    claimed.extend(gates.iter().copied());
+                    continue;
+                }
+
+                let mut area = 0.0_f32;
+                let (mut mass, mut mx, mut my, mut mh) = (0.0_f32, 0.0_f32, 0.0_f32, 0.0_f32);
+                let mut max_reflectivity = f32::NEG_INFINITY;
+                for &(r, g) in gates.iter() {
+                    let range = field.gate_range(g) as f64;
+                    let ground = ground_range(range, elevation) as f32;
+                    let azimuth = field.azimuths[r].to_radians();
+                    let z = field.data[r][g];
+                    let weight = db_to_linear(z).powf(4.0 / 7.0);
+                    area += ground * azimuth_step * field.gate_spacing / 1e6;
+                    mass += weight;
+                    mx += weight * ground * azimuth.sin();
+                    my += weight * ground * azimuth.cos();
+                    mh += weight * (site_height + beam_height(range, elevation) as f32);
+                    max_reflectivity = max_reflectivity.max(z);
+                }
+                if area < options.min_area || mass <= 0.0 {
+                    continue;
+                }
+                claimed.extend(gates.iter().copied());
+                components.push(CellComponent {
+                    elevation: field.elevation,
+                    threshold,
+                    x: mx / mass,
+                    y: my / mass,
+                    height: mh / mass,
+                    area,
+                    max_reflectivity,
+                    mass,
+                });
+            }
+        }
+    }
+
+    components
+}
+
+#[derive(Debug, Clone)]
+pub struct ForecastPosition {
+    pub minutes: u32,
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackedCell {
+    pub id: u32,
+    pub cell: StormCell,
+    // Motion in m/s towards east (u) and north (v), once seen twice.
+    pub motion: Option<(f32, f32)>,
+    pub forecast: Vec<ForecastPosition>,
+    // (time, x, y) of every past position, oldest first.
+    pub history: Vec<(f64, f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerOptions {
+    pub max_speed: f32, // m/s
+    // Cells unmatched for longer than this (seconds) are dropped.
+    pub max_gap: f64,
+    pub forecast_minutes: Vec<u32>,
+    // Weight of the newest displacement when updating the motion vector.
+    pub motion_smoothing: f32,
+}
+
+impl Default for TrackerOptions {
+    fn default() -> Self {
+        TrackerOptions {
+            max_speed: 35.0,
+            max_gap: 1200.0,
+            forecast_minutes: vec![15, 30, 45, 60],
+            motion_smoothing: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CellTracker {
+    pub options: TrackerOptions,
+    pub tracks: Vec<TrackedCell>,
+    next_id: u32,
+}
+
+impl CellTracker {
+    pub fn new(options: TrackerOptions) -> CellTracker {
+        CellTracker {
+            options,
+            tracks: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    // Matches the cells of a volume scanned at `time` against the current
+    // tracks, nearest forecast position first, and returns the tracks seen in
+    // this volume. Tracks unmatched for longer than max_gap are dropped even
+    // when the volume has no cells.
+    pub fn update(&mut self, time: f64, cells: Vec<StormCell>) -> Vec<&TrackedCell> {
+        self.tracks
+            .retain(|t| time - t.cell.time <= self.options.max_gap);
+
+        // The elapsed time of each candidate pair is carried through to the
+        // motion update so it is always the one the gate was checked with.
+        let mut pairs: Vec<(f32, usize, usize, f32)> = Vec::new();
+        for (ti, track) in self.tracks.iter().enumerate() {
+            let dt = (time - track.cell.time) as f32;
+            if dt <= 0.0 {
+                continue;
+            }
+            let (fx, fy) = match track.motion {
+                Some((u, v)) => (track.cell.x + u * dt, track.cell.y + v * dt),
+                None => (track.cell.x, track.cell.y),
+            };
+            let radius = self.options.max_speed * dt;
+            for (ci, cell) in cells.iter().enumerate() {
+                let distance = (cell.x - fx).hypot(cell.y - fy);
+                if distance <= radius {
+                    pairs.push((distance, ti, ci, dt));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_taken = vec![false; self.tracks.len()];
+        let mut cell_track: Vec<Option<(usize, f32)>> = vec![None; cells.len()];
+        for (_, ti, ci, dt) in pairs {
+            if track_taken[ti] || cell_track[ci].is_some() {
+                continue;
+            }
+            track_taken[ti] = true;
+            cell_track[ci] = Some((ti, dt));
+        }
+
+        let mut seen = Vec::new();
+        for (ci, cell) in cells.into_iter().enumerate() {
+            match cell_track[ci] {
+                Some((ti, dt)) => {
+                    let track = &mut self.tracks[ti];
+                    let observed = ((cell.x - track.cell.x) / dt, (cell.y - track.cell.y) / dt);
+                    let w = self.options.motion_smoothing;
+                    track.motion = Some(match track.motion {
+                        Some((u, v)) => (
+                            w * observed.0 + (1.0 - w) * u,
+                            w * observed.1 + (1.0 - w) * v,
+                        ),
+                        None => observed,
+                    });
+                    track.history.push((cell.time, cell.x, cell.y));
+                    track.cell = cell;
+                    seen.push(ti);
+                }
+                None => {
+                    self.tracks.push(TrackedCell {
+                        id: self.next_id,
+                        history: vec![(cell.time, cell.x, cell.y)],
+                        cell,
+                        motion: None,
+                        forecast: Vec::new(),
+                    });
+                    self.next_id += 1;
+                    seen.push(self.tracks.len() - 1);
+                }
+            }
+        }
+
+        for &ti in seen.iter() {
+            let track = &mut self.tracks[ti];
+            track.forecast = match track.motion {
+                Some((u, v)) => self
+                    .options
+                    .forecast_minutes
+                    .iter()
+                    .map(|&minutes| ForecastPosition {
+                        minutes,
+                        x: track.cell.x + u * minutes as f32 * 60.0,
+                        y: track.cell.y + v * minutes as f32 * 60.0,
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+        }
+
+        seen.iter().map(|&ti| &self.tracks[ti]).collect()
+    }
+}
+
+// Runs identification and tracking over a sequence of volumes in time order.
+pub fn track_volumes(
+    volumes: &[Volume],
+    cell_options: &CellOptions,
+    tracker_options: &TrackerOptions,
+) -> anyhow::Result<CellTracker> {
+    let mut ordered: Vec<&Volume> = volumes.iter().collect();
+    ordered.sort_by(|a, b| {
+        a.start_time()
+            .unwrap_or_default()
+            .total_cmp(&b.start_time().unwrap_or_default())
+    });
+    let mut tracker = CellTracker::new(tracker_options.clone());
+    for volume in ordered {
+        let Some(time) = volume.start_time() else {
+            continue;
+        };
+        let cells = identify_cells(volume, cell_options)?;
+        tracker.update(time, cells);
+    }
+    Ok(tracker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(time: f64, x: f32) -> StormCell {
+        StormCell {
+            time,
+            x,
+            y: 0.0,
+            latitude: 35.0,
+            longitude: -97.0,
+            base: 1_000.0,
+            top: 10_000.0,
+            max_reflectivity: 55.0,
+            max_reflectivity_height: 4_000.0,
+            vil: 30.0,
+            components: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn quiet_volumes_age_out_tracks() {
+        let mut tracker = CellTracker::new(TrackerOptions::default());
+        tracker.update(0.0, vec![cell(0.0, 0.0)]);
+        tracker.update(300.0, vec![cell(300.0, 3_000.0)]);
+        assert_eq!(tracker.tracks.len(), 1);
+        assert!(tracker.tracks[0].motion.is_some());
+
+        tracker.update(900.0, Vec::new());
+        assert_eq!(tracker.tracks.len(), 1);
+        tracker.update(1_600.0, Vec::new());
+        assert!(tracker.tracks.is_empty());
+
+        // A cell reappearing after the gap starts a new track.
+        let seen = tracker.update(1_900.0, vec![cell(1_900.0, 19_000.0)]);
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].id, 2);
+        assert!(seen[0].motion.is_none());
+    }
+
+    #[test]
+    fn motion_uses_the_gated_time_step() {
+        let mut tracker = CellTracker::new(TrackerOptions::default());
+        tracker.update(0.0, vec![cell(0.0, 0.0)]);
+        // A cell stamped with the previous time is still matched by the volume
+        // time, and its motion must not divide by a zero time step.
+        let seen = tracker.update(300.0, vec![cell(0.0, 3_000.0)]);
+        let (u, v) = seen[0].motion.unwrap();
+        assert!((u - 10.0).abs() < 1e-4 && v == 0.0, "{:?}", (u, v));
+        assert!(seen[0]
+            .forecast
+            .iter()
+            .all(|f| f.x.is_finite() && f.y.is_finite()));
+
+        // A repeated volume time never pairs with the existing track.
+        let seen = tracker.update(0.0, vec![cell(0.0, 3_000.0)]);
+        assert!(seen[0].motion.is_none());
+    }
+}
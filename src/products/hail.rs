@@ -0,0 +1,211 @@
+use crate::geometry::{beam_height, ground_range, slant_range};
+use crate::products::cells::StormCell;
+use crate::reader::sounding::Sounding;
+use crate::volume::{Moment, PolarField, Volume};
+
+// Hail detection after Witt et al. (1998). POH comes from the height of the
+// 45 dBZ echo above the freezing level; the severe hail index (SHI) is a
+// temperature-weighted vertical integral of hail kinetic energy flux, from
+// which POSH and MESH follow.
+
+// Environmental levels in metres above sea level.
+#[derive(Debug, Clone, Copy)]
+pub struct FreezingLevels {
+    pub freezing: f32,
+    pub minus_20: f32,
+}
+
+impl FreezingLevels {
+    pub fn from_sounding(sounding: &Sounding) -> anyhow::Result<FreezingLevels> {
+        let freezing = sounding
+            .height_of_temperature(0.0)
+            .ok_or_else(|| anyhow::anyhow!("Sounding never reaches 0 C"))?;
+        let minus_20 = sounding
+            .height_of_temperature(-20.0)
+            .ok_or_else(|| anyhow::anyhow!("Sounding never reaches -20 C"))?;
+        Ok(FreezingLevels { freezing, minus_20 })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HailEstimate {
+    pub shi: f32,  // J m^-1 s^-1
+    pub poh: f32,  // percent
+    pub posh: f32, // percent
+    pub mesh: f32, // mm
+}
+
+// Hail estimate from a reflectivity profile of (height above sea level, dBZ)
+// samples. NaN reflectivities count as no echo.
+pub fn hail_estimate(
+    profile: &[(f32, f32)],
+    levels: FreezingLevels,
+    site_height: f32,
+) -> HailEstimate {
+    let mut samples: Vec<(f32, f32)> = profile.to_vec();
+    samples.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let integrand: Vec<(f32, f32)> = samples
+        .iter()
+        .map(|&(h, z)| (h, temperature_weight(h, levels) * hail_kinetic_energy(z)))
+        .collect();
+    let shi = 0.1
+        * integrand
+            .windows(2)
+            .map(|w| 0.5 * (w[0].1 + w[1].1) * (w[1].0 - w[0].0))
+            .sum::<f32>();
+
+    let h45 = samples
+        .iter()
+        .filter(|(_, z)| *z >= 45.0)
+        .map(|(h, _)| *h)
+        .fold(f32::NEG_INFINITY, f32::max);
+
+    HailEstimate {
+        shi,
+        poh: probability_of_hail(h45 - levels.freezing),
+        posh: probability_of_severe_hail(shi, levels.freezing - site_height),
+        mesh: maximum_expected_size(shi),
+    }
+}
+
+// Uses the component maxima of a storm cell as the reflectivity profile.
+pub fn cell_hail(cell: &StormCell, levels: FreezingLevels, site_height: f32) -> HailEstimate {
+    let profile: Vec<(f32, f32)> = cell
+        .components
+        .iter()
+        .map(|c| (c.height, c.max_reflectivity))
+        .collect();
+    hail_estimate(&profile, levels, site_height)
+}
+
+// Hail kinetic energy flux (J m^-2 s^-1), ramped in between 40 and 50 dBZ.
+pub fn hail_kinetic_energy(z: f32) -> f32 {
+    if z.is_nan() || z <= 40.0 {
+        return 0.0;
+    }
+    let weight = ((z - 40.0) / 10.0).min(1.0);
+    5e-6 * 10_f32.powf(0.084 * z) * weight
+}
+
+fn temperature_weight(height: f32, levels: FreezingLevels) -> f32 {
+    if height <= levels.freezing {
+        0.0
+    } else if height >= levels.minus_20 {
+        1.0
+    } else {
+        (height - levels.freezing) / (levels.minus_20 - levels.freezing)
+    }
+}
+
+// POH in 10% steps of the 45 dBZ echo top above the freezing level (metres).
+pub fn probability_of_hail(height_above_freezing: f32) -> f32 {
+    const STEPS_KM: [f32; 10] = [
+        1.625, 1.875, 2.125, 2.375, 2.625, 2.925, 3.3, 3.75, 4.5, 5.5,
+    ];
+    let km = height_above_freezing / 1000.0;
+    if !km.is_finite() {
+        return 0.0;
+    }
+    STEPS_KM.iter().filter(|&&s| km >= s).count() as f32 * 10.0
+}
+
+// POSH against the warning threshold WT = 57.5 H0 - 121, with H0 the freezing
+// level in km above the radar and WT floored at 20.
+pub fn probability_of_severe_hail(shi: f32, freezing_above_radar: f32) -> f32 {
+    if shi <= 0.0 {
+        return 0.0;
+    }
+    let threshold = (57.5 * freezing_above_radar / 1000.0 - 121.0).max(20.0);
+    (29.0 * (shi / threshold).ln() + 50.0).clamp(0.0, 100.0)
+}
+
+pub fn maximum_expected_size(shi: f32) -> f32 {
+    2.54 * shi.max(0.0).sqrt()
+}
+
+// Per-column products on the geometry of the lowest reflectivity sweep.
+#[derive(Debug, Clone)]
+pub struct HailColumns {
+    pub time: f64,
+    pub shi: PolarField,
+    pub poh: PolarField,
+    pub posh: PolarField,
+    pub mesh: PolarField,
+}
+
+// Builds a column above every gate of the lowest sweep from the gates at the
+// same ground range and nearest azimuth on each higher sweep.
+pub fn hail_columns(volume: &Volume, levels: FreezingLevels) -> anyhow::Result<HailColumns> {
+    let site_height = volume.site().map_or(0.0, |s| s.site_height as f32);
+    let mut fields: Vec<PolarField> = volume
+        .sweeps_with(Moment::Reflectivity)
+        .filter_map(|s| s.field(Moment::Reflectivity))
+        .collect();
+    fields.sort_by(|a, b| a.elevation.total_cmp(&b.elevation));
+    fields.dedup_by(|b, a| (a.elevation - b.elevation).abs() < 0.2);
+    let base = fields
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("Volume has no reflectivity sweeps"))?
+        .clone();
+
+    let mut shi = base.filled_like(f32::NAN);
+    let mut poh = base.filled_like(f32::NAN);
+    let mut posh = base.filled_like(f32::NAN);
+    let mut mesh = base.filled_like(f32::NAN);
+
+    for (r, &azimuth) in base.azimuths.iter().enumerate() {
+        let radials: Vec<Option<usize>> = fields
+            .iter()
+            .map(|f| f.radial_at_azimuth(azimuth))
+            .collect();
+        for g in 0..base.n_gates() {
+            let ground = ground_range(base.gate_range(g) as f64, base.elevation as f64);
+            let profile: Vec<(f32, f32)> = fields
+                .iter()
+                .zip(radials.iter())
+                .filter_map(|(field, radial)| {
+                    let elevation = field.elevation as f64;
+                    let range = slant_range(ground, elevation);
+                    let gate = field.gate_at_range(range as f32)?;
+                    let height = site_height + beam_height(range, elevation) as f32;
+                    Some((height, field.get((*radial)?, gate)))
+                })
+                .collect();
+            if profile.iter().all(|(_, z)| z.is_nan()) {
+                continue;
+            }
+            let estimate = hail_estimate(&profile, levels, site_height);
+            shi.data[r][g] = estimate.shi;
+            poh.data[r][g] = estimate.poh;
+            posh.data[r][g] = estimate.posh;
+            mesh.data[r][g] = estimate.mesh;
+        }
+    }
+
+    Ok(HailColumns {
+        time: volume.start_time().unwrap_or_default(),
+        shi,
+        poh,
+        posh,
+        mesh,
+    })
+}
+
+// Maximum MESH over a sequence of volumes from one site, on the geometry of
+// the first.
+pub fn mesh_swath(columns: &[HailColumns]) -> Option<PolarField> {
+    let (first, rest) = columns.split_first()?;
+    let mut swath = first.mesh.clone();
+    for c in rest {
+        let aligned = c.mesh.aligned_to(&swath);
+        for (row, other) in swath.data.iter_mut().zip(aligned.data.iter()) {
+            for (value, &v) in row.iter_mut().zip(other.iter()) {
+                if value.is_nan() || v > *value {
+                    *value = v;
+                }
+            }
+        }
+    }
+    Some(swath)
+}
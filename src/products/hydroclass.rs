@@ -0,0 +1,466 @@
+use crate::geometry::beam_height;
+use crate::products::kdp::{kdp_from_fields, KdpOptions};
+use crate::products::trapezoid;
+use crate::volume::{Moment, PolarField, Sweep};
+
+// Fuzzy-logic hydrometeor classification after Park et al. (2009). Each class
+// has a trapezoidal membership function per input; the weighted mean of the
+// memberships is the class score and the best score wins, among the classes
+// the gate's position relative to the melting layer allows.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HydrometeorClass {
+    Biological,
+    GroundClutter,
+    IceCrystals,
+    DrySnow,
+    WetSnow,
+    LightModerateRain,
+    HeavyRain,
+    BigDrops,
+    Graupel,
+    Hail,
+}
+
+impl HydrometeorClass {
+    pub const ALL: [HydrometeorClass; 10] = [
+        HydrometeorClass::Biological,
+        HydrometeorClass::GroundClutter,
+        HydrometeorClass::IceCrystals,
+        HydrometeorClass::DrySnow,
+        HydrometeorClass::WetSnow,
+        HydrometeorClass::LightModerateRain,
+        HydrometeorClass::HeavyRain,
+        HydrometeorClass::BigDrops,
+        HydrometeorClass::Graupel,
+        HydrometeorClass::Hail,
+    ];
+
+    // Stable numeric code for exports, starting at 1 so 0 can mean unclassified.
+    pub fn code(&self) -> u8 {
+        *self as u8 + 1
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HydrometeorClass::Biological => "biological",
+            HydrometeorClass::GroundClutter => "ground clutter",
+            HydrometeorClass::IceCrystals => "ice crystals",
+            HydrometeorClass::DrySnow => "dry snow",
+            HydrometeorClass::WetSnow => "wet snow",
+            HydrometeorClass::LightModerateRain => "light/moderate rain",
+            HydrometeorClass::HeavyRain => "heavy rain",
+            HydrometeorClass::BigDrops => "big drops",
+            HydrometeorClass::Graupel => "graupel",
+            HydrometeorClass::Hail => "hail",
+        }
+    }
+}
+
+// Melting layer bottom and top in metres above sea level.
+#[derive(Debug, Clone, Copy)]
+pub struct MeltingLayer {
+    pub bottom: f32,
+    pub top: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LayerPosition {
+    Below,
+    Within,
+    Above,
+}
+
+#[derive(Debug, Clone)]
+pub struct HydroclassOptions {
+    pub beamwidth: f32, // degrees
+    // Window lengths for the texture fields, metres.
+    pub reflectivity_texture_window: f32,
+    pub phase_texture_window: f32,
+    pub min_score: f32,
+    pub kdp: KdpOptions,
+}
+
+impl Default for HydroclassOptions {
+    fn default() -> Self {
+        HydroclassOptions {
+            beamwidth: 0.95,
+            reflectivity_texture_window: 1_000.0,
+            phase_texture_window: 2_000.0,
+            min_score: 0.3,
+            kdp: KdpOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HydrometeorClassification {
+    pub classes: Vec<Vec<Option<HydrometeorClass>>>,
+    // Aggregation score of the winning class, on the reflectivity geometry.
+    pub score: PolarField,
+}
+
+// Inputs at one gate; NaN marks a missing value, which is left out of the
+// aggregation.
+#[derive(Debug, Clone, Copy)]
+struct GateInputs {
+    z: f32,
+    zdr: f32,
+    rho: f32,
+    log_kdp: f32,
+    sd_z: f32,
+    sd_phi: f32,
+}
+
+pub fn classify_sweep(
+    sweep: &Sweep,
+    site_height: f32,
+    melting_layer: MeltingLayer,
+    options: &HydroclassOptions,
+) -> anyhow::Result<HydrometeorClassification> {
+    let z = sweep
+        .field(Moment::Reflectivity)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no reflectivity", sweep.elevation_number))?;
+    let rho = sweep
+        .field(Moment::CorrelationCoefficient)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no RHO", sweep.elevation_number))?
+        .aligned_to(&z);
+    let zdr = sweep
+        .field(Moment::DifferentialReflectivity)
+        .map(|f| f.aligned_to(&z));
+    let phi = sweep.field(Moment::DifferentialPhase);
+    let kdp = phi.as_ref().map(|phi| {
+        let result = kdp_from_fields(
+            phi,
+            Some(&rho.aligned_to(phi)),
+            Some(&z.aligned_to(phi)),
+            &options.kdp,
+        );
+        (result.kdp.aligned_to(&z), result.phidp.aligned_to(&z))
+    });
+
+    let sd_z = texture(&z, options.reflectivity_texture_window);
+    let sd_phi = kdp
+        .as_ref()
+        .map(|(_, phidp)| texture(phidp, options.phase_texture_window));
+
+    let mut classes = vec![vec![None; z.n_gates()]; z.n_radials()];
+    let mut score = z.filled_like(f32::NAN);
+
+    let rows = classes.iter_mut().zip(score.data.iter_mut()).enumerate();
+    for (r, (class_row, score_row)) in rows {
+        let gates = class_row.iter_mut().zip(score_row.iter_mut()).enumerate();
+        for (g, (class_slot, score_slot)) in gates {
+            let inputs = GateInputs {
+                z: z.get(r, g),
+                zdr: zdr.as_ref().map_or(f32::NAN, |f| f.get(r, g)),
+                rho: rho.get(r, g),
+                log_kdp: kdp
+                    .as_ref()
+                    .map_or(f32::NAN, |(k, _)| 10.0 * k.get(r, g).max(1e-3).log10()),
+                sd_z: sd_z.get(r, g),
+                sd_phi: sd_phi.as_ref().map_or(f32::NAN, |f| f.get(r, g)),
+            };
+            if !(inputs.z.is_finite() && inputs.rho.is_finite()) {
+                continue;
+            }
+
+            let range = z.gate_range(g) as f64;
+            let half_beam = options.beamwidth as f64 / 2.0;
+            let elevation = z.elevation as f64;
+            let bottom = site_height + beam_height(range, elevation - half_beam) as f32;
+            let top = site_height + beam_height(range, elevation + half_beam) as f32;
+            let position = if bottom > melting_layer.top {
+                LayerPosition::Above
+            } else if top < melting_layer.bottom {
+                LayerPosition::Below
+            } else {
+                LayerPosition::Within
+            };
+
+            if let Some((class, value)) = best_class(&inputs, position) {
+                if value >= options.min_score {
+                    *class_slot = Some(class);
+                    *score_slot = value;
+                }
+            }
+        }
+    }
+
+    Ok(HydrometeorClassification { classes, score })
+}
+
+// The highest scoring class among those allowed at `position`.
+fn best_class(inputs: &GateInputs, position: LayerPosition) -> Option<(HydrometeorClass, f32)> {
+    HydrometeorClass::ALL
+        .iter()
+        .filter(|c| allowed(**c, position))
+        .map(|c| (*c, aggregate(*c, inputs)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn allowed(class: HydrometeorClass, position: LayerPosition) -> bool {
+    use HydrometeorClass::*;
+    match position {
+        LayerPosition::Below => matches!(
+            class,
+            Biological | GroundClutter | LightModerateRain | HeavyRain | BigDrops | Hail
+        ),
+        LayerPosition::Within => matches!(
+            class,
+            Biological
+                | GroundClutter
+                | WetSnow
+                | Graupel
+                | LightModerateRain
+                | HeavyRain
+                | BigDrops
+                | Hail
+        ),
+        LayerPosition::Above => matches!(class, IceCrystals | DrySnow | Graupel | Hail),
+    }
+}
+
+// Reflectivity-dependent bounds from Park et al. (2009).
+fn f1(z: f32) -> f32 {
+    -0.50 + 2.50e-3 * z + 7.50e-4 * z * z
+}
+
+fn f2(z: f32) -> f32 {
+    0.68 - 4.81e-2 * z + 2.92e-3 * z * z
+}
+
+fn f3(z: f32) -> f32 {
+    1.42 + 6.67e-2 * z + 4.85e-4 * z * z
+}
+
+fn g1(z: f32) -> f32 {
+    -44.0 + 0.8 * z
+}
+
+fn g2(z: f32) -> f32 {
+    -22.0 + 0.5 * z
+}
+
+type Trapezoid = [f32; 4];
+
+// Membership parameters for (Z, ZDR, RHO, LKdp, SD(Z), SD(PHIDP)) and their
+// weights, from Park et al. (2009) tables 2 and 3.
+fn parameters(class: HydrometeorClass, z: f32) -> ([Trapezoid; 6], [f32; 6]) {
+    use HydrometeorClass::*;
+    const PRECIP_TEXTURE: [Trapezoid; 2] = [[0.0, 0.5, 3.0, 6.0], [0.0, 1.0, 15.0, 30.0]];
+    let [tz, tphi] = PRECIP_TEXTURE;
+    let any_kdp = [-30.0, -25.0, 10.0, 20.0];
+    let rain_kdp = [g1(z) - 1.0, g1(z), g2(z), g2(z) + 1.0];
+
+    match class {
+        GroundClutter => (
+            [
+                [15.0, 20.0, 70.0, 80.0],
+                [-4.0, -2.0, 1.0, 2.0],
+                [0.5, 0.6, 0.9, 0.95],
+                any_kdp,
+                [2.0, 4.0, 35.0, 50.0],
+                [30.0, 40.0, 50.0, 60.0],
+            ],
+            [0.2, 0.4, 1.0, 0.0, 0.6, 0.8],
+        ),
+        Biological => (
+            [
+                [5.0, 10.0, 20.0, 30.0],
+                [0.0, 2.0, 10.0, 12.0],
+                [0.3, 0.5, 0.8, 0.83],
+                [-30.0, -25.0, 10.0, 10.0],
+                [1.0, 2.0, 4.0, 7.0],
+                [8.0, 10.0, 40.0, 60.0],
+            ],
+            [0.4, 0.6, 1.0, 0.0, 0.8, 0.8],
+        ),
+        DrySnow => (
+            [
+                [5.0, 10.0, 35.0, 40.0],
+                [-0.3, 0.0, 0.3, 0.6],
+                [0.95, 0.98, 1.0, 1.01],
+                any_kdp,
+                tz,
+                tphi,
+            ],
+            [1.0, 0.8, 0.6, 0.0, 0.2, 0.2],
+        ),
+        WetSnow => (
+            [
+                [25.0, 30.0, 40.0, 50.0],
+                [0.5, 1.0, 2.0, 3.0],
+                [0.88, 0.92, 0.95, 0.985],
+                any_kdp,
+                tz,
+                tphi,
+            ],
+            [0.6, 0.8, 1.0, 0.0, 0.2, 0.2],
+        ),
+        IceCrystals => (
+            [
+                [0.0, 5.0, 20.0, 25.0],
+                [0.1, 0.4, 3.0, 3.3],
+                [0.95, 0.98, 1.0, 1.01],
+                [-5.0, 0.0, 10.0, 15.0],
+                tz,
+                tphi,
+            ],
+            [1.0, 0.6, 0.4, 0.5, 0.2, 0.2],
+        ),
+        Graupel => (
+            [
+                [25.0, 35.0, 50.0, 55.0],
+                [-0.3, 0.0, f1(z), f1(z) + 0.3],
+                [0.9, 0.97, 1.0, 1.01],
+                any_kdp,
+                tz,
+                tphi,
+            ],
+            [0.8, 1.0, 0.4, 0.0, 0.2, 0.2],
+        ),
+        BigDrops => (
+            [
+                [20.0, 25.0, 45.0, 50.0],
+                [f2(z) - 0.3, f2(z), f3(z), f3(z) + 1.0],
+                [0.92, 0.95, 1.0, 1.01],
+                rain_kdp,
+                tz,
+                tphi,
+            ],
+            [0.8, 1.0, 0.6, 0.0, 0.2, 0.2],
+        ),
+        LightModerateRain => (
+            [
+                [5.0, 10.0, 45.0, 50.0],
+                [f1(z) - 0.3, f1(z), f2(z), f2(z) + 0.5],
+                [0.95, 0.97, 1.0, 1.01],
+                rain_kdp,
+                tz,
+                tphi,
+            ],
+            [1.0, 0.8, 0.6, 0.0, 0.2, 0.2],
+        ),
+        HeavyRain => (
+            [
+                [40.0, 45.0, 55.0, 60.0],
+                [f1(z) - 0.3, f1(z), f2(z), f2(z) + 0.5],
+                [0.92, 0.95, 1.0, 1.01],
+                rain_kdp,
+                tz,
+                tphi,
+            ],
+            [1.0, 0.8, 0.6, 1.0, 0.2, 0.2],
+        ),
+        Hail => (
+            [
+                [45.0, 50.0, 75.0, 80.0],
+                [-0.3, 0.0, f1(z), f1(z) + 0.5],
+                [0.75, 0.8, 1.0, 1.01],
+                [-10.0, -4.0, g1(z), g1(z) + 1.0],
+                tz,
+                tphi,
+            ],
+            [1.0, 0.8, 0.6, 1.0, 0.2, 0.2],
+        ),
+    }
+}
+
+fn aggregate(class: HydrometeorClass, inputs: &GateInputs) -> f32 {
+    let (memberships, weights) = parameters(class, inputs.z);
+    let values = [
+        inputs.z,
+        inputs.zdr,
+        inputs.rho,
+        inputs.log_kdp,
+        inputs.sd_z,
+        inputs.sd_phi,
+    ];
+
+    let mut sum = 0.0;
+    let mut weight_sum = 0.0;
+    for ((value, [x1, x2, x3, x4]), weight) in values.iter().zip(memberships).zip(weights) {
+        if !value.is_finite() || weight == 0.0 {
+            continue;
+        }
+        sum += weight * trapezoid(*value, x1, x2, x3, x4);
+        weight_sum += weight;
+    }
+    if weight_sum == 0.0 {
+        return 0.0;
+    }
+    sum / weight_sum
+}
+
+// Standard deviation along each radial over a running window of `window` metres.
+pub fn texture(field: &PolarField, window: f32) -> PolarField {
+    let half = ((window / 2.0) / field.gate_spacing).round().max(1.0) as usize;
+    let mut out = field.filled_like(f32::NAN);
+    for (r, radial) in field.data.iter().enumerate() {
+        for g in 0..radial.len() {
+            if !radial[g].is_finite() {
+                continue;
+            }
+            let lo = g.saturating_sub(half);
+            let hi = (g + half).min(radial.len() - 1);
+            let values: Vec<f32> = radial[lo..=hi]
+                .iter()
+                .copied()
+                .filter(|v| v.is_finite())
+                .collect();
+            if values.len() < 3 {
+                continue;
+            }
+            let n = values.len() as f32;
+            let mean = values.iter().sum::<f32>() / n;
+            let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+            out.data[r][g] = variance.sqrt();
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typical_gates_pick_their_class() {
+        use HydrometeorClass::*;
+        use LayerPosition::*;
+        // Z (dBZ), ZDR (dB), RHO, LKdp (10 log10 KDP), SD(Z) (dB) and
+        // SD(PHIDP) (deg) in the middle of each class's ranges in Park et
+        // al. (2009).
+        let cases = [
+            (
+                GroundClutter,
+                Below,
+                [50.0, -1.0, 0.7, f32::NAN, 10.0, 45.0],
+            ),
+            (Biological, Below, [15.0, 5.0, 0.6, f32::NAN, 3.0, 25.0]),
+            (IceCrystals, Above, [12.0, 2.0, 0.99, 5.0, 1.0, 5.0]),
+            (DrySnow, Above, [25.0, 0.2, 0.99, -10.0, 1.0, 5.0]),
+            (WetSnow, Within, [35.0, 1.5, 0.93, f32::NAN, 1.0, 5.0]),
+            (LightModerateRain, Below, [30.0, 0.8, 0.99, -12.0, 1.0, 5.0]),
+            (HeavyRain, Below, [52.0, 2.5, 0.98, 2.0, 1.0, 5.0]),
+            (BigDrops, Below, [35.0, 3.0, 0.97, -10.0, 1.0, 5.0]),
+            (Graupel, Above, [45.0, 0.5, 0.99, f32::NAN, 1.0, 5.0]),
+            (Hail, Below, [60.0, 0.2, 0.93, 0.0, 1.0, 5.0]),
+        ];
+        for (expected, position, [z, zdr, rho, log_kdp, sd_z, sd_phi]) in cases {
+            let inputs = GateInputs {
+                z,
+                zdr,
+                rho,
+                log_kdp,
+                sd_z,
+                sd_phi,
+            };
+            let (class, score) = best_class(&inputs, position).unwrap();
+            assert_eq!(class, expected, "score {}", score);
+            assert!(score > HydroclassOptions::default().min_score);
+        }
+        assert_eq!(HydrometeorClass::ALL.len(), cases.len());
+    }
+}
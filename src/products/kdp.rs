@@ -0,0 +1,311 @@
+use crate::volume::{Moment, PolarField, Sweep};
+
+// Specific differential phase from the PHI moment, radial by radial: mask by
+// RHO and estimated SNR, drop short speckle runs, unfold the 0-360 wrap,
+// remove the system offset, smooth, and take KDP as half the range derivative
+// of the processed PHIDP. Window lengths shorten in heavy precipitation where
+// PHIDP rises quickly, following Ryzhkov and Zrnic (1996).
+
+#[derive(Debug, Clone)]
+pub struct KdpOptions {
+    pub min_rho: f32,
+    pub min_snr: f32, // dB
+    // Reflectivity a gate would have at 1 km with an SNR of 0 dB.
+    pub noise_reflectivity_1km: f32,
+    pub min_run: usize,
+    // Reflectivity above which the short window is used.
+    pub heavy_rain_reflectivity: f32,
+    pub short_window: f32, // metres
+    pub long_window: f32,
+    // Number of leading valid gates used to estimate the system phase offset.
+    pub offset_gates: usize,
+}
+
+impl Default for KdpOptions {
+    fn default() -> Self {
+        KdpOptions {
+            min_rho: 0.9,
+            min_snr: 5.0,
+            noise_reflectivity_1km: -41.5,
+            min_run: 5,
+            heavy_rain_reflectivity: 40.0,
+            short_window: 2_000.0,
+            long_window: 6_000.0,
+            offset_gates: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct KdpResult {
+    pub kdp: PolarField,   // degrees per km
+    pub phidp: PolarField, // degrees, system offset removed
+}
+
+pub fn specific_differential_phase(
+    sweep: &Sweep,
+    options: &KdpOptions,
+) -> anyhow::Result<KdpResult> {
+    let phi = sweep
+        .field(Moment::DifferentialPhase)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no PHI data", sweep.elevation_number))?;
+    let rho = sweep
+        .field(Moment::CorrelationCoefficient)
+        .map(|f| f.aligned_to(&phi));
+    let reflectivity = sweep
+        .field(Moment::Reflectivity)
+        .map(|f| f.aligned_to(&phi));
+    Ok(kdp_from_fields(
+        &phi,
+        rho.as_ref(),
+        reflectivity.as_ref(),
+        options,
+    ))
+}
+
+pub fn kdp_from_fields(
+    phi: &PolarField,
+    rho: Option<&PolarField>,
+    reflectivity: Option<&PolarField>,
+    options: &KdpOptions,
+) -> KdpResult {
+    let mut kdp = phi.filled_like(f32::NAN);
+    let mut phidp = phi.filled_like(f32::NAN);
+
+    for r in 0..phi.n_radials() {
+        let mut valid: Vec<bool> = (0..phi.n_gates())
+            .map(|g| {
+                let value = phi.get(r, g);
+                if !value.is_finite() {
+                    return false;
+                }
+                if let Some(rho) = rho {
+                    let value = rho.get(r, g);
+                    if value.is_nan() || value < options.min_rho {
+                        return false;
+                    }
+                }
+                if let Some(z) = reflectivity {
+                    let range_km = phi.gate_range(g) / 1000.0;
+                    let snr = z.get(r, g)
+                        - options.noise_reflectivity_1km
+                        - 20.0 * range_km.max(0.001).log10();
+                    if snr.is_nan() || snr < options.min_snr {
+                        return false;
+                    }
+                }
+                true
+            })
+            .collect();
+        despeckle(&mut valid, options.min_run);
+
+        let unfolded = unfold_phase(&phi.data[r], &valid);
+        let Some(offset) = system_offset(&unfolded, &valid, options.offset_gates) else {
+            continue;
+        };
+        let filled = fill_gaps(&unfolded, &valid, offset);
+
+        for g in (0..phi.n_gates()).filter(|&g| valid[g]) {
+            let heavy =
+                reflectivity.is_some_and(|z| z.get(r, g) >= options.heavy_rain_reflectivity);
+            let window = if heavy {
+                options.short_window
+            } else {
+                options.long_window
+            };
+            let half = ((window / 2.0) / phi.gate_spacing).round().max(1.0) as usize;
+            let lo = g.saturating_sub(half);
+            let hi = (g + half).min(phi.n_gates() - 1);
+
+            let (mean, slope) = linear_fit(phi, &filled, lo, hi);
+            phidp.data[r][g] = mean + slope * (phi.gate_range(g) - centre_range(phi, lo, hi));
+            // slope is degrees per metre; KDP is half the two-way slope per km.
+            kdp.data[r][g] = 0.5 * slope * 1000.0;
+        }
+    }
+
+    KdpResult { kdp, phidp }
+}
+
+// Clears runs of valid gates shorter than `min_run`.
+fn despeckle(valid: &mut [bool], min_run: usize) {
+    let mut start = None;
+    for g in 0..=valid.len() {
+        let is_valid = g < valid.len() && valid[g];
+        match (is_valid, start) {
+            (true, None) => start = Some(g),
+            (false, Some(s)) => {
+                if g - s < min_run {
+                    valid[s..g].iter_mut().for_each(|v| *v = false);
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+// Removes 360 degree wraps by keeping each gate within 180 degrees of the
+// running median of the previous valid gates.
+fn unfold_phase(raw: &[f32], valid: &[bool]) -> Vec<f32> {
+    let mut out = raw.to_vec();
+    let mut recent: Vec<f32> = Vec::new();
+    for (g, value) in out.iter_mut().enumerate() {
+        if !valid[g] {
+            continue;
+        }
+        if !recent.is_empty() {
+            let mut sorted = recent.clone();
+            sorted.sort_by(f32::total_cmp);
+            let reference = sorted[sorted.len() / 2];
+            *value += ((reference - *value) / 360.0).round() * 360.0;
+        }
+        recent.push(*value);
+        if recent.len() > 5 {
+            recent.remove(0);
+        }
+    }
+    out
+}
+
+fn system_offset(unfolded: &[f32], valid: &[bool], n: usize) -> Option<f32> {
+    let mut leading: Vec<f32> = unfolded
+        .iter()
+        .zip(valid.iter())
+        .filter(|(_, v)| **v)
+        .map(|(p, _)| *p)
+        .take(n.max(1))
+        .collect();
+    if leading.is_empty() {
+        return None;
+    }
+    leading.sort_by(f32::total_cmp);
+    Some(leading[leading.len() / 2])
+}
+
+// Offset-removed phase with gaps linearly bridged between valid gates and held
+// flat past the last one, so windows spanning gaps still see a continuous profile.
+fn fill_gaps(unfolded: &[f32], valid: &[bool], offset: f32) -> Vec<f32> {
+    let n = unfolded.len();
+    let mut out = vec![0.0; n];
+    let mut previous: Option<usize> = None;
+    for g in 0..n {
+        if !valid[g] {
+            continue;
+        }
+        let value = unfolded[g] - offset;
+        match previous {
+            None => out[..g].iter_mut().for_each(|v| *v = value),
+            Some(p) => {
+                let start = unfolded[p] - offset;
+                for (k, slot) in out.iter_mut().enumerate().take(g).skip(p + 1) {
+                    *slot = start + (value - start) * (k - p) as f32 / (g - p) as f32;
+                }
+            }
+        }
+        out[g] = value;
+        previous = Some(g);
+    }
+    if let Some(p) = previous {
+        let last = out[p];
+        out[p + 1..].iter_mut().for_each(|v| *v = last);
+    }
+    out
+}
+
+fn centre_range(field: &PolarField, lo: usize, hi: usize) -> f32 {
+    (field.gate_range(lo) + field.gate_range(hi)) / 2.0
+}
+
+// Least-squares line through gates lo..=hi: (mean value, slope per metre).
+fn linear_fit(field: &PolarField, values: &[f32], lo: usize, hi: usize) -> (f32, f32) {
+    let centre = centre_range(field, lo, hi);
+    let n = (hi - lo + 1) as f32;
+    let mean = values[lo..=hi].iter().sum::<f32>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for (g, value) in values.iter().enumerate().take(hi + 1).skip(lo) {
+        let x = field.gate_range(g) - centre;
+        sxy += x * (value - mean);
+        sxx += x * x;
+    }
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    (mean, slope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phi_field(phase: impl Fn(f32) -> f32) -> PolarField {
+        let (first_gate, gate_spacing) = (2_125.0, 250.0);
+        let data = (0..4)
+            .map(|_| {
+                (0..400)
+                    .map(|g| phase(first_gate + g as f32 * gate_spacing))
+                    .collect()
+            })
+            .collect();
+        PolarField {
+            elevation: 0.5,
+            azimuths: vec![0.5, 1.5, 2.5, 3.5],
+            times: vec![0.0; 4],
+            first_gate,
+            gate_spacing,
+            data,
+        }
+    }
+
+    #[test]
+    fn wrapped_ramp_gives_constant_kdp() {
+        // 1.5 deg/km one way from a 300 degree system phase, wrapping past
+        // 360 about 20 km out, with a gap of missing gates further along.
+        let kdp = 1.5;
+        let phi = phi_field(|range| {
+            let gate = ((range - 2_125.0) / 250.0) as usize;
+            if (150..165).contains(&gate) {
+                f32::NAN
+            } else {
+                (300.0 + 2.0 * kdp * range / 1000.0).rem_euclid(360.0)
+            }
+        });
+        let result = kdp_from_fields(&phi, None, None, &KdpOptions::default());
+
+        let mut checked = 0;
+        for (r, row) in result.kdp.data.iter().enumerate() {
+            for (g, &value) in row.iter().enumerate() {
+                if phi.get(r, g).is_nan() {
+                    assert!(value.is_nan());
+                    continue;
+                }
+                assert!((value - kdp).abs() < 1e-3, "gate {}: {}", g, value);
+                checked += 1;
+            }
+        }
+        assert_eq!(checked, 4 * 385);
+
+        // The processed phase keeps rising through the wrap.
+        let phidp = &result.phidp.data[0];
+        let rise = phidp[399] - phidp[0];
+        assert!((rise - 2.0 * kdp * 399.0 * 0.25).abs() < 0.1, "{}", rise);
+    }
+
+    #[test]
+    fn system_offset_of_noisy_wrapped_start() {
+        // A 355 degree offset with noise, one gate wrapped to 1 degree, before
+        // the phase starts to rise.
+        let noise = [-1.0, 1.0, -2.0, 2.0, 0.0, 3.0, -3.0, 6.0, -4.0, 1.0];
+        let mut raw: Vec<f32> = noise.iter().map(|n| (355.0 + n) % 360.0).collect();
+        raw.extend((1..40).map(|g| (355.0 + g as f32).rem_euclid(360.0)));
+        let valid = vec![true; raw.len()];
+
+        let unfolded = unfold_phase(&raw, &valid);
+        assert_eq!(raw[7], 1.0);
+        assert_eq!(unfolded[7], 361.0);
+        let offset = system_offset(&unfolded, &valid, 10).unwrap();
+        assert!((offset - 355.0).abs() <= 1.0, "{}", offset);
+
+        let filled = fill_gaps(&unfolded, &valid, offset);
+        assert!((filled[raw.len() - 1] - 39.0).abs() <= 1.0);
+        assert_eq!(system_offset(&unfolded, &vec![false; raw.len()], 10), None);
+    }
+}
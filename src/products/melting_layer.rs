@@ -0,0 +1,200 @@
+use crate::geometry::beam_height;
+use crate::products::hydroclass::MeltingLayer;
+use crate::volume::{Moment, Volume};
+
+// Melting layer detection in the spirit of Giangrande et al. (2008). On the
+// mid-level tilts each radial is searched for the bright band: a RHO minimum
+// coinciding with enhanced reflectivity and ZDR. The layer extends either side
+// of the minimum while RHO stays depressed. Radial detections are pooled by
+// azimuth sector and the sector medians give the volume estimate.
+
+#[derive(Debug, Clone)]
+pub struct MeltingLayerOptions {
+    pub min_elevation: f32,
+    pub max_elevation: f32,
+    pub sector_width: f32, // degrees
+    // Search heights above the radar, metres.
+    pub min_height: f32,
+    pub max_height: f32,
+    pub min_rho: f32,
+    // RHO below this marks the depression around the minimum.
+    pub max_rho: f32,
+    pub min_reflectivity: f32,
+    pub min_zdr: f32,
+    pub max_thickness: f32,
+    pub min_sector_detections: usize,
+}
+
+impl Default for MeltingLayerOptions {
+    fn default() -> Self {
+        MeltingLayerOptions {
+            min_elevation: 4.0,
+            max_elevation: 10.0,
+            sector_width: 10.0,
+            min_height: 500.0,
+            max_height: 6_000.0,
+            min_rho: 0.80,
+            max_rho: 0.97,
+            min_reflectivity: 25.0,
+            min_zdr: 0.8,
+            max_thickness: 1_500.0,
+            min_sector_detections: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SectorMeltingLayer {
+    pub azimuth_start: f32,
+    pub azimuth_end: f32,
+    pub layer: Option<MeltingLayer>,
+    pub detections: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct MeltingLayerEstimate {
+    pub time: f64,
+    pub layer: Option<MeltingLayer>,
+    pub sectors: Vec<SectorMeltingLayer>,
+}
+
+impl MeltingLayerEstimate {
+    // The sector estimate covering `azimuth`, falling back to the volume estimate.
+    pub fn at_azimuth(&self, azimuth: f32) -> Option<MeltingLayer> {
+        let azimuth = azimuth.rem_euclid(360.0);
+        self.sectors
+            .iter()
+            .find(|s| azimuth >= s.azimuth_start && azimuth < s.azimuth_end)
+            .and_then(|s| s.layer)
+            .or(self.layer)
+    }
+}
+
+pub fn detect_melting_layer(
+    volume: &Volume,
+    options: &MeltingLayerOptions,
+) -> anyhow::Result<MeltingLayerEstimate> {
+    let site_height = volume.site().map_or(0.0, |s| s.site_height as f32);
+    let n_sectors = (360.0 / options.sector_width).ceil() as usize;
+    let mut detections: Vec<Vec<MeltingLayer>> = vec![Vec::new(); n_sectors];
+
+    let sweeps = volume
+        .sweeps_with(Moment::CorrelationCoefficient)
+        .filter(|s| {
+            s.elevation_angle >= options.min_elevation && s.elevation_angle <= options.max_elevation
+        });
+    for sweep in sweeps {
+        let Some(rho) = sweep.field(Moment::CorrelationCoefficient) else {
+            continue;
+        };
+        let (Some(z), Some(zdr)) = (
+            sweep.field(Moment::Reflectivity),
+            sweep.field(Moment::DifferentialReflectivity),
+        ) else {
+            continue;
+        };
+        let z = z.aligned_to(&rho);
+        let zdr = zdr.aligned_to(&rho);
+        let height = |g: usize| beam_height(rho.gate_range(g) as f64, rho.elevation as f64) as f32;
+
+        for r in 0..rho.n_radials() {
+            let candidates = (0..rho.n_gates()).filter(|&g| {
+                let h = height(g);
+                let value = rho.get(r, g);
+                h >= options.min_height
+                    && h <= options.max_height
+                    && value >= options.min_rho
+                    && value < options.max_rho
+                    && z.get(r, g) >= options.min_reflectivity
+                    && zdr.get(r, g) >= options.min_zdr
+            });
+            let Some(minimum) = candidates.min_by(|&a, &b| rho.get(r, a).total_cmp(&rho.get(r, b)))
+            else {
+                continue;
+            };
+
+            let depressed = |g: usize| rho.get(r, g) < options.max_rho;
+            let mut lo = minimum;
+            while lo > 0 && depressed(lo - 1) {
+                lo -= 1;
+            }
+            let mut hi = minimum;
+            while hi + 1 < rho.n_gates() && depressed(hi + 1) {
+                hi += 1;
+            }
+            let layer = MeltingLayer {
+                bottom: site_height + height(lo),
+                top: site_height + height(hi),
+            };
+            if layer.top - layer.bottom > options.max_thickness {
+                continue;
+            }
+            let sector = ((rho.azimuths[r].rem_euclid(360.0) / options.sector_width) as usize)
+                .min(n_sectors - 1);
+            detections[sector].push(layer);
+        }
+    }
+
+    let sectors: Vec<SectorMeltingLayer> = detections
+        .iter()
+        .enumerate()
+        .map(|(i, layers)| SectorMeltingLayer {
+            azimuth_start: i as f32 * options.sector_width,
+            azimuth_end: ((i + 1) as f32 * options.sector_width).min(360.0),
+            layer: (layers.len() >= options.min_sector_detections).then(|| median_layer(layers)),
+            detections: layers.len(),
+        })
+        .collect();
+
+    let all: Vec<MeltingLayer> = sectors
+        .iter()
+        .zip(detections.iter())
+        .filter(|(sector, _)| sector.layer.is_some())
+        .flat_map(|(_, layers)| layers.iter().copied())
+        .collect();
+
+    Ok(MeltingLayerEstimate {
+        time: volume.start_time().unwrap_or_default(),
+        layer: (!all.is_empty()).then(|| median_layer(&all)),
+        sectors,
+    })
+}
+
+fn median_layer(layers: &[MeltingLayer]) -> MeltingLayer {
+    let median = |mut values: Vec<f32>| {
+        values.sort_by(f32::total_cmp);
+        values[values.len() / 2]
+    };
+    MeltingLayer {
+        bottom: median(layers.iter().map(|l| l.bottom).collect()),
+        top: median(layers.iter().map(|l| l.top).collect()),
+    }
+}
+
+// Time-smoothed melting layer for each estimate: the detection-weighted mean of
+// the estimates in the preceding `window` seconds. Volumes without a detection
+// inherit the smoothed value while earlier detections are within the window.
+pub fn smooth_melting_layer(
+    estimates: &[MeltingLayerEstimate],
+    window: f64,
+) -> Vec<Option<MeltingLayer>> {
+    estimates
+        .iter()
+        .map(|current| {
+            let (bottom, top, weight) = estimates
+                .iter()
+                .filter(|e| e.time <= current.time && e.time > current.time - window)
+                .filter_map(|e| {
+                    let weight = e.sectors.iter().map(|s| s.detections).sum::<usize>() as f32;
+                    e.layer.map(|l| (l, weight.max(1.0)))
+                })
+                .fold((0.0, 0.0, 0.0), |(b, t, w), (l, weight)| {
+                    (b + l.bottom * weight, t + l.top * weight, w + weight)
+                });
+            (weight > 0.0).then(|| MeltingLayer {
+                bottom: bottom / weight,
+                top: top / weight,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+
+use crate::geometry::{beam_height, destination, ground_range};
+use crate::products::shear::{llsd_shear, mean_radial_spacing, LlsdOptions};
+use crate::qc::dealias::{dealias_volume, DealiasedVelocity};
+use crate::volume::{angle_difference, PolarField, Volume};
+
+// Mesocyclone and tornado vortex signature detection. Regions of cyclonic
+// azimuthal shear are picked out on each dealiased sweep and measured for the
+// strongest velocity couplet; features on adjacent sweeps are stacked into 3-D
+// circulations and classified by depth, strength and gate-to-gate shear.
+
+#[derive(Debug, Clone)]
+pub struct MesocycloneOptions {
+    pub llsd: LlsdOptions,
+    pub min_shear: f32, // s^-1
+    pub min_gates: usize,
+    pub min_rotational_velocity: f32, // m/s
+    pub max_range: f32,               // metres
+    pub vertical_search_radius: f32,  // metres
+    pub min_features: usize,
+    pub mesocyclone_min_depth: f32,
+    pub mesocyclone_min_rank: u8,
+    // A TVS needs this gate-to-gate velocity difference within this height
+    // of the radar and extending through at least this depth.
+    pub tvs_delta_v: f32,
+    pub tvs_max_base: f32,
+    pub tvs_min_depth: f32,
+}
+
+impl Default for MesocycloneOptions {
+    fn default() -> Self {
+        MesocycloneOptions {
+            llsd: LlsdOptions::default(),
+            min_shear: 0.005,
+            min_gates: 10,
+            min_rotational_velocity: 10.0,
+            max_range: 230_000.0,
+            vertical_search_radius: 5_000.0,
+            min_features: 2,
+            mesocyclone_min_depth: 3_000.0,
+            mesocyclone_min_rank: 5,
+            tvs_delta_v: 25.0,
+            tvs_max_base: 1_000.0,
+            tvs_min_depth: 1_500.0,
+        }
+    }
+}
+
+// A 2-D shear feature on one sweep. Positions are metres east (x) and north
+// (y) of the radar, heights metres above sea level.
+#[derive(Debug, Clone)]
+pub struct ShearFeature {
+    pub elevation: f32,
+    pub azimuth: f32,
+    pub range: f32,
+    pub x: f32,
+    pub y: f32,
+    pub height: f32,
+    pub max_shear: f32,
+    pub rotational_velocity: f32,
+    // Distance between the velocity extrema of the strongest couplet.
+    pub diameter: f32,
+    pub gate_to_gate_delta_v: f32,
+    pub n_gates: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CirculationKind {
+    TornadoVortexSignature,
+    Mesocyclone,
+    Circulation,
+}
+
+#[derive(Debug, Clone)]
+pub struct Circulation {
+    pub time: f64,
+    pub kind: CirculationKind,
+    pub x: f32,
+    pub y: f32,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub base: f32,
+    pub top: f32,
+    pub depth: f32,
+    pub max_rotational_velocity: f32,
+    pub max_rotational_velocity_height: f32,
+    pub max_shear: f32,
+    pub max_gate_to_gate_delta_v: f32,
+    pub diameter: f32,
+    pub strength_rank: u8,
+    pub features: Vec<ShearFeature>,
+}
+
+// Detects circulations in one volume, strongest first. Without `dealiased`
+// velocities the volume is dealiased here with no reference.
+pub fn detect_circulations(
+    volume: &Volume,
+    dealiased: Option<&[DealiasedVelocity]>,
+    options: &MesocycloneOptions,
+) -> anyhow::Result<Vec<Circulation>> {
+    let site = volume.require_site()?;
+    let site_height = site.site_height as f32;
+
+    let owned;
+    let dealiased = match dealiased {
+        Some(d) => d,
+        None => {
+            owned = dealias_volume(volume, None, None)?;
+            &owned
+        }
+    };
+    if dealiased.is_empty() {
+        anyhow::bail!("Volume has no velocity sweeps");
+    }
+
+    let mut levels: Vec<(f32, Vec<ShearFeature>)> = dealiased
+        .iter()
+        .map(|d| {
+            (
+                d.velocity.elevation,
+                shear_features(&d.velocity, site_height, options),
+            )
+        })
+        .collect();
+    levels.sort_by(|a, b| a.0.total_cmp(&b.0));
+    levels.dedup_by(|b, a| (a.0 - b.0).abs() < 0.2);
+
+    let time = volume.start_time().unwrap_or_default();
+    let mut used: Vec<Vec<bool>> = levels.iter().map(|l| vec![false; l.1.len()]).collect();
+    let mut circulations = Vec::new();
+
+    for start in 0..levels.len() {
+        for f in 0..levels[start].1.len() {
+            if used[start][f] {
+                continue;
+            }
+            used[start][f] = true;
+            let mut stack = vec![levels[start].1[f].clone()];
+
+            for level in start + 1..levels.len() {
+                let last = stack.last().expect("stack starts non-empty");
+                let next = levels[level]
+                    .1
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !used[level][*i])
+                    .map(|(i, other)| (i, (other.x - last.x).hypot(other.y - last.y)))
+                    .filter(|(_, d)| *d <= options.vertical_search_radius)
+                    .min_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(i, _)| i);
+                let Some(i) = next else { break };
+                used[level][i] = true;
+                stack.push(levels[level].1[i].clone());
+            }
+
+            if stack.len() >= options.min_features {
+                circulations.push(build_circulation(
+                    stack,
+                    time,
+                    site.latitude as f64,
+                    site.longitude as f64,
+                    site_height,
+                    options,
+                ));
+            }
+        }
+    }
+
+    circulations.sort_by(|a, b| {
+        b.strength_rank.cmp(&a.strength_rank).then(
+            b.max_rotational_velocity
+                .total_cmp(&a.max_rotational_velocity),
+        )
+    });
+    Ok(circulations)
+}
+
+// Simplified strength rank: 1 at 10 m/s rotational velocity, one step per
+// further 2.5 m/s, capped at 25.
+pub fn strength_rank(rotational_velocity: f32) -> u8 {
+    if rotational_velocity < 10.0 {
+        return 0;
+    }
+    (((rotational_velocity - 10.0) / 2.5).floor() as u8 + 1).min(25)
+}
+
+fn build_circulation(
+    features: Vec<ShearFeature>,
+    time: f64,
+    lat: f64,
+    lon: f64,
+    site_height: f32,
+    options: &MesocycloneOptions,
+) -> Circulation {
+    // Features are ordered bottom up; the circulation is located by its base.
+    let lowest = &features[0];
+    let (latitude, longitude) = destination(lat, lon, lowest.azimuth as f64, {
+        (lowest.x as f64).hypot(lowest.y as f64)
+    });
+    let strongest = features
+        .iter()
+        .max_by(|a, b| a.rotational_velocity.total_cmp(&b.rotational_velocity))
+        .expect("circulations have at least one feature");
+    let base = features
+        .iter()
+        .map(|f| f.height)
+        .fold(f32::INFINITY, f32::min);
+    let top = features
+        .iter()
+        .map(|f| f.height)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let depth = top - base;
+    let max_shear = features.iter().map(|f| f.max_shear).fold(0.0, f32::max);
+    let max_gate_to_gate_delta_v = features
+        .iter()
+        .map(|f| f.gate_to_gate_delta_v)
+        .fold(0.0, f32::max);
+    let rank = strength_rank(strongest.rotational_velocity);
+
+    let kind = if max_gate_to_gate_delta_v >= options.tvs_delta_v
+        && base - site_height <= options.tvs_max_base
+        && depth >= options.tvs_min_depth
+    {
+        CirculationKind::TornadoVortexSignature
+    } else if depth >= options.mesocyclone_min_depth && rank >= options.mesocyclone_min_rank {
+        CirculationKind::Mesocyclone
+    } else {
+        CirculationKind::Circulation
+    };
+
+    Circulation {
+        time,
+        kind,
+        x: lowest.x,
+        y: lowest.y,
+        latitude,
+        longitude,
+        base,
+        top,
+        depth,
+        max_rotational_velocity: strongest.rotational_velocity,
+        max_rotational_velocity_height: strongest.height,
+        max_shear,
+        max_gate_to_gate_delta_v,
+        diameter: strongest.diameter,
+        strength_rank: rank,
+        features,
+    }
+}
+
+// Connected regions of cyclonic azimuthal shear on one dealiased sweep.
+// Positive LLSD shear (velocity increasing clockwise) is cyclonic.
+pub fn shear_features(
+    velocity: &PolarField,
+    site_height: f32,
+    options: &MesocycloneOptions,
+) -> Vec<ShearFeature> {
+    let n_radials = velocity.n_radials();
+    let n_gates = velocity.n_gates();
+    if n_radials == 0 || n_gates == 0 {
+        return Vec::new();
+    }
+    let shear = llsd_shear(velocity, &options.llsd).azimuthal_shear;
+    let spacing = mean_radial_spacing(velocity).to_radians();
+    let elevation = velocity.elevation as f64;
+    let is_core = |r: usize, g: usize| {
+        let s = shear.data[r][g];
+        !s.is_nan() && s >= options.min_shear && velocity.gate_range(g) <= options.max_range
+    };
+
+    let mut visited = vec![vec![false; n_gates]; n_radials];
+    let mut features = Vec::new();
+    for r0 in 0..n_radials {
+        for g0 in 0..n_gates {
+            if visited[r0][g0] || !is_core(r0, g0) {
+                continue;
+            }
+            let mut gates = Vec::new();
+            let mut queue = VecDeque::from([(r0, g0)]);
+            visited[r0][g0] = true;
+            while let Some((r, g)) = queue.pop_front() {
+                gates.push((r, g));
+                let mut next = vec![
+                    ((r + 1) % n_radials, g),
+                    ((r + n_radials - 1) % n_radials, g),
+                ];
+                if g + 1 < n_gates {
+                    next.push((r, g + 1));
+                }
+                if g > 0 {
+                    next.push((r, g - 1));
+                }
+                for (nr, ng) in next {
+                    if !visited[nr][ng] && is_core(nr, ng) {
+                        visited[nr][ng] = true;
+                        queue.push_back((nr, ng));
+                    }
+                }
+            }
+            if gates.len() < options.min_gates {
+                continue;
+            }
+
+            // Shear-weighted centre, with azimuths averaged as unit vectors so
+            // features straddling north stay in one piece.
+            let (mut weight, mut sx, mut sy, mut range) = (0.0_f32, 0.0_f32, 0.0_f32, 0.0_f32);
+            let mut max_shear = 0.0_f32;
+            for &(r, g) in gates.iter() {
+                let w = shear.data[r][g];
+                let az = velocity.azimuths[r].to_radians();
+                weight += w;
+                sx += w * az.sin();
+                sy += w * az.cos();
+                range += w * velocity.gate_range(g);
+                max_shear = max_shear.max(w);
+            }
+            let azimuth = sx.atan2(sy).to_degrees().rem_euclid(360.0);
+            let range = range / weight;
+
+            let (rotational_velocity, diameter) =
+                couplet(velocity, &gates, azimuth, spacing, options);
+            if rotational_velocity < options.min_rotational_velocity {
+                continue;
+            }
+            let gate_to_gate_delta_v = gates
+                .iter()
+                .filter_map(|&(r, g)| {
+                    let dv = velocity.data[(r + 1) % n_radials][g] - velocity.data[r][g];
+                    dv.is_finite().then_some(dv)
+                })
+                .fold(0.0, f32::max);
+
+            let ground = ground_range(range as f64, elevation) as f32;
+            features.push(ShearFeature {
+                elevation: velocity.elevation,
+                azimuth,
+                range,
+                x: ground * azimuth.to_radians().sin(),
+                y: ground * azimuth.to_radians().cos(),
+                height: site_height + beam_height(range as f64, elevation) as f32,
+                max_shear,
+                rotational_velocity,
+                diameter,
+                gate_to_gate_delta_v,
+                n_gates: gates.len(),
+            });
+        }
+    }
+    features
+}
+
+// Strongest cyclonic couplet along the feature's range gates: the outbound
+// maximum must lie clockwise of the inbound minimum. Each gate is searched
+// across the feature's radials widened by half the LLSD window.
+fn couplet(
+    velocity: &PolarField,
+    gates: &[(usize, usize)],
+    azimuth: f32,
+    spacing: f32,
+    options: &MesocycloneOptions,
+) -> (f32, f32) {
+    let n_radials = velocity.n_radials() as i64;
+    let mut by_gate: Vec<(usize, Vec<usize>)> = Vec::new();
+    for &(r, g) in gates {
+        match by_gate.iter_mut().find(|(gate, _)| *gate == g) {
+            Some((_, radials)) => radials.push(r),
+            None => by_gate.push((g, vec![r])),
+        }
+    }
+
+    let mut best = (0.0_f32, 0.0_f32);
+    for (g, radials) in by_gate {
+        let range = velocity.gate_range(g);
+        let margin = if spacing > 0.0 && range > 0.0 {
+            ((options.llsd.azimuthal_width / 2.0 / (spacing * range)).round() as i64)
+                .clamp(1, options.llsd.max_half_radials as i64)
+        } else {
+            1
+        };
+        let mut span: Vec<usize> = radials
+            .iter()
+            .flat_map(|&r| {
+                (-margin..=margin).map(move |d| (r as i64 + d).rem_euclid(n_radials) as usize)
+            })
+            .collect();
+        span.sort_unstable();
+        span.dedup();
+
+        // (offset from the feature azimuth in degrees, velocity)
+        let mut points: Vec<(f32, f32)> = span
+            .into_iter()
+            .filter_map(|r| {
+                let v = velocity.data[r][g];
+                v.is_finite()
+                    .then(|| (angle_difference(velocity.azimuths[r], azimuth), v))
+            })
+            .collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Running minimum from the counter-clockwise side so the pair is cyclonic.
+        let mut min_so_far: Option<(f32, f32)> = None;
+        for &(offset, v) in points.iter() {
+            if let Some((min_offset, min_v)) = min_so_far {
+                let vrot = (v - min_v) / 2.0;
+                if vrot > best.0 {
+                    best = (vrot, (offset - min_offset).to_radians() * range);
+                }
+            }
+            if min_so_far.is_none_or(|(_, m)| v < m) {
+                min_so_far = Some((offset, v));
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::{synthetic, Moment};
+
+    const CENTRE: (f32, f32) = (40_000.0, 0.0);
+    const CORE_RADIUS: f32 = 3_000.0;
+    const PEAK: f32 = 25.0;
+
+    // Radial velocity of a cyclonic Rankine vortex, upright through the
+    // depth of the volume, in otherwise calm air.
+    fn rankine(elevation: f32, azimuth: f32, range: f32) -> f32 {
+        let (sin, cos) = azimuth.to_radians().sin_cos();
+        let ground = range * elevation.to_radians().cos();
+        let (dx, dy) = (ground * sin - CENTRE.0, ground * cos - CENTRE.1);
+        let r = dx.hypot(dy).max(1.0);
+        let speed = if r < CORE_RADIUS {
+            PEAK * r / CORE_RADIUS
+        } else {
+            PEAK * CORE_RADIUS / r
+        };
+        let (u, v) = (-speed * dy / r, speed * dx / r);
+        (u * sin + v * cos) * elevation.to_radians().cos()
+    }
+
+    #[test]
+    fn rankine_couplet_is_a_mesocyclone() {
+        let elevations = [0.5, 1.5, 2.4, 3.4, 4.3, 6.0];
+        let volume = synthetic::volume(
+            &elevations,
+            200,
+            30.0,
+            &[Moment::Velocity],
+            |_, el, az, range| rankine(el, az, range),
+        );
+        let circulations =
+            detect_circulations(&volume, None, &MesocycloneOptions::default()).unwrap();
+        assert_eq!(circulations.len(), 1, "{:?}", circulations);
+
+        let c = &circulations[0];
+        assert_eq!(c.kind, CirculationKind::Mesocyclone);
+        assert_eq!(c.features.len(), elevations.len());
+        assert!(
+            (c.x - CENTRE.0).hypot(c.y - CENTRE.1) < 1_500.0,
+            "{} {}",
+            c.x,
+            c.y
+        );
+        assert!(c.depth > 3_000.0, "{}", c.depth);
+        // Sampling on 1 degree radials sees a little less than the peak.
+        assert!(c.max_rotational_velocity > 0.8 * PEAK && c.max_rotational_velocity <= PEAK);
+        assert_eq!(c.strength_rank, strength_rank(c.max_rotational_velocity));
+        assert!((5..=7).contains(&c.strength_rank), "{}", c.strength_rank);
+        assert!(c.max_gate_to_gate_delta_v < MesocycloneOptions::default().tvs_delta_v);
+    }
+
+    #[test]
+    fn strength_rank_steps() {
+        assert_eq!(strength_rank(9.9), 0);
+        assert_eq!(strength_rank(10.0), 1);
+        assert_eq!(strength_rank(12.5), 2);
+        assert_eq!(strength_rank(24.9), 6);
+        assert_eq!(strength_rank(100.0), 25);
+    }
+}
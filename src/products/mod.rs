@@ -0,0 +1,78 @@
+pub mod cells;
+pub mod hail;
+pub mod hydroclass;
+pub mod kdp;
+pub mod melting_layer;
+pub mod mesocyclone;
+pub mod nowcast;
+pub mod qpe;
+pub mod qvp;
+pub mod shear;
+pub mod vad;
+
+// Trapezoid membership rising over x1..x2 and falling over x3..x4.
+pub fn trapezoid(x: f32, x1: f32, x2: f32, x3: f32, x4: f32) -> f32 {
+    if x < x1 || x > x4 {
+        0.0
+    } else if x < x2 {
+        (x - x1) / (x2 - x1)
+    } else if x <= x3 {
+        1.0
+    } else {
+        (x4 - x) / (x4 - x3)
+    }
+}
+
+pub fn db_to_linear(db: f32) -> f32 {
+    10f32.powf(db / 10.0)
+}
+
+pub fn linear_to_db(linear: f32) -> f32 {
+    10.0 * linear.log10()
+}
+
+// Mean of the finite values, or NaN if fewer than `min_count` are present.
+pub fn finite_mean(values: impl Iterator<Item = f32>, min_count: usize) -> f32 {
+    let (sum, count) = values
+        .filter(|v| v.is_finite())
+        .fold((0.0_f64, 0_usize), |(s, n), v| (s + v as f64, n + 1));
+    if count == 0 || count < min_count {
+        return f32::NAN;
+    }
+    (sum / count as f64) as f32
+}
+
+// Solves `a x = b` by Gaussian elimination with partial pivoting. Returns None
+// for a singular system. Intended for the small normal-equation systems used
+// by the least-squares fits.
+pub fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (x, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *x -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let tail: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - tail) / a[row][row];
+    }
+    Some(x)
+}
+
+// Meteorological direction the wind blows from, in degrees.
+pub fn wind_direction(u: f32, v: f32) -> f32 {
+    ((-u).atan2(-v).to_degrees() + 360.0) % 360.0
+}
@@ -0,0 +1,477 @@
+use crate::grid::{composite_reflectivity, GridSpec, GriddedField};
+use crate::volume::Volume;
+
+// Extrapolation nowcasting. A dense motion field is estimated between
+// consecutive gridded reflectivity fields with pyramidal Lucas-Kanade optical
+// flow, averaged over the pairs, and the latest field is advected along it
+// with backward semi-Lagrangian trajectories.
+
+#[derive(Debug, Clone)]
+pub struct NowcastOptions {
+    // Reflectivity below this (dBZ) is treated as clear air when tracking.
+    pub min_reflectivity: f32,
+    pub pyramid_levels: usize,
+    // Half-width in cells of the Lucas-Kanade window at each level.
+    pub window: usize,
+    pub iterations: usize,
+    // Half-width in cells of the confidence-weighted flow smoothing.
+    pub smoothing_radius: usize,
+    pub step_minutes: u32,
+    pub max_lead_minutes: u32,
+}
+
+impl Default for NowcastOptions {
+    fn default() -> Self {
+        NowcastOptions {
+            min_reflectivity: 10.0,
+            pyramid_levels: 4,
+            window: 5,
+            iterations: 5,
+            smoothing_radius: 8,
+            step_minutes: 5,
+            max_lead_minutes: 60,
+        }
+    }
+}
+
+// Motion in m/s towards east (u) and north (v) for every grid cell.
+#[derive(Debug, Clone)]
+pub struct MotionField {
+    pub spec: GridSpec,
+    pub u: Vec<Vec<f32>>,
+    pub v: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Nowcast {
+    pub motion: MotionField,
+    // One field per lead time, each stamped with its valid time.
+    pub forecasts: Vec<GriddedField>,
+}
+
+pub fn nowcast(fields: &[GriddedField], options: &NowcastOptions) -> anyhow::Result<Nowcast> {
+    let motion = estimate_motion(fields, options)?;
+    let latest = fields
+        .iter()
+        .max_by(|a, b| a.time.total_cmp(&b.time))
+        .expect("estimate_motion needs at least two fields");
+    Ok(Nowcast {
+        forecasts: extrapolate(latest, &motion, options),
+        motion,
+    })
+}
+
+// Nowcast from the composite reflectivity of each volume on a shared grid.
+pub fn nowcast_volumes(
+    volumes: &[Volume],
+    spec: GridSpec,
+    options: &NowcastOptions,
+) -> anyhow::Result<Nowcast> {
+    let fields = volumes
+        .iter()
+        .map(|v| composite_reflectivity(v, spec))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    nowcast(&fields, options)
+}
+
+pub fn estimate_motion(
+    fields: &[GriddedField],
+    options: &NowcastOptions,
+) -> anyhow::Result<MotionField> {
+    if fields.len() < 2 {
+        anyhow::bail!(
+            "Need at least two fields to estimate motion, got {}",
+            fields.len()
+        );
+    }
+    let mut ordered: Vec<&GriddedField> = fields.iter().collect();
+    ordered.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let spec = ordered[0].spec;
+    if ordered.iter().any(|f| f.spec != spec) {
+        anyhow::bail!("Fields must share one grid");
+    }
+    if spec.n_rows == 0 || spec.n_cols == 0 {
+        anyhow::bail!("Grid has no cells to track");
+    }
+
+    let (n_rows, n_cols) = (spec.n_rows, spec.n_cols);
+    let mut u = vec![vec![0.0_f32; n_cols]; n_rows];
+    let mut v = vec![vec![0.0_f32; n_cols]; n_rows];
+    let mut pairs = 0;
+    for pair in ordered.windows(2) {
+        let dt = (pair[1].time - pair[0].time) as f32;
+        if dt <= 0.0 {
+            continue;
+        }
+        let prev = tracking_image(pair[0], options.min_reflectivity);
+        let next = tracking_image(pair[1], options.min_reflectivity);
+        let (du, dv) = pyramid_flow(&prev, &next, options);
+        for r in 0..n_rows {
+            for c in 0..n_cols {
+                u[r][c] += du[r][c] / dt;
+                v[r][c] += dv[r][c] / dt;
+            }
+        }
+        pairs += 1;
+    }
+    if pairs == 0 {
+        anyhow::bail!("Fields have no increasing time steps");
+    }
+
+    // Cells per second to m/s; rows count southwards.
+    let (dx, dy) = spec.cell_size();
+    for r in 0..n_rows {
+        for c in 0..n_cols {
+            u[r][c] *= dx as f32 / pairs as f32;
+            v[r][c] *= -(dy as f32) / pairs as f32;
+        }
+    }
+    Ok(MotionField { spec, u, v })
+}
+
+// Advects `field` along `motion` for each lead time. Trajectories are traced
+// back in one-minute sub-steps and the field is sampled bilinearly at their
+// origin.
+pub fn extrapolate(
+    field: &GriddedField,
+    motion: &MotionField,
+    options: &NowcastOptions,
+) -> Vec<GriddedField> {
+    let spec = field.spec;
+    let (dx, dy) = spec.cell_size();
+    let u: Image = motion
+        .u
+        .iter()
+        .map(|row| row.iter().map(|&x| x / dx as f32).collect())
+        .collect();
+    let v: Image = motion
+        .v
+        .iter()
+        .map(|row| row.iter().map(|&x| -x / dy as f32).collect())
+        .collect();
+
+    let mut positions: Vec<Vec<(f32, f32)>> = (0..spec.n_rows)
+        .map(|r| (0..spec.n_cols).map(|c| (c as f32, r as f32)).collect())
+        .collect();
+    let step = options.step_minutes.max(1);
+    let mut forecasts = Vec::new();
+    for lead in (step..=options.max_lead_minutes).step_by(step as usize) {
+        for _ in 0..step {
+            for row in positions.iter_mut() {
+                for (x, y) in row.iter_mut() {
+                    let (du, dv) = (sample(&u, *x, *y), sample(&v, *x, *y));
+                    *x -= du * 60.0;
+                    *y -= dv * 60.0;
+                }
+            }
+        }
+        let mut forecast = GriddedField::filled(spec, field.time + lead as f64 * 60.0, f32::NAN);
+        for (out, row) in forecast.data.iter_mut().zip(positions.iter()) {
+            for (value, &(x, y)) in out.iter_mut().zip(row.iter()) {
+                *value = sample_field(&field.data, x, y);
+            }
+        }
+        forecasts.push(forecast);
+    }
+    forecasts
+}
+
+type Image = Vec<Vec<f32>>;
+
+fn tracking_image(field: &GriddedField, min_reflectivity: f32) -> Image {
+    field
+        .data
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&z| {
+                    if z.is_nan() {
+                        0.0
+                    } else {
+                        (z - min_reflectivity).max(0.0)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Displacement in cells (columns, rows) taking `prev` onto `next`.
+fn pyramid_flow(prev: &Image, next: &Image, options: &NowcastOptions) -> (Image, Image) {
+    let mut prev_levels = vec![prev.clone()];
+    let mut next_levels = vec![next.clone()];
+    for _ in 1..options.pyramid_levels.max(1) {
+        let (p, n) = (prev_levels.last().unwrap(), next_levels.last().unwrap());
+        if p.len() < 16 || p[0].len() < 16 {
+            break;
+        }
+        prev_levels.push(downsample(p));
+        next_levels.push(downsample(n));
+    }
+
+    let coarsest = prev_levels.last().unwrap();
+    let mut u = vec![vec![0.0; coarsest[0].len()]; coarsest.len()];
+    let mut v = u.clone();
+    for level in (0..prev_levels.len()).rev() {
+        let (p, n) = (&prev_levels[level], &next_levels[level]);
+        if u.len() != p.len() || u[0].len() != p[0].len() {
+            u = upsample(&u, p.len(), p[0].len());
+            v = upsample(&v, p.len(), p[0].len());
+        }
+        let confidence = lucas_kanade(p, n, &mut u, &mut v, options);
+        smooth_flow(&mut u, &mut v, &confidence, options.smoothing_radius);
+    }
+    (u, v)
+}
+
+// Refines (u, v) in place and returns the per-cell confidence, the smaller
+// eigenvalue of the structure tensor.
+fn lucas_kanade(
+    prev: &Image,
+    next: &Image,
+    u: &mut Image,
+    v: &mut Image,
+    options: &NowcastOptions,
+) -> Image {
+    let (n_rows, n_cols) = (prev.len(), prev[0].len());
+    let mut ix = vec![vec![0.0_f32; n_cols]; n_rows];
+    let mut iy = ix.clone();
+    for r in 0..n_rows {
+        for c in 0..n_cols {
+            let (c0, c1) = (c.saturating_sub(1), (c + 1).min(n_cols - 1));
+            let (r0, r1) = (r.saturating_sub(1), (r + 1).min(n_rows - 1));
+            ix[r][c] = (prev[r][c1] - prev[r][c0]) / (c1 - c0).max(1) as f32;
+            iy[r][c] = (prev[r1][c] - prev[r0][c]) / (r1 - r0).max(1) as f32;
+        }
+    }
+    let product = |a: &Image, b: &Image| -> Image {
+        a.iter()
+            .zip(b.iter())
+            .map(|(ra, rb)| ra.iter().zip(rb.iter()).map(|(x, y)| x * y).collect())
+            .collect()
+    };
+    let sxx = box_sum(&product(&ix, &ix), options.window);
+    let sxy = box_sum(&product(&ix, &iy), options.window);
+    let syy = box_sum(&product(&iy, &iy), options.window);
+
+    let mut confidence = vec![vec![0.0_f32; n_cols]; n_rows];
+    for r in 0..n_rows {
+        for c in 0..n_cols {
+            let (a, b, d) = (sxx[r][c], sxy[r][c], syy[r][c]);
+            let trace = a + d;
+            let det = a * d - b * b;
+            confidence[r][c] =
+                (trace / 2.0 - ((trace / 2.0).powi(2) - det).max(0.0).sqrt()).max(0.0);
+        }
+    }
+
+    for _ in 0..options.iterations {
+        let mut it = vec![vec![0.0_f32; n_cols]; n_rows];
+        for r in 0..n_rows {
+            for c in 0..n_cols {
+                it[r][c] = sample(next, c as f32 + u[r][c], r as f32 + v[r][c]) - prev[r][c];
+            }
+        }
+        let bx = box_sum(&product(&ix, &it), options.window);
+        let by = box_sum(&product(&iy, &it), options.window);
+        for r in 0..n_rows {
+            for c in 0..n_cols {
+                if confidence[r][c] <= 1e-3 {
+                    continue;
+                }
+                let (a, b, d) = (sxx[r][c], sxy[r][c], syy[r][c]);
+                let det = a * d - b * b;
+                u[r][c] -= (d * bx[r][c] - b * by[r][c]) / det;
+                v[r][c] -= (a * by[r][c] - b * bx[r][c]) / det;
+            }
+        }
+    }
+    confidence
+}
+
+// Confidence-weighted box average of the flow. Cells with no confident
+// neighbours take the confidence-weighted mean of the whole field.
+fn smooth_flow(u: &mut Image, v: &mut Image, confidence: &Image, radius: usize) {
+    let weighted = |f: &Image| -> Image {
+        f.iter()
+            .zip(confidence.iter())
+            .map(|(rf, rc)| rf.iter().zip(rc.iter()).map(|(x, w)| x * w).collect())
+            .collect()
+    };
+    let weight = box_sum(confidence, radius);
+    let su = box_sum(&weighted(u), radius);
+    let sv = box_sum(&weighted(v), radius);
+
+    let total: f32 = confidence.iter().flatten().sum();
+    let (mean_u, mean_v) = if total > 0.0 {
+        (
+            weighted(u).iter().flatten().sum::<f32>() / total,
+            weighted(v).iter().flatten().sum::<f32>() / total,
+        )
+    } else {
+        (0.0, 0.0)
+    };
+    for r in 0..u.len() {
+        for c in 0..u[0].len() {
+            if weight[r][c] > 1e-3 {
+                u[r][c] = su[r][c] / weight[r][c];
+                v[r][c] = sv[r][c] / weight[r][c];
+            } else {
+                u[r][c] = mean_u;
+                v[r][c] = mean_v;
+            }
+        }
+    }
+}
+
+// Sum over a (2 * radius + 1) square window, clipped at the edges.
+fn box_sum(image: &Image, radius: usize) -> Image {
+    let (n_rows, n_cols) = (image.len(), image[0].len());
+    let mut integral = vec![vec![0.0_f64; n_cols + 1]; n_rows + 1];
+    for r in 0..n_rows {
+        for c in 0..n_cols {
+            integral[r + 1][c + 1] =
+                image[r][c] as f64 + integral[r][c + 1] + integral[r + 1][c] - integral[r][c];
+        }
+    }
+    (0..n_rows)
+        .map(|r| {
+            let (r0, r1) = (r.saturating_sub(radius), (r + radius + 1).min(n_rows));
+            (0..n_cols)
+                .map(|c| {
+                    let (c0, c1) = (c.saturating_sub(radius), (c + radius + 1).min(n_cols));
+                    (integral[r1][c1] - integral[r0][c1] - integral[r1][c0] + integral[r0][c0])
+                        as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn downsample(image: &Image) -> Image {
+    let (n_rows, n_cols) = (image.len(), image[0].len());
+    (0..n_rows.div_ceil(2))
+        .map(|r| {
+            (0..n_cols.div_ceil(2))
+                .map(|c| {
+                    let block: Vec<f32> = image[2 * r..(2 * r + 2).min(n_rows)]
+                        .iter()
+                        .flat_map(|row| row[2 * c..(2 * c + 2).min(n_cols)].iter().copied())
+                        .collect();
+                    block.iter().sum::<f32>() / block.len() as f32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Doubles a coarse flow field onto the next finer level.
+fn upsample(flow: &Image, n_rows: usize, n_cols: usize) -> Image {
+    (0..n_rows)
+        .map(|r| {
+            (0..n_cols)
+                .map(|c| 2.0 * sample(flow, (c as f32 - 0.5) / 2.0, (r as f32 - 0.5) / 2.0))
+                .collect()
+        })
+        .collect()
+}
+
+// Bilinear sample with coordinates clamped to the image.
+fn sample(image: &Image, x: f32, y: f32) -> f32 {
+    let (n_rows, n_cols) = (image.len(), image[0].len());
+    let x = x.clamp(0.0, (n_cols - 1) as f32);
+    let y = y.clamp(0.0, (n_rows - 1) as f32);
+    let (c0, r0) = (x.floor() as usize, y.floor() as usize);
+    let (c1, r1) = ((c0 + 1).min(n_cols - 1), (r0 + 1).min(n_rows - 1));
+    let (fx, fy) = (x - c0 as f32, y - r0 as f32);
+    let top = image[r0][c0] * (1.0 - fx) + image[r0][c1] * fx;
+    let bottom = image[r1][c0] * (1.0 - fx) + image[r1][c1] * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+// Bilinear sample of a NaN-masked field; NaN outside the grid or where most
+// of the interpolation weight falls on missing cells.
+fn sample_field(data: &[Vec<f32>], x: f32, y: f32) -> f32 {
+    let (n_rows, n_cols) = (data.len(), data.first().map_or(0, |r| r.len()));
+    if x < -0.5 || y < -0.5 || x > n_cols as f32 - 0.5 || y > n_rows as f32 - 0.5 {
+        return f32::NAN;
+    }
+    let x = x.clamp(0.0, (n_cols - 1) as f32);
+    let y = y.clamp(0.0, (n_rows - 1) as f32);
+    let (c0, r0) = (x.floor() as usize, y.floor() as usize);
+    let (c1, r1) = ((c0 + 1).min(n_cols - 1), (r0 + 1).min(n_rows - 1));
+    let (fx, fy) = (x - c0 as f32, y - r0 as f32);
+    let mut sum = 0.0;
+    let mut weight = 0.0;
+    for (r, wy) in [(r0, 1.0 - fy), (r1, fy)] {
+        for (c, wx) in [(c0, 1.0 - fx), (c1, fx)] {
+            let value = data[r][c];
+            if !value.is_nan() {
+                sum += value * wx * wy;
+                weight += wx * wy;
+            }
+        }
+    }
+    if weight >= 0.5 {
+        sum / weight
+    } else {
+        f32::NAN
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(n: usize) -> GridSpec {
+        GridSpec {
+            north: 36.0,
+            west: -98.0,
+            lat_step: 0.01,
+            lon_step: 0.01,
+            n_rows: n,
+            n_cols: n,
+        }
+    }
+
+    #[test]
+    fn empty_grid_is_an_error() {
+        let fields = [
+            GriddedField::filled(spec(0), 0.0, 30.0),
+            GriddedField::filled(spec(0), 300.0, 30.0),
+        ];
+        assert!(estimate_motion(&fields, &NowcastOptions::default()).is_err());
+    }
+
+    #[test]
+    fn extrapolate_samples_motion_before_moving() {
+        // One column per minute eastward, and a northward drift that grows
+        // with the column, over a field that is linear in row and column so
+        // each forecast value names the cell it came from.
+        let spec = spec(10);
+        let (dx, dy) = spec.cell_size();
+        let mut field = GriddedField::filled(spec, 0.0, 0.0);
+        for (r, row) in field.data.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = 100.0 * r as f32 + c as f32;
+            }
+        }
+        let motion = MotionField {
+            spec,
+            u: vec![vec![dx as f32 / 60.0; 10]; 10],
+            v: (0..10)
+                .map(|_| (0..10).map(|c| 0.1 * c as f32 * dy as f32 / 60.0).collect())
+                .collect(),
+        };
+        let options = NowcastOptions {
+            step_minutes: 1,
+            max_lead_minutes: 1,
+            ..NowcastOptions::default()
+        };
+        let forecasts = extrapolate(&field, &motion, &options);
+        assert_eq!(forecasts.len(), 1);
+        // The cell at row 5, column 5 came from row 5.5, column 4.
+        let value = forecasts[0].data[5][5];
+        assert!((value - 554.0).abs() < 0.01, "{}", value);
+    }
+}
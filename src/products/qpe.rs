@@ -0,0 +1,260 @@
+use crate::products::db_to_linear;
+use crate::products::kdp::{kdp_from_fields, KdpOptions};
+use crate::volume::{Moment, PolarField, Volume};
+
+// Rain rate from the lowest reflectivity sweep and accumulation across
+// volumes. Accumulation integrates each radial separately between its own
+// collection times, so the sweep's scan time is respected gate by gate.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RainRelation {
+    // Z = 200 R^1.6
+    MarshallPalmer,
+    // Z = 300 R^1.4, the WSR-88D default
+    Convective,
+    // Z = 250 R^1.2 (Rosenfeld tropical)
+    Tropical,
+    // Z = a R^b
+    PowerLaw { a: f32, b: f32 },
+    // R = 0.0142 Z^0.77 Zdr^-1.67, Z and Zdr linear (Ryzhkov et al. 2005)
+    ReflectivityDifferentialReflectivity,
+    // R = 44 KDP^0.822 (Ryzhkov et al. 2005), no rain for negative KDP
+    SpecificDifferentialPhase,
+}
+
+impl RainRelation {
+    fn needs(&self) -> (bool, bool) {
+        match self {
+            RainRelation::ReflectivityDifferentialReflectivity => (true, false),
+            RainRelation::SpecificDifferentialPhase => (false, true),
+            _ => (false, false),
+        }
+    }
+
+    // Rain rate in mm/h. ZDR in dB, KDP in degrees per km.
+    pub fn rate(&self, reflectivity: f32, zdr: f32, kdp: f32) -> f32 {
+        let z = db_to_linear(reflectivity);
+        let power_law = |a: f32, b: f32| (z / a).powf(1.0 / b);
+        match *self {
+            RainRelation::MarshallPalmer => power_law(200.0, 1.6),
+            RainRelation::Convective => power_law(300.0, 1.4),
+            RainRelation::Tropical => power_law(250.0, 1.2),
+            RainRelation::PowerLaw { a, b } => power_law(a, b),
+            RainRelation::ReflectivityDifferentialReflectivity => {
+                0.0142 * z.powf(0.77) * db_to_linear(zdr).powf(-1.67)
+            }
+            // Negative KDP is noise rather than rain; missing KDP stays NaN so
+            // the caller falls back to Z-R.
+            RainRelation::SpecificDifferentialPhase => {
+                if kdp.is_nan() {
+                    f32::NAN
+                } else {
+                    44.0 * kdp.max(0.0).powf(0.822)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QpeOptions {
+    pub relation: RainRelation,
+    // Reflectivity is capped here before conversion to limit hail contamination.
+    pub max_reflectivity: f32,
+    pub min_reflectivity: f32,
+    // Gaps between volumes longer than this (seconds) are not integrated.
+    pub max_gap: f64,
+    pub kdp: KdpOptions,
+}
+
+impl Default for QpeOptions {
+    fn default() -> Self {
+        QpeOptions {
+            relation: RainRelation::Convective,
+            max_reflectivity: 53.0,
+            min_reflectivity: 10.0,
+            max_gap: 1800.0,
+            kdp: KdpOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RainRate {
+    pub rate: PolarField, // mm/h, 0 where there is no echo
+}
+
+#[derive(Debug, Clone)]
+pub struct Accumulation {
+    pub start: f64,
+    pub end: f64,
+    pub depth: PolarField, // mm
+}
+
+pub fn rain_rate(volume: &Volume, options: &QpeOptions) -> anyhow::Result<RainRate> {
+    let sweep = volume
+        .sweeps_with(Moment::Reflectivity)
+        .min_by(|a, b| a.elevation_angle.total_cmp(&b.elevation_angle))
+        .ok_or_else(|| anyhow::anyhow!("Volume has no reflectivity sweeps"))?;
+    let reflectivity = sweep
+        .field(Moment::Reflectivity)
+        .ok_or_else(|| anyhow::anyhow!("Sweep has no reflectivity field"))?;
+
+    let (needs_zdr, needs_kdp) = options.relation.needs();
+    let zdr = if needs_zdr {
+        let field = sweep
+            .field(Moment::DifferentialReflectivity)
+            .ok_or_else(|| anyhow::anyhow!("The lowest sweep has no ZDR for R(Z, ZDR)"))?;
+        Some(field.aligned_to(&reflectivity))
+    } else {
+        None
+    };
+    let kdp = if needs_kdp {
+        let phi = sweep
+            .field(Moment::DifferentialPhase)
+            .ok_or_else(|| anyhow::anyhow!("The lowest sweep has no PHI for R(KDP)"))?;
+        let rho = sweep
+            .field(Moment::CorrelationCoefficient)
+            .map(|f| f.aligned_to(&phi));
+        let z = reflectivity.aligned_to(&phi);
+        let result = kdp_from_fields(&phi, rho.as_ref(), Some(&z), &options.kdp);
+        Some(result.kdp.aligned_to(&reflectivity))
+    } else {
+        None
+    };
+
+    Ok(RainRate {
+        rate: rain_rate_from_fields(&reflectivity, zdr.as_ref(), kdp.as_ref(), options),
+    })
+}
+
+pub fn rain_rate_from_fields(
+    reflectivity: &PolarField,
+    zdr: Option<&PolarField>,
+    kdp: Option<&PolarField>,
+    options: &QpeOptions,
+) -> PolarField {
+    let mut rate = reflectivity.filled_like(0.0);
+    for (r, radial) in rate.data.iter_mut().enumerate() {
+        for (g, value) in radial.iter_mut().enumerate() {
+            let z = reflectivity.get(r, g);
+            if !(z.is_finite() && z >= options.min_reflectivity) {
+                continue;
+            }
+            let z = z.min(options.max_reflectivity);
+            let zdr = zdr.map_or(0.0, |f| f.get(r, g));
+            let kdp = kdp.map_or(0.0, |f| f.get(r, g));
+            let rain = options.relation.rate(z, zdr, kdp);
+            // Fall back to the convective Z-R where a dual-pol input is missing.
+            *value = if rain.is_finite() {
+                rain
+            } else {
+                RainRelation::Convective.rate(z, 0.0, 0.0)
+            };
+        }
+    }
+    rate
+}
+
+// Integrates consecutive rain rate fields over [start, end], treating the rate
+// at each gate as varying linearly between the two radials' collection times.
+pub fn accumulate(
+    rates: &[RainRate],
+    start: f64,
+    end: f64,
+    options: &QpeOptions,
+) -> anyhow::Result<Accumulation> {
+    let mut sorted: Vec<&RainRate> = rates.iter().collect();
+    sorted.sort_by(|a, b| mean_time(&a.rate).total_cmp(&mean_time(&b.rate)));
+    let first = sorted
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No rain rate fields to accumulate"))?;
+
+    let target = &first.rate;
+    let mut depth = target.filled_like(0.0);
+
+    for pair in sorted.windows(2) {
+        let a = pair[0].rate.aligned_to(target);
+        let b = pair[1].rate.aligned_to(target);
+        let a_times = radial_times(&pair[0].rate, target);
+        let b_times = radial_times(&pair[1].rate, target);
+
+        for r in 0..target.n_radials() {
+            let (t0, t1) = (a_times[r], b_times[r]);
+            if t0.is_nan() || t1.is_nan() || t1 <= t0 || t1 - t0 > options.max_gap {
+                continue;
+            }
+            let lo = t0.max(start);
+            let hi = t1.min(end);
+            if hi <= lo {
+                continue;
+            }
+            let w_lo = ((lo - t0) / (t1 - t0)) as f32;
+            let w_hi = ((hi - t0) / (t1 - t0)) as f32;
+            let hours = ((hi - lo) / 3600.0) as f32;
+
+            for g in 0..target.n_gates() {
+                let (ra, rb) = (a.get(r, g), b.get(r, g));
+                if !(ra.is_finite() && rb.is_finite()) {
+                    continue;
+                }
+                let rate_lo = ra + (rb - ra) * w_lo;
+                let rate_hi = ra + (rb - ra) * w_hi;
+                depth.data[r][g] += 0.5 * (rate_lo + rate_hi) * hours;
+            }
+        }
+    }
+
+    Ok(Accumulation { start, end, depth })
+}
+
+// Accumulation over the whole sequence.
+pub fn storm_total(rates: &[RainRate], options: &QpeOptions) -> anyhow::Result<Accumulation> {
+    let times: Vec<f64> = rates.iter().flat_map(|r| r.rate.times.clone()).collect();
+    let start = times.iter().copied().reduce(f64::min).unwrap_or_default();
+    let end = times.iter().copied().reduce(f64::max).unwrap_or_default();
+    accumulate(rates, start, end, options)
+}
+
+// Accumulation over the hour ending at `end`.
+pub fn one_hour(
+    rates: &[RainRate],
+    end: f64,
+    options: &QpeOptions,
+) -> anyhow::Result<Accumulation> {
+    accumulate(rates, end - 3600.0, end, options)
+}
+
+fn mean_time(field: &PolarField) -> f64 {
+    if field.times.is_empty() {
+        return 0.0;
+    }
+    field.times.iter().sum::<f64>() / field.times.len() as f64
+}
+
+// Collection time of the radial in `field` nearest each radial of `target`.
+fn radial_times(field: &PolarField, target: &PolarField) -> Vec<f64> {
+    target
+        .azimuths
+        .iter()
+        .map(|&az| {
+            field
+                .radial_at_azimuth(az)
+                .map_or(f64::NAN, |r| field.times[r])
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kdp_rate_ignores_negative_kdp() {
+        let relation = RainRelation::SpecificDifferentialPhase;
+        assert_eq!(relation.rate(30.0, 0.0, -0.5), 0.0);
+        assert_eq!(relation.rate(30.0, 0.0, 0.0), 0.0);
+        assert!((relation.rate(30.0, 0.0, 1.0) - 44.0).abs() < 1e-4);
+        assert!(relation.rate(30.0, 0.0, f32::NAN).is_nan());
+    }
+}
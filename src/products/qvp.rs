@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+
+use crate::geometry::beam_height;
+use crate::products::{db_to_linear, finite_mean, linear_to_db};
+use crate::volume::{Moment, Volume};
+
+// Quasi-vertical profiles (Ryzhkov et al. 2016): azimuthal averages of the
+// dual-pol moments on a high elevation sweep, plotted against beam height.
+
+#[derive(Debug, Clone)]
+pub struct QvpOptions {
+    pub elevation: f32,
+    // Fraction of radials that must hold valid data for a gate to be averaged.
+    pub min_valid_fraction: f32,
+    pub moments: Vec<Moment>,
+}
+
+impl Default for QvpOptions {
+    fn default() -> Self {
+        QvpOptions {
+            elevation: 19.5,
+            min_valid_fraction: 0.3,
+            moments: vec![
+                Moment::Reflectivity,
+                Moment::DifferentialReflectivity,
+                Moment::CorrelationCoefficient,
+                Moment::DifferentialPhase,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuasiVerticalProfile {
+    pub time: f64,
+    pub elevation: f32,
+    pub heights: Vec<f32>, // metres above sea level
+    pub profiles: BTreeMap<Moment, Vec<f32>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QvpTimeSeries {
+    pub times: Vec<f64>,
+    pub heights: Vec<f32>,
+    // [time][height] for each moment
+    pub profiles: BTreeMap<Moment, Vec<Vec<f32>>>,
+}
+
+// Reflectivity and ZDR are averaged in linear units, the others as-is.
+fn averages_linearly(moment: Moment) -> bool {
+    matches!(
+        moment,
+        Moment::Reflectivity | Moment::DifferentialReflectivity
+    )
+}
+
+pub fn quasi_vertical_profile(
+    volume: &Volume,
+    options: &QvpOptions,
+) -> anyhow::Result<QuasiVerticalProfile> {
+    // Heights are of the beam centre, so they start from the feedhorn like
+    // the antenna altitude in the exporters.
+    let site_height = volume
+        .site()
+        .map_or(0.0, |s| s.site_height as f32 + s.feedhorn_height as f32);
+    let first_moment = options
+        .moments
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No moments requested for the QVP"))?;
+    let sweep = volume
+        .nearest_sweep(*first_moment, options.elevation)
+        .ok_or_else(|| anyhow::anyhow!("No sweep carries {}", first_moment.block_name()))?;
+
+    let fields: Vec<_> = options
+        .moments
+        .iter()
+        .filter_map(|&moment| Some((moment, sweep.field(moment)?)))
+        .collect();
+    // Every moment is resampled onto the radials and gates of the one reaching
+    // furthest, so each profile level averages the same gates.
+    let Some(reference) = fields
+        .iter()
+        .map(|(_, field)| field)
+        .max_by_key(|field| field.n_gates())
+        .cloned()
+    else {
+        anyhow::bail!(
+            "Sweep at {:.1} degrees has none of the requested moments",
+            sweep.elevation_angle
+        );
+    };
+    let min_count = (reference.n_radials() as f32 * options.min_valid_fraction).ceil() as usize;
+
+    let mut profiles = BTreeMap::new();
+    for (moment, field) in fields {
+        let field = field.aligned_to(&reference);
+        let profile: Vec<f32> = (0..field.n_gates())
+            .map(|g| {
+                let column = field.data.iter().map(|radial| radial[g]);
+                if averages_linearly(moment) {
+                    linear_to_db(finite_mean(column.map(db_to_linear), min_count))
+                } else {
+                    finite_mean(column, min_count)
+                }
+            })
+            .collect();
+        profiles.insert(moment, profile);
+    }
+    let heights = (0..reference.n_gates())
+        .map(|g| {
+            site_height
+                + beam_height(reference.gate_range(g) as f64, sweep.elevation_angle as f64) as f32
+        })
+        .collect();
+
+    Ok(QuasiVerticalProfile {
+        time: sweep.start_time().unwrap_or_default(),
+        elevation: sweep.elevation_angle,
+        heights,
+        profiles,
+    })
+}
+
+// Builds a time-height QVP across volumes, interpolating every profile onto
+// the height levels of the first one.
+pub fn qvp_time_series(volumes: &[Volume], options: &QvpOptions) -> anyhow::Result<QvpTimeSeries> {
+    let mut qvps = volumes
+        .iter()
+        .map(|v| quasi_vertical_profile(v, options))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    qvps.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let heights = qvps
+        .first()
+        .map(|q| q.heights.clone())
+        .ok_or_else(|| anyhow::anyhow!("No volumes given for the QVP time series"))?;
+
+    let mut profiles: BTreeMap<Moment, Vec<Vec<f32>>> = BTreeMap::new();
+    for qvp in qvps.iter() {
+        for &moment in options.moments.iter() {
+            let column = match qvp.profiles.get(&moment) {
+                Some(profile) => heights
+                    .iter()
+                    .map(|&h| interpolate(&qvp.heights, profile, h))
+                    .collect(),
+                None => vec![f32::NAN; heights.len()],
+            };
+            profiles.entry(moment).or_default().push(column);
+        }
+    }
+
+    Ok(QvpTimeSeries {
+        times: qvps.iter().map(|q| q.time).collect(),
+        heights,
+        profiles,
+    })
+}
+
+// Linear interpolation on a monotonically increasing axis; NaN outside it.
+pub fn interpolate(xs: &[f32], ys: &[f32], x: f32) -> f32 {
+    let upper = xs.partition_point(|&v| v < x);
+    if upper == 0 {
+        return if xs.first() == Some(&x) {
+            ys[0]
+        } else {
+            f32::NAN
+        };
+    }
+    if upper >= xs.len() {
+        return f32::NAN;
+    }
+    let (x0, x1) = (xs[upper - 1], xs[upper]);
+    let (y0, y1) = (ys[upper - 1], ys[upper]);
+    if x1 == x0 {
+        return y0;
+    }
+    y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::synthetic::{self, FEEDHORN_HEIGHT, SITE_HEIGHT};
+
+    #[test]
+    fn moments_on_different_gates_share_heights() {
+        let zdr = |range: f32| range / 20_000.0;
+        let mut volume =
+            synthetic::volume(&[19.5], 400, 30.0, &[Moment::Reflectivity], |_, _, _, _| {
+                20.0
+            });
+        // ZDR on 500 m gates, as a coarser moment block would be.
+        let values: Vec<f32> = (0..200).map(|g| zdr(2_125.0 + g as f32 * 500.0)).collect();
+        for radial in volume.sweeps[0].radials.iter_mut() {
+            radial.moments.push(synthetic::moment_data(
+                Moment::DifferentialReflectivity,
+                &values,
+                2_125,
+                500,
+            ));
+        }
+
+        let options = QvpOptions {
+            moments: vec![Moment::Reflectivity, Moment::DifferentialReflectivity],
+            ..QvpOptions::default()
+        };
+        let qvp = quasi_vertical_profile(&volume, &options).unwrap();
+        assert_eq!(qvp.heights.len(), 400);
+        let antenna = SITE_HEIGHT as f32 + FEEDHORN_HEIGHT as f32;
+        let expected = antenna + beam_height(2_125.0, 19.5) as f32;
+        assert!(
+            (qvp.heights[0] - expected).abs() < 0.01,
+            "{}",
+            qvp.heights[0]
+        );
+
+        let profile = &qvp.profiles[&Moment::DifferentialReflectivity];
+        assert_eq!(profile.len(), 400);
+        for g in [0, 100, 250, 398] {
+            let range = 2_125.0 + g as f32 * 250.0;
+            assert!(
+                (profile[g] - zdr(range)).abs() < 0.05,
+                "gate {}: {}",
+                g,
+                profile[g]
+            );
+        }
+    }
+}
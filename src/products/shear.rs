@@ -0,0 +1,197 @@
+use crate::products::solve_linear;
+use crate::products::vad::VadProfile;
+use crate::volume::{angle_difference, PolarField};
+
+// Storm-relative velocity and linear least-squares derivatives (LLSD, Smith and
+// Elmore 2004) of radial velocity: azimuthal shear and radial divergence.
+
+// Storm motion vector in m/s, u towards east and v towards north.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StormMotion {
+    pub u: f32,
+    pub v: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StormMotionMethod {
+    // Mean wind over 0-6 km above the radar.
+    MeanWind,
+    // Bunkers et al. (2000) right mover: 7.5 m/s to the right of the
+    // 0-6 km shear vector from the mean wind.
+    BunkersRight,
+}
+
+const BUNKERS_DEVIATION: f32 = 7.5;
+
+impl StormMotion {
+    pub fn speed(&self) -> f32 {
+        self.u.hypot(self.v)
+    }
+
+    // Estimates storm motion from a VAD profile. `site_height` converts the
+    // profile's sea-level heights to heights above the radar.
+    pub fn estimate(
+        profile: &VadProfile,
+        site_height: f32,
+        method: StormMotionMethod,
+    ) -> anyhow::Result<StormMotion> {
+        let layer = |bottom: f32, top: f32| -> Option<(f32, f32)> {
+            let winds: Vec<(f32, f32)> = profile
+                .levels
+                .iter()
+                .filter(|l| l.height - site_height >= bottom && l.height - site_height <= top)
+                .map(|l| (l.u, l.v))
+                .collect();
+            if winds.is_empty() {
+                return None;
+            }
+            let n = winds.len() as f32;
+            Some((
+                winds.iter().map(|w| w.0).sum::<f32>() / n,
+                winds.iter().map(|w| w.1).sum::<f32>() / n,
+            ))
+        };
+
+        let (mean_u, mean_v) = layer(0.0, 6000.0)
+            .ok_or_else(|| anyhow::anyhow!("VAD profile has no winds below 6 km"))?;
+        match method {
+            StormMotionMethod::MeanWind => Ok(StormMotion {
+                u: mean_u,
+                v: mean_v,
+            }),
+            StormMotionMethod::BunkersRight => {
+                let (low_u, low_v) = layer(0.0, 500.0)
+                    .ok_or_else(|| anyhow::anyhow!("VAD profile has no winds below 500 m"))?;
+                let (high_u, high_v) = layer(5500.0, 6000.0)
+                    .ok_or_else(|| anyhow::anyhow!("VAD profile has no winds at 5.5-6 km"))?;
+                let (shear_u, shear_v) = (high_u - low_u, high_v - low_v);
+                let shear = shear_u.hypot(shear_v);
+                if shear == 0.0 {
+                    return Ok(StormMotion {
+                        u: mean_u,
+                        v: mean_v,
+                    });
+                }
+                Ok(StormMotion {
+                    u: mean_u + BUNKERS_DEVIATION * shear_v / shear,
+                    v: mean_v - BUNKERS_DEVIATION * shear_u / shear,
+                })
+            }
+        }
+    }
+}
+
+// Subtracts the radial component of the storm motion from every gate.
+pub fn storm_relative_velocity(velocity: &PolarField, motion: StormMotion) -> PolarField {
+    let cos_el = velocity.elevation.to_radians().cos();
+    let mut out = velocity.clone();
+    for (radial, &azimuth) in out.data.iter_mut().zip(velocity.azimuths.iter()) {
+        let az = azimuth.to_radians();
+        let storm_radial = (motion.u * az.sin() + motion.v * az.cos()) * cos_el;
+        for value in radial.iter_mut() {
+            *value -= storm_radial;
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone)]
+pub struct LlsdOptions {
+    // Full width of the fitting window across the radials, metres.
+    pub azimuthal_width: f32,
+    // Full depth of the fitting window along the radial, metres.
+    pub radial_depth: f32,
+    pub max_half_radials: usize,
+    pub min_points: usize,
+}
+
+impl Default for LlsdOptions {
+    fn default() -> Self {
+        LlsdOptions {
+            azimuthal_width: 2500.0,
+            radial_depth: 750.0,
+            max_half_radials: 10,
+            min_points: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShearProducts {
+    pub azimuthal_shear: PolarField, // s^-1
+    pub divergence: PolarField,      // s^-1
+}
+
+// Fits v = a + b * s + c * dr in a window around each gate, where s is the
+// arc distance across radials and dr the range offset. b is the azimuthal
+// shear and c the radial divergence.
+pub fn llsd_shear(velocity: &PolarField, options: &LlsdOptions) -> ShearProducts {
+    let n_radials = velocity.n_radials();
+    let n_gates = velocity.n_gates();
+    let half_gates = ((options.radial_depth / 2.0) / velocity.gate_spacing).round() as usize;
+
+    let spacing = mean_radial_spacing(velocity).to_radians();
+
+    let mut azimuthal_shear = velocity.filled_like(f32::NAN);
+    let mut divergence = velocity.filled_like(f32::NAN);
+
+    for r in 0..n_radials {
+        for g in 0..n_gates {
+            if !velocity.data[r][g].is_finite() {
+                continue;
+            }
+            let range = velocity.gate_range(g);
+            let radial_spacing = spacing * range;
+            let half_radials = if radial_spacing > 0.0 {
+                ((options.azimuthal_width / 2.0 / radial_spacing).round() as usize)
+                    .clamp(1, options.max_half_radials)
+            } else {
+                options.max_half_radials
+            };
+
+            let mut ata = vec![vec![0.0_f64; 3]; 3];
+            let mut atb = vec![0.0_f64; 3];
+            let mut count = 0;
+            for dr in -(half_radials as i64)..=half_radials as i64 {
+                let nr = (r as i64 + dr).rem_euclid(n_radials as i64) as usize;
+                let s = angle_difference(velocity.azimuths[nr], velocity.azimuths[r]).to_radians()
+                    as f64
+                    * range as f64;
+                for ng in g.saturating_sub(half_gates)..=(g + half_gates).min(n_gates - 1) {
+                    let v = velocity.data[nr][ng];
+                    if !v.is_finite() {
+                        continue;
+                    }
+                    let row = [1.0, s, (velocity.gate_range(ng) - range) as f64];
+                    for i in 0..3 {
+                        for j in 0..3 {
+                            ata[i][j] += row[i] * row[j];
+                        }
+                        atb[i] += row[i] * v as f64;
+                    }
+                    count += 1;
+                }
+            }
+            if count < options.min_points {
+                continue;
+            }
+            if let Some(x) = solve_linear(ata, atb) {
+                azimuthal_shear.data[r][g] = x[1] as f32;
+                divergence.data[r][g] = x[2] as f32;
+            }
+        }
+    }
+
+    ShearProducts {
+        azimuthal_shear,
+        divergence,
+    }
+}
+
+// Average angular spacing between radials, degrees.
+pub fn mean_radial_spacing(field: &PolarField) -> f32 {
+    if field.n_radials() == 0 {
+        return 0.0;
+    }
+    360.0 / field.n_radials() as f32
+}
@@ -0,0 +1,314 @@
+use crate::geometry::slant_range_at_height;
+use crate::products::{solve_linear, wind_direction};
+use crate::qc::dealias::{DealiasedVelocity, ReferenceWind};
+use crate::volume::{Moment, PolarField, Volume};
+
+// Velocity-azimuth display wind retrieval. On each tilt the radial velocity
+// around a range ring is fitted with v = c0 + c1 cos(az) + c2 sin(az); the
+// harmonic terms give the horizontal wind at the ring's height. Each height
+// level keeps the best fit over all tilts.
+
+#[derive(Debug, Clone)]
+pub struct VadOptions {
+    pub min_elevation: f32,
+    pub max_elevation: f32,
+    pub min_range: f32, // metres
+    pub max_range: f32,
+    // Spacing of the output height levels, metres above sea level.
+    pub level_spacing: f32,
+    pub max_height: f32,
+    pub min_points: usize,
+    pub max_azimuth_gap: f32, // degrees
+    pub max_rms: f32,         // m/s
+}
+
+impl Default for VadOptions {
+    fn default() -> Self {
+        VadOptions {
+            min_elevation: 1.0,
+            max_elevation: 20.0,
+            min_range: 5_000.0,
+            max_range: 60_000.0,
+            level_spacing: 304.8,
+            max_height: 15_240.0,
+            min_points: 25,
+            max_azimuth_gap: 30.0,
+            max_rms: 4.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VadLevel {
+    pub height: f32, // metres above sea level
+    pub elevation: f32,
+    pub slant_range: f32,
+    pub u: f32,
+    pub v: f32,
+    pub speed: f32,
+    pub direction: f32,
+    pub rms: f32,
+    // Coefficient of determination of the sinusoid fit.
+    pub r_squared: f32,
+    pub n_points: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct VadProfile {
+    pub time: f64,
+    pub levels: Vec<VadLevel>,
+}
+
+impl VadProfile {
+    pub fn reference_winds(&self) -> Vec<ReferenceWind> {
+        self.levels
+            .iter()
+            .map(|l| ReferenceWind {
+                height: l.height,
+                u: l.u,
+                v: l.v,
+            })
+            .collect()
+    }
+}
+
+// Retrieves a VAD profile from one volume. With `dealiased` velocities the fit
+// is a plain least-squares solve; with raw velocities it is made robust to
+// folding by first searching for the wind that best explains the folded data.
+pub fn vad_profile(
+    volume: &Volume,
+    dealiased: Option<&[DealiasedVelocity]>,
+    options: &VadOptions,
+) -> anyhow::Result<VadProfile> {
+    let site_height = volume.site().map_or(0.0, |s| s.site_height as f32);
+
+    let tilts: Vec<(PolarField, Option<f32>)> = match dealiased {
+        Some(fields) => fields.iter().map(|d| (d.velocity.clone(), None)).collect(),
+        None => volume
+            .sweeps_with(Moment::Velocity)
+            .filter_map(|s| Some((s.field(Moment::Velocity)?, s.nyquist_velocity())))
+            .collect(),
+    };
+    if tilts.is_empty() {
+        anyhow::bail!("Volume has no velocity sweeps");
+    }
+
+    let n_levels = (options.max_height / options.level_spacing).floor() as usize;
+    let mut levels = Vec::new();
+    for level in 1..=n_levels {
+        let height = level as f32 * options.level_spacing;
+        if height <= site_height {
+            continue;
+        }
+
+        let best = tilts
+            .iter()
+            .filter(|(f, _)| {
+                f.elevation >= options.min_elevation && f.elevation <= options.max_elevation
+            })
+            .filter_map(|(field, nyquist)| {
+                let range = find_ring_range(field, height - site_height)?;
+                if range < options.min_range || range > options.max_range {
+                    return None;
+                }
+                let fit = fit_ring(field, range, *nyquist, options)?;
+                Some(VadLevel { height, ..fit })
+            })
+            .min_by(|a, b| a.rms.total_cmp(&b.rms));
+
+        if let Some(best) = best {
+            levels.push(best);
+        }
+    }
+
+    Ok(VadProfile {
+        time: volume.start_time().unwrap_or_default(),
+        levels,
+    })
+}
+
+// A velocity wind profile (VWP) time series over a sequence of volumes.
+pub fn vad_time_series(
+    volumes: &[Volume],
+    options: &VadOptions,
+) -> anyhow::Result<Vec<VadProfile>> {
+    let mut profiles = volumes
+        .iter()
+        .map(|v| vad_profile(v, None, options))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    profiles.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(profiles)
+}
+
+fn find_ring_range(field: &PolarField, height_above_radar: f32) -> Option<f32> {
+    let range = slant_range_at_height(height_above_radar as f64, field.elevation as f64);
+    let last = field.gate_range(field.n_gates().saturating_sub(1)) as f64;
+    (range.is_finite() && range > 0.0 && range <= last).then_some(range as f32)
+}
+
+fn fit_ring(
+    field: &PolarField,
+    range: f32,
+    nyquist: Option<f32>,
+    options: &VadOptions,
+) -> Option<VadLevel> {
+    let gate = field.gate_at_range(range)?;
+    let mut points: Vec<(f32, f32)> = field
+        .azimuths
+        .iter()
+        .zip(field.data.iter())
+        .filter_map(|(&az, radial)| {
+            let v = radial[gate];
+            v.is_finite().then_some((az, v))
+        })
+        .collect();
+    if points.len() < options.min_points || azimuth_gap(&points) > options.max_azimuth_gap {
+        return None;
+    }
+
+    if let Some(nyquist) = nyquist {
+        let (c1, c2) = folded_search(&points, nyquist);
+        let interval = 2.0 * nyquist;
+        for (az, v) in points.iter_mut() {
+            let model = c1 * az.to_radians().cos() + c2 * az.to_radians().sin();
+            *v += ((model - *v) / interval).round() * interval;
+        }
+    }
+
+    let (c0, c1, c2) = least_squares_sinusoid(&points)?;
+    let residuals: Vec<f32> = points
+        .iter()
+        .map(|&(az, v)| v - (c0 + c1 * az.to_radians().cos() + c2 * az.to_radians().sin()))
+        .collect();
+    let n = points.len() as f32;
+    let ss_res: f32 = residuals.iter().map(|r| r * r).sum();
+    let mean = points.iter().map(|(_, v)| v).sum::<f32>() / n;
+    let ss_tot: f32 = points.iter().map(|(_, v)| (v - mean).powi(2)).sum();
+    let rms = (ss_res / n).sqrt();
+    if rms > options.max_rms {
+        return None;
+    }
+
+    let cos_el = field.elevation.to_radians().cos();
+    let u = c2 / cos_el;
+    let v = c1 / cos_el;
+    Some(VadLevel {
+        height: 0.0,
+        elevation: field.elevation,
+        slant_range: range,
+        u,
+        v,
+        speed: u.hypot(v),
+        direction: wind_direction(u, v),
+        rms,
+        r_squared: if ss_tot > 0.0 {
+            1.0 - ss_res / ss_tot
+        } else {
+            0.0
+        },
+        n_points: points.len(),
+    })
+}
+
+// Largest azimuthal gap between consecutive valid points, including the wrap.
+fn azimuth_gap(points: &[(f32, f32)]) -> f32 {
+    let mut azimuths: Vec<f32> = points.iter().map(|(az, _)| *az).collect();
+    azimuths.sort_by(f32::total_cmp);
+    let wrap = azimuths
+        .first()
+        .zip(azimuths.last())
+        .map_or(360.0, |(first, last)| first + 360.0 - last);
+    azimuths
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .fold(wrap, f32::max)
+}
+
+fn least_squares_sinusoid(points: &[(f32, f32)]) -> Option<(f32, f32, f32)> {
+    let mut ata = vec![vec![0.0_f64; 3]; 3];
+    let mut atb = vec![0.0_f64; 3];
+    for &(az, v) in points {
+        let row = [
+            1.0,
+            (az as f64).to_radians().cos(),
+            (az as f64).to_radians().sin(),
+        ];
+        for i in 0..3 {
+            for j in 0..3 {
+                ata[i][j] += row[i] * row[j];
+            }
+            atb[i] += row[i] * v as f64;
+        }
+    }
+    let x = solve_linear(ata, atb)?;
+    Some((x[0] as f32, x[1] as f32, x[2] as f32))
+}
+
+// Coarse-to-fine search for the harmonic coefficients minimising the residual
+// after folding the model into the Nyquist interval.
+fn folded_search(points: &[(f32, f32)], nyquist: f32) -> (f32, f32) {
+    let interval = 2.0 * nyquist;
+    // (cos, sin, velocity) per point, shared by every grid node.
+    let harmonics: Vec<(f32, f32, f32)> = points
+        .iter()
+        .map(|&(az, v)| {
+            let (sin, cos) = az.to_radians().sin_cos();
+            (cos, sin, v)
+        })
+        .collect();
+    let cost = |c1: f32, c2: f32| -> f32 {
+        harmonics
+            .iter()
+            .map(|&(cos, sin, v)| {
+                let model = c1 * cos + c2 * sin;
+                let diff = v - model;
+                let diff = diff - (diff / interval).round() * interval;
+                diff * diff
+            })
+            .sum()
+    };
+
+    let mut best = (0.0, 0.0);
+    let mut best_cost = f32::INFINITY;
+    let mut step = 4.0;
+    let mut span = 80.0;
+    while step >= 0.25 {
+        let n = (span / step) as i32;
+        let centre = best;
+        for i in -n..=n {
+            for j in -n..=n {
+                let c1 = centre.0 + i as f32 * step;
+                let c2 = centre.1 + j as f32 * step;
+                let c = cost(c1, c2);
+                if c < best_cost {
+                    best_cost = c;
+                    best = (c1, c2);
+                }
+            }
+        }
+        span = step * 2.0;
+        step /= 4.0;
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folded_search_recovers_folded_wind() {
+        // 25 m/s harmonics seen with a 10 m/s Nyquist velocity.
+        let (nyquist, c1, c2) = (10.0_f32, -18.0_f32, 17.0_f32);
+        let points: Vec<(f32, f32)> = (0..360)
+            .step_by(2)
+            .map(|az| {
+                let az = az as f32;
+                let v = c1 * az.to_radians().cos() + c2 * az.to_radians().sin();
+                (az, v - (v / (2.0 * nyquist)).round() * 2.0 * nyquist)
+            })
+            .collect();
+        let (a, b) = folded_search(&points, nyquist);
+        assert!((a - c1).abs() < 0.5 && (b - c2).abs() < 0.5, "{} {}", a, b);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::products::db_to_linear;
+use crate::products::kdp::{kdp_from_fields, KdpOptions};
+use crate::volume::{Moment, MomentData, PolarField, Sweep, Volume, GATE_RANGE_FOLDED};
+
+// Rain attenuation correction of reflectivity and ZDR from the processed
+// PHIDP profile of each radial, either linearly in PHIDP or with the ZPHI
+// method of Testud et al. (2000), which distributes the total PHIDP-derived
+// attenuation along the path in proportion to Z^b.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttenuationMethod {
+    LinearPhidp,
+    Zphi { b: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct AttenuationOptions {
+    pub method: AttenuationMethod,
+    // Specific attenuation per degree of PHIDP, dB/deg (A_H = alpha K_DP).
+    pub alpha: f32,
+    // Specific differential attenuation per degree of PHIDP, dB/deg.
+    pub beta: f32,
+    // Upper bound on the two-way path integrated attenuation, dB.
+    pub max_pia: f32,
+    pub kdp: KdpOptions,
+}
+
+impl Default for AttenuationOptions {
+    fn default() -> Self {
+        // S-band coefficients (Bringi and Chandrasekar 2001).
+        AttenuationOptions {
+            method: AttenuationMethod::Zphi { b: 0.78 },
+            alpha: 0.04,
+            beta: 0.004,
+            max_pia: 10.0,
+            kdp: KdpOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AttenuationCorrected {
+    pub reflectivity: PolarField,
+    pub zdr: Option<PolarField>,
+    // Two-way path integrated attenuation applied to reflectivity, dB.
+    pub pia: PolarField,
+}
+
+pub fn correct_attenuation(
+    sweep: &Sweep,
+    options: &AttenuationOptions,
+) -> anyhow::Result<AttenuationCorrected> {
+    let z = sweep
+        .field(Moment::Reflectivity)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no reflectivity", sweep.elevation_number))?;
+    let phi = sweep
+        .field(Moment::DifferentialPhase)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no PHI", sweep.elevation_number))?;
+    let rho = sweep
+        .field(Moment::CorrelationCoefficient)
+        .map(|f| f.aligned_to(&phi));
+    let phidp = kdp_from_fields(&phi, rho.as_ref(), Some(&z.aligned_to(&phi)), &options.kdp)
+        .phidp
+        .aligned_to(&z);
+    let zdr = sweep
+        .field(Moment::DifferentialReflectivity)
+        .map(|f| f.aligned_to(&z));
+
+    Ok(correct_attenuation_fields(
+        &z,
+        zdr.as_ref(),
+        &phidp,
+        options,
+    ))
+}
+
+// `phidp` is the processed, offset-free differential phase on the reflectivity
+// geometry.
+pub fn correct_attenuation_fields(
+    reflectivity: &PolarField,
+    zdr: Option<&PolarField>,
+    phidp: &PolarField,
+    options: &AttenuationOptions,
+) -> AttenuationCorrected {
+    let mut pia = reflectivity.filled_like(0.0);
+    let spacing_km = reflectivity.gate_spacing / 1000.0;
+
+    for (r, pia_row) in pia.data.iter_mut().enumerate() {
+        let path: Vec<usize> = (0..reflectivity.n_gates())
+            .filter(|&g| reflectivity.get(r, g).is_finite() && phidp.get(r, g).is_finite())
+            .collect();
+        let (Some(&first), Some(&last)) = (path.first(), path.last()) else {
+            continue;
+        };
+
+        match options.method {
+            AttenuationMethod::LinearPhidp => {
+                let mut running = 0.0_f32;
+                for (g, value) in pia_row.iter_mut().enumerate().skip(first) {
+                    let phase = phidp.get(r, g);
+                    if phase.is_finite() {
+                        running = running.max(options.alpha * phase);
+                    }
+                    *value = running;
+                }
+            }
+            AttenuationMethod::Zphi { b } => {
+                let delta_phi = phidp.get(r, last) - phidp.get(r, first);
+                if delta_phi <= 0.0 {
+                    continue;
+                }
+                // Z^b in linear units along the path, zero in gaps.
+                let zb: Vec<f32> = (0..reflectivity.n_gates())
+                    .map(|g| {
+                        let z = reflectivity.get(r, g);
+                        if g >= first && g <= last && z.is_finite() {
+                            db_to_linear(z).powf(b)
+                        } else {
+                            0.0
+                        }
+                    })
+                    .collect();
+                // I(r, r1) = 0.46 b * integral from r to r1 of Z^b ds.
+                let mut tail = vec![0.0_f32; zb.len() + 1];
+                for g in (0..zb.len()).rev() {
+                    tail[g] = tail[g + 1] + 0.46 * b * zb[g] * spacing_km;
+                }
+                let factor = 10f32.powf(0.1 * b * options.alpha * delta_phi) - 1.0;
+                let total = tail[first];
+
+                let mut running = 0.0_f32;
+                for (g, value) in pia_row.iter_mut().enumerate().skip(first) {
+                    let denominator = total + factor * tail[g];
+                    if g <= last && denominator > 0.0 {
+                        let specific = zb[g] * factor / denominator;
+                        running += 2.0 * specific * spacing_km;
+                    }
+                    *value = running;
+                }
+            }
+        }
+
+        for value in pia_row.iter_mut() {
+            *value = value.min(options.max_pia);
+        }
+    }
+
+    let mut corrected = reflectivity.clone();
+    add_fields(&mut corrected, &pia, 1.0);
+    let corrected_zdr = zdr.map(|zdr| {
+        let mut out = zdr.clone();
+        add_fields(&mut out, &pia, options.beta / options.alpha);
+        out
+    });
+
+    AttenuationCorrected {
+        reflectivity: corrected,
+        zdr: corrected_zdr,
+        pia,
+    }
+}
+
+fn add_fields(target: &mut PolarField, pia: &PolarField, factor: f32) {
+    for (r, radial) in target.data.iter_mut().enumerate() {
+        for (g, value) in radial.iter_mut().enumerate() {
+            let correction = pia.get(r, g);
+            if value.is_finite() && correction.is_finite() {
+                *value += factor * correction;
+            }
+        }
+    }
+}
+
+// A copy of the volume with REF and ZDR corrected in place on every sweep that
+// has PHI, re-encoded with each block's own scale and offset.
+pub fn correct_volume(volume: &Volume, options: &AttenuationOptions) -> Volume {
+    let mut corrected = volume.clone();
+    for sweep in corrected.sweeps.iter_mut() {
+        let Ok(result) = correct_attenuation(sweep, options) else {
+            continue;
+        };
+        for radial in sweep.radials.iter_mut() {
+            let Some(r) = result
+                .reflectivity
+                .radial_at_azimuth(radial.header.azimuth_angle)
+            else {
+                continue;
+            };
+            for moment in radial.moments.iter_mut() {
+                let factor = match moment.moment {
+                    Moment::Reflectivity => 1.0,
+                    Moment::DifferentialReflectivity => options.beta / options.alpha,
+                    _ => continue,
+                };
+                encode_correction(moment, &result.pia, r, factor);
+            }
+        }
+    }
+    corrected
+}
+
+fn encode_correction(moment: &mut MomentData, pia: &PolarField, radial: usize, factor: f32) {
+    let max_raw = if moment.header.data_word_size == 16 {
+        u16::MAX
+    } else {
+        u8::MAX as u16
+    };
+    let first = moment.first_gate_range();
+    let spacing = moment.gate_spacing();
+    for g in 0..moment.gates.len() {
+        let (Some(value), Some(pg)) = (
+            moment.value(g),
+            pia.gate_at_range(first + g as f32 * spacing),
+        ) else {
+            continue;
+        };
+        let correction = pia.get(radial, pg);
+        if !correction.is_finite() {
+            continue;
+        }
+        let raw = ((value + factor * correction) * moment.header.scale + moment.header.offset)
+            .round()
+            .clamp((GATE_RANGE_FOLDED + 1) as f32, max_raw as f32);
+        moment.gates[g] = raw as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume::synthetic;
+
+    const KDP: f32 = 1.0;
+
+    // Rain with a steady 1 deg/km KDP from a 30 degree system phase; the
+    // first ten degrees of azimuth are near the top of the REF code range.
+    fn rain() -> Volume {
+        let moments = [
+            Moment::Reflectivity,
+            Moment::DifferentialReflectivity,
+            Moment::DifferentialPhase,
+            Moment::CorrelationCoefficient,
+        ];
+        synthetic::volume(
+            &[0.5],
+            400,
+            30.0,
+            &moments,
+            |moment, _, azimuth, range| match moment {
+                Moment::Reflectivity if azimuth < 10.0 => 90.0,
+                Moment::Reflectivity => 30.0,
+                Moment::DifferentialReflectivity => 1.0,
+                Moment::DifferentialPhase => 30.0 + 2.0 * KDP * range / 1000.0,
+                _ => 0.99,
+            },
+        )
+    }
+
+    fn linear() -> AttenuationOptions {
+        AttenuationOptions {
+            method: AttenuationMethod::LinearPhidp,
+            ..AttenuationOptions::default()
+        }
+    }
+
+    #[test]
+    fn linear_phidp_correction() {
+        let volume = rain();
+        let options = linear();
+        let result = correct_attenuation(&volume.sweeps[0], &options).unwrap();
+        let zdr = result.zdr.as_ref().unwrap();
+
+        // The system offset is taken a few gates out, so the phase rise and
+        // the attenuation are counted from there.
+        let r = result.reflectivity.radial_at_azimuth(180.0).unwrap();
+        let origin = result.reflectivity.gate_range(5);
+        for g in [20, 100, 250, 399] {
+            let range = result.reflectivity.gate_range(g);
+            let pia = options.alpha * 2.0 * KDP * (range - origin) / 1000.0;
+            assert!((result.pia.get(r, g) - pia).abs() < 0.05, "gate {}", g);
+            let z = result.reflectivity.get(r, g);
+            assert!((z - (30.0 + pia)).abs() < 0.05, "gate {}: {}", g, z);
+            let corrected_zdr = zdr.get(r, g);
+            let expected = 1.0 + options.beta / options.alpha * pia;
+            assert!((corrected_zdr - expected).abs() < 0.01, "gate {}", g);
+        }
+        assert!(result.pia.get(r, 399) > 7.0);
+    }
+
+    #[test]
+    fn corrected_codes_stay_in_range() {
+        let volume = rain();
+        let options = linear();
+        let result = correct_attenuation(&volume.sweeps[0], &options).unwrap();
+        let corrected = correct_volume(&volume, &options);
+
+        for radial in corrected.sweeps[0].radials.iter() {
+            let r = result
+                .reflectivity
+                .radial_at_azimuth(radial.header.azimuth_angle)
+                .unwrap();
+            for moment in [Moment::Reflectivity, Moment::DifferentialReflectivity] {
+                let data = radial.moment(moment).unwrap();
+                let max = (u8::MAX as f32 - data.header.offset) / data.header.scale;
+                for g in 0..data.gates.len() {
+                    let raw = data.gates[g];
+                    assert!(raw > GATE_RANGE_FOLDED && raw <= u8::MAX as u16);
+                    let value = data.value(g).unwrap();
+                    let expected = match moment {
+                        Moment::Reflectivity => result.reflectivity.get(r, g),
+                        _ => result.zdr.as_ref().unwrap().get(r, g),
+                    };
+                    let step = 1.0 / data.header.scale;
+                    assert!(
+                        (value - expected.min(max)).abs() <= step,
+                        "{:?} gate {}",
+                        moment,
+                        g
+                    );
+                }
+            }
+        }
+
+        // The hot sector saturates at the top code instead of wrapping.
+        let hot = corrected.sweeps[0].radials[0]
+            .moment(Moment::Reflectivity)
+            .unwrap();
+        assert_eq!(hot.gates[399], u8::MAX as u16);
+    }
+}
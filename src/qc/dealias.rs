@@ -0,0 +1,618 @@
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::geometry::beam_height;
+use crate::volume::{Moment, PolarField, Sweep, Volume};
+
+// Region-based velocity unfolding. Gates are split into Nyquist sub-intervals
+// and grouped into connected regions; regions are then folded into agreement
+// with their already-unfolded neighbours, largest shared boundary first.
+// Seed regions are matched against a reference (wind profile or previous
+// volume) when one is available.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DealiasFlag {
+    NoData,
+    Confident,
+    // Unfolded, but the region was small or disagreed with its neighbours or the reference.
+    Uncertain,
+    // A region with no neighbours and no reference; left as measured.
+    Isolated,
+}
+
+// Wind at one height (metres above sea level), components in m/s.
+#[derive(Debug, Clone, Copy)]
+pub struct ReferenceWind {
+    pub height: f32,
+    pub u: f32,
+    pub v: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum DealiasReference {
+    #[default]
+    None,
+    WindProfile(Vec<ReferenceWind>),
+    // An already dealiased velocity field from the previous volume.
+    Previous(PolarField),
+}
+
+#[derive(Debug, Clone)]
+pub struct DealiasOptions {
+    pub interval_splits: usize,
+    pub min_region_size: usize,
+    pub reference: DealiasReference,
+}
+
+impl Default for DealiasOptions {
+    fn default() -> Self {
+        DealiasOptions {
+            interval_splits: 3,
+            min_region_size: 10,
+            reference: DealiasReference::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DealiasedVelocity {
+    pub nyquist: f32,
+    pub velocity: PolarField,
+    pub flags: Vec<Vec<DealiasFlag>>,
+}
+
+pub fn dealias_sweep(
+    sweep: &Sweep,
+    site_height: f32,
+    options: &DealiasOptions,
+) -> anyhow::Result<DealiasedVelocity> {
+    let field = sweep
+        .field(Moment::Velocity)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no velocity data", sweep.elevation_number))?;
+    let nyquist = sweep
+        .nyquist_velocity()
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no RAD block", sweep.elevation_number))?;
+    dealias_field(&field, nyquist, site_height, options)
+}
+
+// Dealiases every velocity sweep in a volume. Each sweep is seeded from the
+// closest-elevation sweep of `previous` if given, otherwise from the profile.
+pub fn dealias_volume(
+    volume: &Volume,
+    wind_profile: Option<&[ReferenceWind]>,
+    previous: Option<&[DealiasedVelocity]>,
+) -> anyhow::Result<Vec<DealiasedVelocity>> {
+    let site_height = volume.site().map_or(0.0, |s| s.site_height as f32);
+
+    volume
+        .sweeps_with(Moment::Velocity)
+        .map(|sweep| {
+            let previous_sweep = previous.and_then(|p| {
+                p.iter()
+                    .filter(|d| (d.velocity.elevation - sweep.elevation_angle).abs() < 0.3)
+                    .min_by(|a, b| {
+                        (a.velocity.elevation - sweep.elevation_angle)
+                            .abs()
+                            .total_cmp(&(b.velocity.elevation - sweep.elevation_angle).abs())
+                    })
+            });
+            let reference = match (previous_sweep, wind_profile) {
+                (Some(p), _) => DealiasReference::Previous(p.velocity.clone()),
+                (None, Some(profile)) => DealiasReference::WindProfile(profile.to_vec()),
+                (None, None) => DealiasReference::None,
+            };
+            let options = DealiasOptions {
+                reference,
+                ..DealiasOptions::default()
+            };
+            dealias_sweep(sweep, site_height, &options)
+        })
+        .collect()
+}
+
+pub fn dealias_field(
+    field: &PolarField,
+    nyquist: f32,
+    site_height: f32,
+    options: &DealiasOptions,
+) -> anyhow::Result<DealiasedVelocity> {
+    if nyquist <= 0.0 {
+        anyhow::bail!("Nyquist velocity must be positive, got {}", nyquist);
+    }
+    let interval = 2.0 * nyquist;
+    let reference = reference_field(field, site_height, &options.reference);
+    let (labels, regions) = label_regions(field, nyquist, options.interval_splits.max(1));
+    let edges = region_edges(field, &labels);
+
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); regions.len()];
+    for &(a, b) in edges.keys() {
+        neighbours[a].push(b);
+        neighbours[b].push(a);
+    }
+
+    // Per region: accumulated (sum of required shifts, boundary gate count).
+    let mut pending: Vec<(f64, usize)> = vec![(0.0, 0); regions.len()];
+    let mut offsets: Vec<Option<f32>> = vec![None; regions.len()];
+    let mut flags_by_region = vec![DealiasFlag::Confident; regions.len()];
+
+    let reference_shifts = match &reference {
+        Some(r) => region_mean_differences(field, &labels, regions.len(), r),
+        None => vec![None; regions.len()],
+    };
+
+    let mut by_size: Vec<usize> = (0..regions.len()).collect();
+    by_size.sort_by(|&a, &b| regions[b].size.cmp(&regions[a].size));
+
+    for &seed in by_size.iter() {
+        if offsets[seed].is_some() {
+            continue;
+        }
+        let seed_offset = reference_shifts[seed].map(|s| (s / interval).round() * interval);
+        if seed_offset.is_none() && neighbours[seed].is_empty() {
+            flags_by_region[seed] = DealiasFlag::Isolated;
+        }
+        let mut frontier = BinaryHeap::new();
+        let mut group = vec![seed];
+        assign(
+            seed,
+            seed_offset.unwrap_or(0.0),
+            &mut offsets,
+            &mut pending,
+            &mut frontier,
+            &neighbours,
+            &edges,
+        );
+
+        // Grow outwards from the seed, always taking the unassigned region with
+        // the longest boundary against the assigned set. Heap entries go stale
+        // as boundaries grow, so only the one matching `pending` is used.
+        while let Some((count, region)) = frontier.pop() {
+            if offsets[region].is_some() || pending[region].1 != count {
+                continue;
+            }
+
+            let sum = pending[region].0;
+            let shift = (sum / count as f64) as f32;
+            let offset = (shift / interval).round() * interval;
+            if (shift - offset).abs() > nyquist / 2.0 {
+                flags_by_region[region] = DealiasFlag::Uncertain;
+            }
+            assign(
+                region,
+                offset,
+                &mut offsets,
+                &mut pending,
+                &mut frontier,
+                &neighbours,
+                &edges,
+            );
+            group.push(region);
+        }
+
+        // Without a reference the group is only known up to a whole number of
+        // intervals; pick the one that puts its mean velocity nearest zero.
+        if seed_offset.is_none() {
+            let (sum, count) = group.iter().fold((0.0_f64, 0_usize), |(s, n), &g| {
+                let offset = offsets[g].unwrap_or(0.0) as f64;
+                (
+                    s + regions[g].velocity_sum + offset * regions[g].size as f64,
+                    n + regions[g].size,
+                )
+            });
+            let shift = (-(sum / count as f64) as f32 / interval).round() * interval;
+            for &g in group.iter() {
+                offsets[g] = offsets[g].map(|o| o + shift);
+            }
+        }
+    }
+
+    for (i, region) in regions.iter().enumerate() {
+        if region.size < options.min_region_size && flags_by_region[i] == DealiasFlag::Confident {
+            flags_by_region[i] = DealiasFlag::Uncertain;
+        }
+    }
+
+    let mut velocity = field.clone();
+    let mut flags = vec![vec![DealiasFlag::NoData; field.n_gates()]; field.n_radials()];
+    for (r, radial) in velocity.data.iter_mut().enumerate() {
+        for (g, value) in radial.iter_mut().enumerate() {
+            if let Some(label) = labels[r][g] {
+                *value += offsets[label].unwrap_or(0.0);
+                flags[r][g] = flags_by_region[label];
+            }
+        }
+    }
+
+    if let Some(reference) = &reference {
+        for (r, radial) in velocity.data.iter().enumerate() {
+            for (g, value) in radial.iter().enumerate() {
+                let expected = reference.get(r, g);
+                if expected.is_finite()
+                    && (value - expected).abs() > nyquist
+                    && flags[r][g] == DealiasFlag::Confident
+                {
+                    flags[r][g] = DealiasFlag::Uncertain;
+                }
+            }
+        }
+    }
+
+    correct_outliers(&mut velocity, &mut flags, nyquist);
+
+    Ok(DealiasedVelocity {
+        nyquist,
+        velocity,
+        flags,
+    })
+}
+
+struct Region {
+    size: usize,
+    velocity_sum: f64,
+}
+
+type Labels = Vec<Vec<Option<usize>>>;
+type Edges = HashMap<(usize, usize), (f64, usize)>;
+
+fn label_regions(field: &PolarField, nyquist: f32, splits: usize) -> (Labels, Vec<Region>) {
+    let n_radials = field.n_radials();
+    let n_gates = field.n_gates();
+    let bin_width = 2.0 * nyquist / splits as f32;
+    let bin = |v: f32| (((v + nyquist) / bin_width).floor().max(0.0) as usize).min(splits - 1);
+
+    let mut labels: Labels = vec![vec![None; n_gates]; n_radials];
+    let mut regions = Vec::new();
+    let mut queue = VecDeque::new();
+
+    for r0 in 0..n_radials {
+        for g0 in 0..n_gates {
+            let v0 = field.data[r0][g0];
+            if !v0.is_finite() || labels[r0][g0].is_some() {
+                continue;
+            }
+            let label = regions.len();
+            let target = bin(v0);
+            let mut size = 0;
+            let mut velocity_sum = 0.0;
+            labels[r0][g0] = Some(label);
+            queue.push_back((r0, g0));
+
+            while let Some((r, g)) = queue.pop_front() {
+                size += 1;
+                velocity_sum += field.data[r][g] as f64;
+                for (nr, ng) in neighbours_of(r, g, n_radials, n_gates) {
+                    let v = field.data[nr][ng];
+                    if v.is_finite() && labels[nr][ng].is_none() && bin(v) == target {
+                        labels[nr][ng] = Some(label);
+                        queue.push_back((nr, ng));
+                    }
+                }
+            }
+            regions.push(Region { size, velocity_sum });
+        }
+    }
+
+    (labels, regions)
+}
+
+// 4-connected neighbours, wrapping in azimuth.
+fn neighbours_of(
+    r: usize,
+    g: usize,
+    n_radials: usize,
+    n_gates: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let mut out = Vec::with_capacity(4);
+    if n_radials > 1 {
+        out.push(((r + 1) % n_radials, g));
+        out.push(((r + n_radials - 1) % n_radials, g));
+    }
+    if g + 1 < n_gates {
+        out.push((r, g + 1));
+    }
+    if g > 0 {
+        out.push((r, g - 1));
+    }
+    out.into_iter()
+}
+
+// Boundary statistics keyed by (a, b) with a < b: sum of (v_a - v_b) and count.
+fn region_edges(field: &PolarField, labels: &Labels) -> Edges {
+    let n_radials = field.n_radials();
+    let n_gates = field.n_gates();
+    let mut edges: Edges = HashMap::new();
+
+    for r in 0..n_radials {
+        for g in 0..n_gates {
+            let Some(a) = labels[r][g] else { continue };
+            let mut pairs = Vec::with_capacity(2);
+            if g + 1 < n_gates {
+                pairs.push((r, g + 1));
+            }
+            if n_radials > 1 {
+                pairs.push(((r + 1) % n_radials, g));
+            }
+            for (nr, ng) in pairs {
+                let Some(b) = labels[nr][ng] else { continue };
+                if a == b {
+                    continue;
+                }
+                let diff = (field.data[r][g] - field.data[nr][ng]) as f64;
+                let (key, diff) = if a < b {
+                    ((a, b), diff)
+                } else {
+                    ((b, a), -diff)
+                };
+                let entry = edges.entry(key).or_insert((0.0, 0));
+                entry.0 += diff;
+                entry.1 += 1;
+            }
+        }
+    }
+
+    edges
+}
+
+fn assign(
+    region: usize,
+    offset: f32,
+    offsets: &mut [Option<f32>],
+    pending: &mut [(f64, usize)],
+    frontier: &mut BinaryHeap<(usize, usize)>,
+    neighbours: &[Vec<usize>],
+    edges: &Edges,
+) {
+    offsets[region] = Some(offset);
+    for &other in neighbours[region].iter() {
+        if offsets[other].is_some() {
+            continue;
+        }
+        // Sum of (v_region - v_other) across the shared boundary.
+        let (sum, count) = if region < other {
+            edges[&(region, other)]
+        } else {
+            let (s, c) = edges[&(other, region)];
+            (-s, c)
+        };
+        pending[other].0 += sum + count as f64 * offset as f64;
+        pending[other].1 += count;
+        frontier.push((pending[other].1, other));
+    }
+}
+
+// Mean of (reference - measured) over each region's gates.
+fn region_mean_differences(
+    field: &PolarField,
+    labels: &Labels,
+    n_regions: usize,
+    reference: &PolarField,
+) -> Vec<Option<f32>> {
+    let mut sums = vec![(0.0_f64, 0_usize); n_regions];
+    for (r, radial) in labels.iter().enumerate() {
+        for (g, label) in radial.iter().enumerate() {
+            let Some(label) = label else { continue };
+            let expected = reference.get(r, g);
+            if expected.is_finite() {
+                sums[*label].0 += (expected - field.data[r][g]) as f64;
+                sums[*label].1 += 1;
+            }
+        }
+    }
+    sums.into_iter()
+        .map(|(sum, count)| (count > 0).then(|| (sum / count as f64) as f32))
+        .collect()
+}
+
+// Builds the reference velocity on the geometry of `field`.
+fn reference_field(
+    field: &PolarField,
+    site_height: f32,
+    reference: &DealiasReference,
+) -> Option<PolarField> {
+    match reference {
+        DealiasReference::None => None,
+        DealiasReference::Previous(previous) => Some(previous.aligned_to(field)),
+        DealiasReference::WindProfile(profile) if profile.is_empty() => None,
+        DealiasReference::WindProfile(profile) => {
+            let mut sorted = profile.clone();
+            sorted.sort_by(|a, b| a.height.total_cmp(&b.height));
+            let heights: Vec<f32> = sorted.iter().map(|w| w.height).collect();
+            let us: Vec<f32> = sorted.iter().map(|w| w.u).collect();
+            let vs: Vec<f32> = sorted.iter().map(|w| w.v).collect();
+            let elevation = (field.elevation as f64).to_radians();
+
+            let mut out = field.filled_like(f32::NAN);
+            for (r, radial) in out.data.iter_mut().enumerate() {
+                let azimuth = (field.azimuths[r] as f64).to_radians();
+                for (g, value) in radial.iter_mut().enumerate() {
+                    let height = site_height
+                        + beam_height(field.gate_range(g) as f64, field.elevation as f64) as f32;
+                    let u = interpolate_clamped(&heights, &us, height);
+                    let v = interpolate_clamped(&heights, &vs, height);
+                    *value = ((u as f64 * azimuth.sin() + v as f64 * azimuth.cos())
+                        * elevation.cos()) as f32;
+                }
+            }
+            Some(out)
+        }
+    }
+}
+
+fn interpolate_clamped(xs: &[f32], ys: &[f32], x: f32) -> f32 {
+    if x <= xs[0] {
+        return ys[0];
+    }
+    if x >= xs[xs.len() - 1] {
+        return ys[ys.len() - 1];
+    }
+    crate::products::qvp::interpolate(xs, ys, x)
+}
+
+// Final gate-by-gate check: a gate more than a Nyquist away from the median of
+// its 3x3 neighbourhood is refolded towards that median.
+fn correct_outliers(velocity: &mut PolarField, flags: &mut [Vec<DealiasFlag>], nyquist: f32) {
+    let n_radials = velocity.n_radials();
+    let n_gates = velocity.n_gates();
+    if n_radials < 3 || n_gates < 3 {
+        return;
+    }
+    let interval = 2.0 * nyquist;
+    let source = velocity.data.clone();
+
+    for r in 0..n_radials {
+        for g in 1..n_gates - 1 {
+            let value = source[r][g];
+            if !value.is_finite() {
+                continue;
+            }
+            let mut window: Vec<f32> = [(r + n_radials - 1) % n_radials, r, (r + 1) % n_radials]
+                .iter()
+                .flat_map(|&nr| source[nr][g - 1..=g + 1].iter().copied())
+                .filter(|v| v.is_finite())
+                .collect();
+            if window.len() < 5 {
+                continue;
+            }
+            window.sort_by(f32::total_cmp);
+            let median = window[window.len() / 2];
+            if (value - median).abs() > nyquist {
+                velocity.data[r][g] = value + ((median - value) / interval).round() * interval;
+                flags[r][g] = DealiasFlag::Uncertain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ELEVATION: f32 = 0.5;
+    const NYQUIST: f32 = 8.0;
+    const WIND: (f32, f32) = (20.0, 12.0);
+
+    // Radial velocity on a 1 degree, 250 m sweep out to 100 km from a wind
+    // field given as (u, v) at (x, y) metres east and north of the radar.
+    fn sweep_field(wind: impl Fn(f32, f32) -> (f32, f32)) -> PolarField {
+        let azimuths: Vec<f32> = (0..360).map(|i| i as f32 + 0.5).collect();
+        let (first_gate, gate_spacing) = (2_125.0, 250.0);
+        let data = azimuths
+            .iter()
+            .map(|az| {
+                let (sin, cos) = az.to_radians().sin_cos();
+                (0..400)
+                    .map(|g| {
+                        let range = first_gate + g as f32 * gate_spacing;
+                        let (u, v) = wind(range * sin, range * cos);
+                        (u * sin + v * cos) * ELEVATION.to_radians().cos()
+                    })
+                    .collect()
+            })
+            .collect();
+        PolarField {
+            elevation: ELEVATION,
+            times: vec![0.0; azimuths.len()],
+            azimuths,
+            first_gate,
+            gate_spacing,
+            data,
+        }
+    }
+
+    fn folded(truth: &PolarField) -> PolarField {
+        let interval = 2.0 * NYQUIST;
+        let mut field = truth.clone();
+        let mut n_folded = 0;
+        for v in field.data.iter_mut().flatten() {
+            let shift = (*v / interval).round() * interval;
+            if shift != 0.0 {
+                n_folded += 1;
+            }
+            *v -= shift;
+        }
+        assert!(n_folded > field.data.len() * field.n_gates() / 4);
+        field
+    }
+
+    // A cyclonic Rankine vortex 40 km east of the radar in the uniform wind.
+    fn rankine(x: f32, y: f32) -> (f32, f32) {
+        let (radius, peak) = (3_000.0, 25.0);
+        let (dx, dy) = (x - 40_000.0, y);
+        let r = dx.hypot(dy).max(1.0);
+        let speed = if r < radius {
+            peak * r / radius
+        } else {
+            peak * radius / r
+        };
+        (WIND.0 - speed * dy / r, WIND.1 + speed * dx / r)
+    }
+
+    fn profile() -> DealiasOptions {
+        DealiasOptions {
+            reference: DealiasReference::WindProfile(vec![
+                ReferenceWind {
+                    height: 0.0,
+                    u: WIND.0,
+                    v: WIND.1,
+                },
+                ReferenceWind {
+                    height: 10_000.0,
+                    u: WIND.0,
+                    v: WIND.1,
+                },
+            ]),
+            ..DealiasOptions::default()
+        }
+    }
+
+    // Fraction of gates with data unfolded back to the true velocity.
+    fn recovered(result: &DealiasedVelocity, truth: &PolarField) -> f32 {
+        let gates: Vec<(f32, f32)> = truth
+            .data
+            .iter()
+            .flatten()
+            .zip(result.velocity.data.iter().flatten())
+            .filter(|(t, _)| t.is_finite())
+            .map(|(t, u)| (*t, *u))
+            .collect();
+        let matches = gates.iter().filter(|(t, u)| (t - u).abs() < 0.01).count();
+        matches as f32 / gates.len() as f32
+    }
+
+    fn confident(result: &DealiasedVelocity) -> f32 {
+        let flags = result.flags.iter().flatten();
+        let n = flags.clone().count();
+        flags.filter(|f| **f == DealiasFlag::Confident).count() as f32 / n as f32
+    }
+
+    #[test]
+    fn unfolds_uniform_wind_with_profile() {
+        let truth = sweep_field(|_, _| WIND);
+        let result = dealias_field(&folded(&truth), NYQUIST, 370.0, &profile()).unwrap();
+        assert!(recovered(&result, &truth) > 0.999);
+        assert!(confident(&result) > 0.99);
+    }
+
+    #[test]
+    fn unfolds_uniform_wind_without_reference() {
+        let truth = sweep_field(|_, _| WIND);
+        let options = DealiasOptions::default();
+        let result = dealias_field(&folded(&truth), NYQUIST, 370.0, &options).unwrap();
+        // Without a reference the zero-velocity region anchors the sweep.
+        assert!(recovered(&result, &truth) > 0.99);
+    }
+
+    #[test]
+    fn unfolds_rankine_vortex_across_a_gap() {
+        // A ring of missing gates just inside the vortex splits the sweep
+        // into disconnected regions.
+        let mut truth = sweep_field(rankine);
+        for radial in truth.data.iter_mut() {
+            radial[120..130].fill(f32::NAN);
+        }
+        let result = dealias_field(&folded(&truth), NYQUIST, 370.0, &profile()).unwrap();
+        assert!(recovered(&result, &truth) > 0.99);
+        for flags in result.flags.iter() {
+            assert!(flags[120..130].iter().all(|f| *f == DealiasFlag::NoData));
+        }
+    }
+}
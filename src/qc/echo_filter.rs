@@ -0,0 +1,421 @@
+use crate::messages::{
+    ClutterFilterMapMetadata, CLUTTER_OPCODE_BYPASS_FILTER, CLUTTER_OPCODE_FORCE_FILTER,
+};
+use crate::products::hydroclass::texture;
+use crate::products::trapezoid;
+use crate::volume::{Moment, PolarField, Sweep, Volume, GATE_BELOW_THRESHOLD};
+
+// Non-meteorological echo identification. Every gate is scored against each
+// echo type with trapezoidal memberships on RHO, ZDR, reflectivity, texture
+// fields, velocity, clutter filter power and the Message 15 clutter filter
+// map; the best score above `min_score` labels the gate.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EchoClass {
+    Meteorological,
+    GroundClutter,
+    AnomalousPropagation,
+    Biological,
+    Chaff,
+}
+
+#[derive(Debug, Clone)]
+pub struct EchoFilterOptions {
+    // Upper elevation bound of each clutter map elevation segment but the last.
+    pub clutter_map_segment_bounds: Vec<f32>,
+    // Ground clutter and AP are only considered at or below this elevation.
+    pub max_clutter_elevation: f32,
+    // Without a clutter map, clutter beyond this range (metres) is called AP.
+    pub anomalous_propagation_range: f32,
+    // Clutter filter power (dB) above which a gate looks like clutter.
+    pub cfp_threshold: f32,
+    pub texture_window: f32, // metres
+    pub min_score: f32,
+    pub remove: Vec<EchoClass>,
+}
+
+impl Default for EchoFilterOptions {
+    fn default() -> Self {
+        EchoFilterOptions {
+            clutter_map_segment_bounds: vec![1.65],
+            max_clutter_elevation: 3.5,
+            anomalous_propagation_range: 40_000.0,
+            cfp_threshold: 10.0,
+            texture_window: 1_000.0,
+            min_score: 0.5,
+            remove: vec![
+                EchoClass::GroundClutter,
+                EchoClass::AnomalousPropagation,
+                EchoClass::Biological,
+                EchoClass::Chaff,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EchoMask {
+    pub elevation_number: i8,
+    // None where there is no reflectivity.
+    pub classes: Vec<Vec<Option<EchoClass>>>,
+    // Score of the winning class, on the reflectivity geometry.
+    pub score: PolarField,
+}
+
+impl EchoMask {
+    pub fn class_at(&self, azimuth: f32, range: f32) -> Option<EchoClass> {
+        let r = self.score.radial_at_azimuth(azimuth)?;
+        let g = self.score.gate_at_range(range)?;
+        self.classes[r][g]
+    }
+
+    pub fn is_removed(&self, azimuth: f32, range: f32, remove: &[EchoClass]) -> bool {
+        self.class_at(azimuth, range)
+            .is_some_and(|class| remove.contains(&class))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GateInputs {
+    z: f32,
+    zdr: f32,
+    rho: f32,
+    sd_z: f32,
+    sd_zdr: f32,
+    sd_phi: f32,
+    abs_velocity: f32,
+    cfp: f32,
+    // 1 inside a forced clutter filter zone, 0 in a bypass zone, NaN without
+    // a map or where the bypass map is in control.
+    clutter_zone: f32,
+}
+
+pub fn classify_echoes(
+    sweep: &Sweep,
+    clutter_map: Option<&ClutterFilterMapMetadata>,
+    options: &EchoFilterOptions,
+) -> anyhow::Result<EchoMask> {
+    let z = sweep
+        .field(Moment::Reflectivity)
+        .ok_or_else(|| anyhow::anyhow!("Sweep {} has no reflectivity", sweep.elevation_number))?;
+    let aligned = |moment: Moment| sweep.field(moment).map(|f| f.aligned_to(&z));
+    let zdr = aligned(Moment::DifferentialReflectivity);
+    let rho = aligned(Moment::CorrelationCoefficient);
+    let phi = aligned(Moment::DifferentialPhase);
+    let velocity = aligned(Moment::Velocity);
+    let cfp = aligned(Moment::ClutterFilterPower);
+
+    let sd_z = texture(&z, options.texture_window);
+    let sd_zdr = zdr.as_ref().map(|f| texture(f, options.texture_window));
+    let sd_phi = phi.as_ref().map(|f| texture(f, options.texture_window));
+    let segment = options
+        .clutter_map_segment_bounds
+        .iter()
+        .filter(|bound| sweep.elevation_angle > **bound)
+        .count();
+    let low_tilt = sweep.elevation_angle <= options.max_clutter_elevation;
+    let value = |field: &Option<PolarField>, r: usize, g: usize| {
+        field.as_ref().map_or(f32::NAN, |f| f.get(r, g))
+    };
+
+    let mut classes = vec![vec![None; z.n_gates()]; z.n_radials()];
+    let mut score = z.filled_like(f32::NAN);
+
+    let rows = classes.iter_mut().zip(score.data.iter_mut()).enumerate();
+    for (r, (class_row, score_row)) in rows {
+        let gates = class_row.iter_mut().zip(score_row.iter_mut()).enumerate();
+        for (g, (class_slot, score_slot)) in gates {
+            let reflectivity = z.get(r, g);
+            if !reflectivity.is_finite() {
+                continue;
+            }
+            let range = z.gate_range(g);
+            let opcode =
+                clutter_map.and_then(|map| map.opcode_at(segment, z.azimuths[r], range / 1000.0));
+            let inputs = GateInputs {
+                z: reflectivity,
+                zdr: value(&zdr, r, g),
+                rho: value(&rho, r, g),
+                sd_z: sd_z.get(r, g),
+                sd_zdr: value(&sd_zdr, r, g),
+                sd_phi: value(&sd_phi, r, g),
+                abs_velocity: value(&velocity, r, g).abs(),
+                cfp: value(&cfp, r, g),
+                clutter_zone: clutter_zone(opcode),
+            };
+
+            let mut candidates = vec![
+                (EchoClass::Meteorological, meteorological_score(&inputs)),
+                (EchoClass::Biological, biological_score(&inputs)),
+                (EchoClass::Chaff, chaff_score(&inputs)),
+            ];
+            if low_tilt {
+                let clutter = clutter_class(opcode, range, options);
+                candidates.push((clutter, clutter_score(&inputs, options.cfp_threshold)));
+            }
+
+            let (class, best) = candidates
+                .into_iter()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or((EchoClass::Meteorological, 0.0));
+            if class == EchoClass::Meteorological || best < options.min_score {
+                *class_slot = Some(EchoClass::Meteorological);
+            } else {
+                *class_slot = Some(class);
+            }
+            *score_slot = best;
+        }
+    }
+
+    Ok(EchoMask {
+        elevation_number: sweep.elevation_number,
+        classes,
+        score,
+    })
+}
+
+// Under op code 1 the bypass map, which is not decoded, decides whether a
+// gate is filtered, so the zone says nothing either way.
+fn clutter_zone(opcode: Option<i16>) -> f32 {
+    match opcode {
+        Some(CLUTTER_OPCODE_FORCE_FILTER) => 1.0,
+        Some(CLUTTER_OPCODE_BYPASS_FILTER) => 0.0,
+        _ => f32::NAN,
+    }
+}
+
+// Clutter-like echo where the map forces filtering is ground clutter; where
+// the map bypasses the filter it is AP. Elsewhere range decides.
+fn clutter_class(opcode: Option<i16>, range: f32, options: &EchoFilterOptions) -> EchoClass {
+    match opcode {
+        Some(CLUTTER_OPCODE_FORCE_FILTER) => EchoClass::GroundClutter,
+        Some(CLUTTER_OPCODE_BYPASS_FILTER) => EchoClass::AnomalousPropagation,
+        _ if range > options.anomalous_propagation_range => EchoClass::AnomalousPropagation,
+        _ => EchoClass::GroundClutter,
+    }
+}
+
+// Weighted mean of memberships, skipping inputs that are missing.
+fn weighted(terms: &[(f32, [f32; 4], f32)]) -> f32 {
+    let mut sum = 0.0;
+    let mut weights = 0.0;
+    for &(value, [x1, x2, x3, x4], weight) in terms {
+        if value.is_finite() {
+            sum += weight * trapezoid(value, x1, x2, x3, x4);
+            weights += weight;
+        }
+    }
+    if weights == 0.0 {
+        0.0
+    } else {
+        sum / weights
+    }
+}
+
+fn meteorological_score(i: &GateInputs) -> f32 {
+    weighted(&[
+        (i.rho, [0.85, 0.95, 1.0, 1.01], 1.0),
+        (i.sd_z, [0.0, 0.0, 4.0, 8.0], 0.6),
+        (i.sd_phi, [0.0, 0.0, 10.0, 20.0], 0.6),
+        (i.sd_zdr, [0.0, 0.0, 1.0, 2.0], 0.4),
+    ])
+}
+
+fn clutter_score(i: &GateInputs, cfp_threshold: f32) -> f32 {
+    weighted(&[
+        (i.rho, [0.0, 0.0, 0.85, 0.95], 0.8),
+        (i.sd_z, [4.0, 8.0, 60.0, 70.0], 0.8),
+        (i.sd_phi, [15.0, 30.0, 360.0, 361.0], 0.8),
+        (i.abs_velocity, [0.0, 0.0, 1.0, 2.0], 0.6),
+        (
+            i.cfp,
+            [cfp_threshold - 5.0, cfp_threshold, 100.0, 101.0],
+            0.6,
+        ),
+        (i.clutter_zone, [0.5, 1.0, 1.0, 1.5], 0.6),
+    ])
+}
+
+fn biological_score(i: &GateInputs) -> f32 {
+    weighted(&[
+        (i.rho, [0.2, 0.3, 0.7, 0.8], 1.0),
+        (i.zdr, [1.0, 3.0, 10.0, 12.0], 0.8),
+        (i.z, [-10.0, 0.0, 20.0, 30.0], 0.4),
+        (i.sd_zdr, [1.0, 2.0, 10.0, 12.0], 0.4),
+    ])
+}
+
+fn chaff_score(i: &GateInputs) -> f32 {
+    weighted(&[
+        (i.rho, [0.0, 0.1, 0.5, 0.7], 1.0),
+        (i.zdr, [-3.0, -2.0, 1.5, 2.5], 0.6),
+        (i.z, [-10.0, 0.0, 20.0, 30.0], 0.4),
+        (i.sd_z, [0.0, 0.0, 4.0, 8.0], 0.4),
+    ])
+}
+
+// Classifies every sweep of a volume. Sweeps without reflectivity (the Doppler
+// half of a split cut) borrow the mask of the nearest sweep at the same angle.
+pub fn classify_volume(
+    volume: &Volume,
+    clutter_map: Option<&ClutterFilterMapMetadata>,
+    options: &EchoFilterOptions,
+) -> Vec<Option<EchoMask>> {
+    let own: Vec<Option<EchoMask>> = volume
+        .sweeps
+        .iter()
+        .map(|sweep| classify_echoes(sweep, clutter_map, options).ok())
+        .collect();
+
+    volume
+        .sweeps
+        .iter()
+        .enumerate()
+        .map(|(i, sweep)| {
+            own[i].clone().or_else(|| {
+                volume
+                    .sweeps
+                    .iter()
+                    .zip(own.iter())
+                    .filter(|(other, mask)| {
+                        mask.is_some()
+                            && (other.elevation_angle - sweep.elevation_angle).abs() < 0.25
+                    })
+                    .min_by(|a, b| {
+                        (a.0.elevation_angle - sweep.elevation_angle)
+                            .abs()
+                            .total_cmp(&(b.0.elevation_angle - sweep.elevation_angle).abs())
+                    })
+                    .and_then(|(_, mask)| mask.clone())
+            })
+        })
+        .collect()
+}
+
+// A copy of the volume with every gate of every moment set below threshold
+// where the mask labels it with one of `options.remove`, plus the masks used.
+pub fn clean_volume(
+    volume: &Volume,
+    clutter_map: Option<&ClutterFilterMapMetadata>,
+    options: &EchoFilterOptions,
+) -> (Volume, Vec<Option<EchoMask>>) {
+    let masks = classify_volume(volume, clutter_map, options);
+    let mut cleaned = volume.clone();
+
+    for (sweep, mask) in cleaned.sweeps.iter_mut().zip(masks.iter()) {
+        let Some(mask) = mask else { continue };
+        for radial in sweep.radials.iter_mut() {
+            let Some(r) = mask.score.radial_at_azimuth(radial.header.azimuth_angle) else {
+                continue;
+            };
+            for moment in radial.moments.iter_mut() {
+                let first = moment.first_gate_range();
+                let spacing = moment.gate_spacing();
+                for (g, raw) in moment.gates.iter_mut().enumerate() {
+                    let class = mask
+                        .score
+                        .gate_at_range(first + g as f32 * spacing)
+                        .and_then(|mg| mask.classes[r][mg]);
+                    if class.is_some_and(|c| options.remove.contains(&c)) {
+                        *raw = GATE_BELOW_THRESHOLD;
+                    }
+                }
+            }
+        }
+    }
+
+    (cleaned, masks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        AzimuthSegment, ElevationSegment, RangeZone, CLUTTER_OPCODE_BYPASS_MAP_IN_CONTROL,
+    };
+
+    // Every azimuth bypasses the filter to 20 km, leaves it to the bypass map
+    // to 60 km and forces it beyond.
+    fn clutter_map() -> ClutterFilterMapMetadata {
+        let zone = |n, opcode, endrange| RangeZone {
+            range_zone_num: n,
+            opcode,
+            endrange,
+        };
+        let azimuth_segment = AzimuthSegment {
+            num_rangezones: 3,
+            range_zones: vec![
+                zone(1, CLUTTER_OPCODE_BYPASS_FILTER, 20),
+                zone(2, CLUTTER_OPCODE_BYPASS_MAP_IN_CONTROL, 60),
+                zone(3, CLUTTER_OPCODE_FORCE_FILTER, 511),
+            ],
+        };
+        ClutterFilterMapMetadata {
+            map_generation_date: 20000,
+            map_generation_time: 600,
+            num_elevation_segments: 1,
+            elevation_segments: vec![ElevationSegment {
+                azimuth_segments: vec![azimuth_segment; 360],
+            }],
+        }
+    }
+
+    fn clutter_like(clutter_zone: f32) -> GateInputs {
+        GateInputs {
+            z: 40.0,
+            zdr: f32::NAN,
+            rho: 0.9,
+            sd_z: 6.0,
+            sd_zdr: f32::NAN,
+            sd_phi: 20.0,
+            abs_velocity: 1.5,
+            cfp: f32::NAN,
+            clutter_zone,
+        }
+    }
+
+    #[test]
+    fn clutter_map_opcodes() {
+        let map = clutter_map();
+        let options = EchoFilterOptions::default();
+        let opcode = |range_km: f32| map.opcode_at(0, 123.4, range_km);
+
+        // Op code 0: the filter is bypassed, so clutter-like echo is AP even
+        // close to the radar.
+        assert_eq!(opcode(10.0), Some(CLUTTER_OPCODE_BYPASS_FILTER));
+        assert_eq!(clutter_zone(opcode(10.0)), 0.0);
+        assert_eq!(
+            clutter_class(opcode(10.0), 10_000.0, &options),
+            EchoClass::AnomalousPropagation
+        );
+
+        // Op code 1: the bypass map decides, so the zone is unknown and range
+        // separates clutter from AP as it does without a map.
+        assert_eq!(opcode(50.0), Some(CLUTTER_OPCODE_BYPASS_MAP_IN_CONTROL));
+        assert!(clutter_zone(opcode(50.0)).is_nan());
+        assert_eq!(
+            clutter_class(opcode(30.0), 30_000.0, &options),
+            EchoClass::GroundClutter
+        );
+        assert_eq!(
+            clutter_class(opcode(50.0), 50_000.0, &options),
+            EchoClass::AnomalousPropagation
+        );
+
+        // Op code 2: a forced zone is known clutter at any range.
+        assert_eq!(opcode(100.0), Some(CLUTTER_OPCODE_FORCE_FILTER));
+        assert_eq!(clutter_zone(opcode(100.0)), 1.0);
+        assert_eq!(
+            clutter_class(opcode(100.0), 100_000.0, &options),
+            EchoClass::GroundClutter
+        );
+
+        // Only the forced zone raises the clutter score; the op code 1 zone
+        // scores as if there were no map.
+        let score = |opcode| clutter_score(&clutter_like(clutter_zone(opcode)), 10.0);
+        let no_map = clutter_score(&clutter_like(f32::NAN), 10.0);
+        assert!(score(opcode(100.0)) > no_map);
+        assert_eq!(score(opcode(50.0)), no_map);
+        assert!(score(opcode(10.0)) < no_map);
+    }
+}
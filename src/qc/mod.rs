@@ -0,0 +1,3 @@
+pub mod attenuation;
+pub mod dealias;
+pub mod echo_filter;
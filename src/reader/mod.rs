@@ -1,3 +1,5 @@
+pub mod sounding;
+
 use bzip2::bufread;
 use core::str;
 use std::io::BufReader;
@@ -11,10 +13,13 @@ use std::{
 
 use crate::messages::{
     ClutterFilterMapMetadata, DigitalRadarDataGenericFormatHeader,
-    DigitalRadarDataGenericFormatHeaderRaw, MessageHeader, MessageHeaderRaw,
-    RawClutterFilterMapMetadata, VolumeHeader, VolumeHeaderRaw,
-    DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE,
+    DigitalRadarDataGenericFormatHeaderRaw, ElevationDataBlock, ElevationDataBlockRaw,
+    GenericMomentHeader, GenericMomentHeaderRaw, MessageHeader, MessageHeaderRaw, RadialDataBlock,
+    RadialDataBlockRaw, RangeZone, RawAzimuthSegment, RawClutterFilterMapMetadata,
+    RawElevationSegment, RawRangeZone, VolumeDataBlock, VolumeDataBlockRaw, VolumeHeader,
+    VolumeHeaderRaw, DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE, GENERIC_MOMENT_HEADER_SIZE,
 };
+use crate::volume::{Moment, MomentData, Radial, Sweep, Volume};
 
 const MESSAGE_RECORD_SIZE: usize = 2432; // number of bytes in a message segment (compressed)
 const MESSAGE_HEADER_STARTING_BYTE_OFFSET: usize = 12;
@@ -69,7 +74,8 @@ pub fn segment_message<'a>(ff: File) -> anyhow::Result<Vec<BufReader<&'a std::fs
 }
 
 pub fn read_volume_header(fp: &str) -> anyhow::Result<VolumeHeader> {
-    let file = std::fs::File::open(fp).unwrap();
+    let file =
+        std::fs::File::open(fp).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", fp, e))?;
     let mut reader = BufReader::new(file);
     let mut vh = VolumeHeaderRaw::new();
 
@@ -85,8 +91,17 @@ pub fn read_volume_header(fp: &str) -> anyhow::Result<VolumeHeader> {
 }
 
 pub fn read_message_header(message: Vec<u8>) -> anyhow::Result<MessageHeader> {
-    let (_, message) = message.split_at(MESSAGE_HEADER_STARTING_BYTE_OFFSET);
-    let (header, _) = message.split_at(MESSAGE_HEADER_SIZE);
+    let header = message
+        .get(
+            MESSAGE_HEADER_STARTING_BYTE_OFFSET
+                ..MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE,
+        )
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Message of {} bytes is too short for a message header",
+                message.len()
+            )
+        })?;
 
     let mut reader = BufReader::new(header);
 
@@ -101,14 +116,16 @@ pub fn read_message_header(message: Vec<u8>) -> anyhow::Result<MessageHeader> {
     reader.read_exact(&mut mh.n_segments)?;
     reader.read_exact(&mut mh.message_segment_no)?;
 
-    let message_header = MessageHeader::try_from(mh)
-        .map_err(|e| anyhow::anyhow!("Failed to convert VolumeHeaderRaw to VolumeHeader: {}", e))?;
+    let message_header = MessageHeader::try_from(mh).map_err(|e| {
+        anyhow::anyhow!("Failed to convert MessageHeaderRaw to MessageHeader: {}", e)
+    })?;
 
     Ok(message_header)
 }
 
 pub fn decompress_nexrad_file(fp: &str) -> anyhow::Result<Vec<Vec<u8>>> {
-    let mut ff: std::fs::File = std::fs::File::open(&fp).expect("Failed to open file");
+    let mut ff: std::fs::File =
+        std::fs::File::open(fp).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", fp, e))?;
     let mut buf: Vec<u8> = Vec::new();
     let file_length = ff.metadata()?.len();
 
@@ -140,8 +157,15 @@ pub fn read_data_header(message: &Vec<u8>) -> anyhow::Result<DigitalRadarDataGen
     let mut dhdr: DigitalRadarDataGenericFormatHeaderRaw =
         DigitalRadarDataGenericFormatHeaderRaw::default();
 
-    let (_, msg) = message.split_at(MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE);
-    let (header, _) = msg.split_at(DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE);
+    let start = MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE;
+    let header = message
+        .get(start..start + DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Message of {} bytes is too short for a Message 31 header",
+                message.len()
+            )
+        })?;
     let mut reader = std::io::Cursor::new(header);
 
     let _ = reader.read_exact(&mut dhdr.radar_identifier);
@@ -179,3 +203,487 @@ pub fn read_data_header(message: &Vec<u8>) -> anyhow::Result<DigitalRadarDataGen
 
     Ok(data_header)
 }
+
+// Offset of the first data block pointer within the generic format header.
+const DATA_BLOCK_POINTER_OFFSET: usize = 32;
+const MESSAGE_31: u8 = 31;
+
+// Splits a decompressed LDM record into its messages. Message 31 is variable
+// length (size in halfwords, excluding the 12 byte CTM header); every other
+// message occupies a fixed 2432 byte slot.
+pub fn split_messages(record: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+
+    while offset + MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE <= record.len() {
+        let header = &record[offset + MESSAGE_HEADER_STARTING_BYTE_OFFSET..];
+        let size_halfwords = u16::from_be_bytes([header[0], header[1]]) as usize;
+        let message_type = header[3];
+
+        let size = if message_type == MESSAGE_31 {
+            MESSAGE_HEADER_STARTING_BYTE_OFFSET + size_halfwords * 2
+        } else {
+            MESSAGE_RECORD_SIZE
+        };
+        if size <= MESSAGE_HEADER_STARTING_BYTE_OFFSET {
+            break;
+        }
+
+        let end = (offset + size).min(record.len());
+        messages.push(&record[offset..end]);
+        offset = end;
+    }
+
+    messages
+}
+
+pub fn message_type_of(message: &[u8]) -> Option<u8> {
+    message
+        .get(MESSAGE_HEADER_STARTING_BYTE_OFFSET + 3)
+        .copied()
+}
+
+fn block_slice(message: &[u8], pointer: usize, size: usize) -> anyhow::Result<&[u8]> {
+    let start = MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE + pointer;
+    message
+        .get(start..start + size)
+        .ok_or_else(|| anyhow::anyhow!("Data block at pointer {} runs past the message", pointer))
+}
+
+pub fn read_volume_data_block(block: &[u8]) -> anyhow::Result<VolumeDataBlock> {
+    let mut raw = VolumeDataBlockRaw::default();
+    let mut reader = std::io::Cursor::new(block);
+
+    reader.read_exact(&mut raw.block_name)?;
+    reader.read_exact(&mut raw.lrtup)?;
+    reader.read_exact(&mut raw.version_major)?;
+    reader.read_exact(&mut raw.version_minor)?;
+    reader.read_exact(&mut raw.latitude)?;
+    reader.read_exact(&mut raw.longitude)?;
+    reader.read_exact(&mut raw.site_height)?;
+    reader.read_exact(&mut raw.feedhorn_height)?;
+    reader.read_exact(&mut raw.calibration_constant)?;
+    reader.read_exact(&mut raw.horizontal_shv_tx_power)?;
+    reader.read_exact(&mut raw.vertical_shv_tx_power)?;
+    reader.read_exact(&mut raw.system_differential_reflectivity)?;
+    reader.read_exact(&mut raw.initial_system_differential_phase)?;
+    reader.read_exact(&mut raw.vcp_number)?;
+    reader.read_exact(&mut raw.processing_status)?;
+
+    Ok(VolumeDataBlock::from(raw))
+}
+
+pub fn read_elevation_data_block(block: &[u8]) -> anyhow::Result<ElevationDataBlock> {
+    let mut raw = ElevationDataBlockRaw::default();
+    let mut reader = std::io::Cursor::new(block);
+
+    reader.read_exact(&mut raw.block_name)?;
+    reader.read_exact(&mut raw.lrtup)?;
+    reader.read_exact(&mut raw.atmospheric_attenuation)?;
+    reader.read_exact(&mut raw.calibration_constant)?;
+
+    Ok(ElevationDataBlock::from(raw))
+}
+
+pub fn read_radial_data_block(block: &[u8]) -> anyhow::Result<RadialDataBlock> {
+    let mut raw = RadialDataBlockRaw::default();
+    let mut reader = std::io::Cursor::new(block);
+
+    reader.read_exact(&mut raw.block_name)?;
+    reader.read_exact(&mut raw.lrtup)?;
+    reader.read_exact(&mut raw.unambiguous_range)?;
+    reader.read_exact(&mut raw.horizontal_noise_level)?;
+    reader.read_exact(&mut raw.vertical_noise_level)?;
+    reader.read_exact(&mut raw.nyquist_velocity)?;
+    reader.read_exact(&mut raw.radial_flags)?;
+    reader.read_exact(&mut raw.horizontal_calibration_constant)?;
+    reader.read_exact(&mut raw.vertical_calibration_constant)?;
+
+    Ok(RadialDataBlock::from(raw))
+}
+
+pub fn read_moment_header(block: &[u8]) -> anyhow::Result<GenericMomentHeader> {
+    let mut raw = GenericMomentHeaderRaw::default();
+    let mut reader = std::io::Cursor::new(block);
+
+    reader.read_exact(&mut raw.block_name)?;
+    reader.read_exact(&mut raw.reserved)?;
+    reader.read_exact(&mut raw.n_gates)?;
+    reader.read_exact(&mut raw.first_gate_range)?;
+    reader.read_exact(&mut raw.gate_spacing)?;
+    reader.read_exact(&mut raw.threshold)?;
+    reader.read_exact(&mut raw.snr_threshold)?;
+    reader.read_exact(&mut raw.control_flags)?;
+    reader.read_exact(&mut raw.data_word_size)?;
+    reader.read_exact(&mut raw.scale)?;
+    reader.read_exact(&mut raw.offset)?;
+
+    GenericMomentHeader::try_from(raw)
+        .map_err(|e| anyhow::anyhow!("Failed to convert GenericMomentHeaderRaw: {}", e))
+}
+
+pub fn read_moment_data(message: &[u8], pointer: usize) -> anyhow::Result<Option<MomentData>> {
+    let header = read_moment_header(block_slice(message, pointer, GENERIC_MOMENT_HEADER_SIZE)?)?;
+    let moment = match Moment::from_block_name(&header.name) {
+        Some(moment) => moment,
+        None => return Ok(None),
+    };
+
+    let n_gates = header.n_gates as usize;
+    let gates: Vec<u16> = match header.data_word_size {
+        8 => block_slice(message, pointer + GENERIC_MOMENT_HEADER_SIZE, n_gates)?
+            .iter()
+            .map(|&b| b as u16)
+            .collect(),
+        16 => block_slice(message, pointer + GENERIC_MOMENT_HEADER_SIZE, n_gates * 2)?
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect(),
+        other => anyhow::bail!(
+            "Unsupported data word size {} in {} block",
+            other,
+            header.name
+        ),
+    };
+
+    Ok(Some(MomentData {
+        moment,
+        header,
+        gates,
+    }))
+}
+
+// Decodes a full Message 31 radial: the generic header followed by every data
+// block named in its pointer table.
+pub fn read_message31(message: &[u8]) -> anyhow::Result<Radial> {
+    read_radial_blocks(message, read_moment_data)
+}
+
+// As read_message31, but moment blocks keep only their headers and leave
+// `gates` empty, for summaries that never look at the data.
+pub fn read_message31_headers(message: &[u8]) -> anyhow::Result<Radial> {
+    read_radial_blocks(message, |message, pointer| {
+        let header =
+            read_moment_header(block_slice(message, pointer, GENERIC_MOMENT_HEADER_SIZE)?)?;
+        Ok(
+            Moment::from_block_name(&header.name).map(|moment| MomentData {
+                moment,
+                header,
+                gates: Vec::new(),
+            }),
+        )
+    })
+}
+
+fn read_radial_blocks(
+    message: &[u8],
+    read_moment: impl Fn(&[u8], usize) -> anyhow::Result<Option<MomentData>>,
+) -> anyhow::Result<Radial> {
+    let header = read_data_header(&message.to_vec())?;
+
+    let pointer_table = block_slice(
+        message,
+        DATA_BLOCK_POINTER_OFFSET,
+        header.data_block_count.max(0) as usize * 4,
+    )?;
+
+    let mut radial = Radial {
+        header,
+        volume: None,
+        elevation: None,
+        radial: None,
+        moments: Vec::new(),
+    };
+
+    for chunk in pointer_table.chunks_exact(4) {
+        let pointer = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+        if pointer == 0 {
+            continue;
+        }
+        let name = block_slice(message, pointer, 4)?;
+
+        match &name[1..] {
+            b"VOL" => {
+                radial.volume = Some(read_volume_data_block(block_slice(
+                    message,
+                    pointer,
+                    crate::messages::VOLUME_DATA_BLOCK_SIZE,
+                )?)?)
+            }
+            b"ELV" => {
+                radial.elevation = Some(read_elevation_data_block(block_slice(
+                    message,
+                    pointer,
+                    crate::messages::ELEVATION_DATA_BLOCK_SIZE,
+                )?)?)
+            }
+            b"RAD" => {
+                radial.radial = Some(read_radial_data_block(block_slice(
+                    message,
+                    pointer,
+                    crate::messages::RADIAL_DATA_BLOCK_SIZE,
+                )?)?)
+            }
+            _ => {
+                if let Some(moment) = read_moment(message, pointer)? {
+                    radial.moments.push(moment);
+                }
+            }
+        }
+    }
+
+    Ok(radial)
+}
+
+// Reads an Archive II file into sweeps of decoded radials.
+pub fn read_volume(fp: &str) -> anyhow::Result<Volume> {
+    let header = read_volume_header(fp)?;
+    let records = decompress_nexrad_file(fp)?;
+    read_volume_from_records(header, &records)
+}
+
+// Groups the Message 31 radials in decompressed records into sweeps. A new
+// sweep starts whenever the elevation number changes.
+pub fn read_volume_from_records(
+    header: VolumeHeader,
+    records: &[Vec<u8>],
+) -> anyhow::Result<Volume> {
+    group_sweeps(header, records, read_message31)
+}
+
+// As read_volume_from_records with read_message31_headers: every block but the
+// gate data.
+pub fn read_volume_headers_from_records(
+    header: VolumeHeader,
+    records: &[Vec<u8>],
+) -> anyhow::Result<Volume> {
+    group_sweeps(header, records, read_message31_headers)
+}
+
+fn group_sweeps(
+    header: VolumeHeader,
+    records: &[Vec<u8>],
+    read_radial: fn(&[u8]) -> anyhow::Result<Radial>,
+) -> anyhow::Result<Volume> {
+    let mut sweeps: Vec<Sweep> = Vec::new();
+    for record in records.iter() {
+        for message in split_messages(record) {
+            if message_type_of(message) != Some(MESSAGE_31) {
+                continue;
+            }
+            let radial = read_radial(message)?;
+
+            match sweeps.last_mut() {
+                Some(sweep) if sweep.elevation_number == radial.header.elevation_number => {
+                    sweep.radials.push(radial)
+                }
+                _ => sweeps.push(Sweep {
+                    elevation_number: radial.header.elevation_number,
+                    elevation_angle: radial.header.elevation_angle,
+                    radials: vec![radial],
+                }),
+            }
+        }
+    }
+
+    // The reported angle drifts slightly radial to radial; use the sweep mean.
+    for sweep in sweeps.iter_mut() {
+        let total: f32 = sweep.radials.iter().map(|r| r.header.elevation_angle).sum();
+        sweep.elevation_angle = total / sweep.radials.len() as f32;
+    }
+
+    Ok(Volume { header, sweeps })
+}
+
+const MESSAGE_15: u8 = 15;
+const AZIMUTH_SEGMENTS: usize = 360;
+
+// Reassembles the Message 15 clutter filter map from the metadata record.
+// The map spans several segments which are concatenated in segment order.
+pub fn read_clutter_filter_map_from_records(
+    records: &[Vec<u8>],
+) -> anyhow::Result<Option<ClutterFilterMapMetadata>> {
+    let mut segments: Vec<(i16, &[u8])> = Vec::new();
+    for record in records.iter() {
+        for message in split_messages(record) {
+            if message_type_of(message) != Some(MESSAGE_15) {
+                continue;
+            }
+            let header = read_message_header(message.to_vec())?;
+            let payload_end = (MESSAGE_HEADER_STARTING_BYTE_OFFSET
+                + header.messagesize.max(0) as usize * 2)
+                .min(message.len());
+            let payload_start = MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE;
+            if payload_end > payload_start {
+                segments.push((
+                    header.message_segment_no,
+                    &message[payload_start..payload_end],
+                ));
+            }
+        }
+    }
+    if segments.is_empty() {
+        return Ok(None);
+    }
+    segments.sort_by_key(|(number, _)| *number);
+    let payload: Vec<u8> = segments
+        .iter()
+        .flat_map(|(_, p)| p.iter().copied())
+        .collect();
+
+    let mut raw = RawClutterFilterMapMetadata::new();
+    let mut reader = std::io::Cursor::new(payload);
+    reader.read_exact(&mut raw.map_generation_date)?;
+    reader.read_exact(&mut raw.map_generation_time)?;
+    reader.read_exact(&mut raw.num_elevation_segments)?;
+
+    let n_elevation_segments = i16::from_be_bytes(raw.num_elevation_segments).max(0) as usize;
+    raw.elevation_segments = Vec::with_capacity(n_elevation_segments);
+    for _ in 0..n_elevation_segments {
+        let mut elevation_segment = RawElevationSegment {
+            azimuth_segments: Vec::with_capacity(AZIMUTH_SEGMENTS),
+        };
+        for _ in 0..AZIMUTH_SEGMENTS {
+            let mut azimuth_segment = RawAzimuthSegment::new();
+            reader.read_exact(&mut azimuth_segment.num_rangezones)?;
+            let n_zones = i16::from_be_bytes(azimuth_segment.num_rangezones).max(0);
+            for zone_number in 1..=n_zones {
+                let mut zone = RawRangeZone::new();
+                zone.range_zone_num = zone_number;
+                reader.read_exact(&mut zone.opcode)?;
+                reader.read_exact(&mut zone.endrange)?;
+                azimuth_segment.range_zones.push(
+                    RangeZone::try_from(zone)
+                        .map_err(|e| anyhow::anyhow!("Failed to convert RawRangeZone: {}", e))?,
+                );
+            }
+            elevation_segment.azimuth_segments.push(azimuth_segment);
+        }
+        raw.elevation_segments.push(elevation_segment);
+    }
+
+    let map = ClutterFilterMapMetadata::try_from(raw)
+        .map_err(|e| anyhow::anyhow!("Failed to convert RawClutterFilterMapMetadata: {}", e))?;
+    Ok(Some(map))
+}
+
+pub fn read_clutter_filter_map(fp: &str) -> anyhow::Result<Option<ClutterFilterMapMetadata>> {
+    let records = decompress_nexrad_file(fp)?;
+    read_clutter_filter_map_from_records(&records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message31(payload_len: usize) -> Vec<u8> {
+        let size_halfwords = ((MESSAGE_HEADER_SIZE + payload_len) / 2) as u16;
+        let mut message = vec![0_u8; MESSAGE_HEADER_STARTING_BYTE_OFFSET];
+        message.extend_from_slice(&size_halfwords.to_be_bytes());
+        message.extend_from_slice(&[0, MESSAGE_31]);
+        message.resize(
+            MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE + payload_len,
+            0,
+        );
+        message
+    }
+
+    // A radial at 45 degrees on the first sweep with a four-gate REF block.
+    fn radial_message() -> Vec<u8> {
+        let header_size = DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE;
+        let mut message = message31(header_size + GENERIC_MOMENT_HEADER_SIZE + 4);
+        let base = MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE;
+        let mut put = |at: usize, bytes: &[u8]| {
+            message[base + at..base + at + bytes.len()].copy_from_slice(bytes);
+        };
+        put(0, b"KTLX");
+        put(4, &3_600_000_i32.to_be_bytes());
+        put(8, &20_000_i16.to_be_bytes());
+        put(12, &45.0_f32.to_be_bytes());
+        put(22, &[1]);
+        put(24, &0.5_f32.to_be_bytes());
+        put(30, &1_i16.to_be_bytes());
+        put(
+            DATA_BLOCK_POINTER_OFFSET,
+            &(header_size as u32).to_be_bytes(),
+        );
+
+        let mut block = b"DREF".to_vec();
+        block.extend([0; 4]);
+        block.extend(4_u16.to_be_bytes());
+        block.extend(2_125_i16.to_be_bytes());
+        block.extend(250_i16.to_be_bytes());
+        block.extend([0; 4]);
+        block.extend([0, 8]);
+        block.extend(2.0_f32.to_be_bytes());
+        block.extend(66.0_f32.to_be_bytes());
+        block.extend([0, 1, 106, 166]);
+        put(header_size, &block);
+        message
+    }
+
+    #[test]
+    fn headers_only_radials_skip_gates() {
+        let message = radial_message();
+        let full = read_message31(&message).unwrap();
+        let data = full.moment(Moment::Reflectivity).unwrap();
+        assert_eq!(data.gates, vec![0, 1, 106, 166]);
+        assert_eq!(data.value(3), Some(50.0));
+
+        let headers = read_message31_headers(&message).unwrap();
+        assert_eq!(headers.header.azimuth_angle, 45.0);
+        let header_only = headers.moment(Moment::Reflectivity).unwrap();
+        assert!(header_only.gates.is_empty());
+        assert_eq!(
+            format!("{:?}", header_only.header),
+            format!("{:?}", data.header)
+        );
+
+        let header = VolumeHeader {
+            volumename: "AR2V0006.001".to_string(),
+            date: 20000,
+            time: 0,
+            icao: "KTLX".to_string(),
+        };
+        let volume = read_volume_headers_from_records(header, &[message]).unwrap();
+        assert_eq!(volume.sweeps.len(), 1);
+        assert_eq!(volume.sweeps[0].radials.len(), 1);
+        assert_eq!(volume.sweeps[0].elevation_angle, 0.5);
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let fp = "/nonexistent/KTLX20240520_210317_V06";
+        assert!(read_volume_header(fp).is_err());
+        assert!(decompress_nexrad_file(fp).is_err());
+        let error = read_volume(fp).unwrap_err().to_string();
+        assert!(error.contains(fp), "{}", error);
+        assert!(read_clutter_filter_map(fp).is_err());
+    }
+
+    #[test]
+    fn short_messages_are_errors() {
+        assert!(read_message_header(vec![0; MESSAGE_HEADER_STARTING_BYTE_OFFSET + 4]).is_err());
+        assert!(read_data_header(&message31(10)).is_err());
+        assert!(read_message31(&message31(10)).is_err());
+        assert!(read_message31(&[0; 20]).is_err());
+    }
+
+    #[test]
+    fn truncated_record_is_an_error() {
+        // The size field claims a full header but the record ends early.
+        let mut record = message31(DIGITAL_RADAR_DATA_GENERIC_FORMAT_HEADER_SIZE);
+        record.truncate(MESSAGE_HEADER_STARTING_BYTE_OFFSET + MESSAGE_HEADER_SIZE + 20);
+        let messages = split_messages(&record);
+        assert_eq!(messages.len(), 1);
+        assert!(read_message_header(messages[0].to_vec()).is_ok());
+
+        let header = VolumeHeader {
+            volumename: "AR2V0006.001".to_string(),
+            date: 20000,
+            time: 0,
+            icao: "KTLX".to_string(),
+        };
+        assert!(read_volume_from_records(header, &[record]).is_err());
+    }
+}
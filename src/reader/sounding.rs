@@ -0,0 +1,110 @@
+use std::fs;
+
+// A single upper-air sounding. Heights are metres above sea level and
+// temperatures degrees Celsius, ordered from the surface up.
+#[derive(Debug, Clone)]
+pub struct Sounding {
+    pub levels: Vec<SoundingLevel>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SoundingLevel {
+    pub pressure: f32, // hPa
+    pub height: f32,
+    pub temperature: f32,
+}
+
+impl Sounding {
+    // Lowest height at which the temperature falls through `temperature`,
+    // interpolated linearly between levels.
+    pub fn height_of_temperature(&self, temperature: f32) -> Option<f32> {
+        let first = self.levels.first()?;
+        if first.temperature <= temperature {
+            return Some(first.height);
+        }
+        self.levels.windows(2).find_map(|w| {
+            let (lower, upper) = (w[0], w[1]);
+            if lower.temperature > temperature && upper.temperature <= temperature {
+                let t = (lower.temperature - temperature) / (lower.temperature - upper.temperature);
+                Some(lower.height + t * (upper.height - lower.height))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+// Reads a plain-text sounding in the University of Wyoming TEXT:LIST layout,
+// whose rows are fixed 7-character columns starting with pressure (hPa),
+// height (m) and temperature (C). Missing values are left blank, so the
+// columns are sliced by position rather than split on whitespace. Header,
+// separator and rows without all three values are skipped.
+pub fn parse_sounding(text: &str) -> anyhow::Result<Sounding> {
+    const WIDTH: usize = 7;
+    let column = |line: &str, i: usize| -> Option<f32> {
+        line.get(i * WIDTH..(i + 1) * WIDTH)
+            .or_else(|| line.get(i * WIDTH..))?
+            .trim()
+            .parse()
+            .ok()
+    };
+    let mut levels: Vec<SoundingLevel> = text
+        .lines()
+        .filter_map(|line| {
+            Some(SoundingLevel {
+                pressure: column(line, 0)?,
+                height: column(line, 1)?,
+                temperature: column(line, 2)?,
+            })
+        })
+        .collect();
+    if levels.len() < 2 {
+        anyhow::bail!(
+            "Sounding has {} usable levels, need at least 2",
+            levels.len()
+        );
+    }
+    levels.sort_by(|a, b| a.height.total_cmp(&b.height));
+    Ok(Sounding { levels })
+}
+
+pub fn read_sounding(fp: &str) -> anyhow::Result<Sounding> {
+    parse_sounding(&fs::read_to_string(fp)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_temperature_rows_are_skipped() {
+        let text = [
+            "-----------------------------------------------------------------------------",
+            "   PRES   HGHT   TEMP   DWPT   RELH   MIXR   DRCT   SKNT   THTA   THTE   THTV",
+            "    hPa     m      C      C      %    g/kg    deg   knot     K      K      K ",
+            "-----------------------------------------------------------------------------",
+            " 1000.0    106",
+            "  978.0    357   23.4   17.4     69  12.92    170     12  298.7  336.1  301.0",
+            "  850.0   1497                                   270     15",
+            "  700.0   3120    8.2   -1.8     49   4.73    250     25  320.7  335.6  321.6",
+            "  500.0   5850  -10.9  -30.9     18   0.53    260     40  330.5  332.4  330.6",
+        ]
+        .join("\n");
+        let sounding = parse_sounding(&text).unwrap();
+        let heights: Vec<f32> = sounding.levels.iter().map(|l| l.height).collect();
+        assert_eq!(heights, vec![357.0, 3120.0, 5850.0]);
+        assert_eq!(sounding.levels[1].temperature, 8.2);
+
+        let zero = sounding.height_of_temperature(0.0).unwrap();
+        assert!(
+            (zero - (3120.0 + 8.2 / 19.1 * 2730.0)).abs() < 1.0,
+            "{}",
+            zero
+        );
+    }
+
+    #[test]
+    fn too_few_levels_is_an_error() {
+        assert!(parse_sounding("  850.0   1497\n  700.0   3120    8.2").is_err());
+    }
+}
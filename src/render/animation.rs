@@ -0,0 +1,176 @@
+use std::fs;
+
+use crate::reader::read_volume;
+use crate::render::colors::ColorTable;
+use crate::render::{render_sweep, Image, PpiOptions};
+use crate::volume::{Moment, Volume};
+
+// Animated loops of one tilt and moment across a series of volumes from the
+// same radar. Each frame is a titled PPI, so it carries its own volume time;
+// frames are ordered by scan time whatever order the files come in.
+
+#[derive(Debug, Clone)]
+pub struct LoopOptions {
+    pub moment: Moment,
+    // Target elevation in degrees; each volume uses its nearest sweep.
+    pub elevation: f32,
+    pub table: ColorTable,
+    pub ppi: PpiOptions,
+    // Seconds each frame is shown, and the longer dwell on the latest one.
+    pub frame_delay: f32,
+    pub final_delay: f32,
+}
+
+impl Default for LoopOptions {
+    fn default() -> Self {
+        LoopOptions {
+            moment: Moment::Reflectivity,
+            elevation: 0.5,
+            table: ColorTable::reflectivity(),
+            ppi: PpiOptions::default(),
+            frame_delay: 0.5,
+            final_delay: 2.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopFrame {
+    pub time: f64,
+    pub image: Image,
+}
+
+// The frame for one volume, or None when it has no sweep with the moment
+// (e.g. a metadata-only file).
+pub fn render_frame(volume: &Volume, opts: &LoopOptions) -> anyhow::Result<Option<LoopFrame>> {
+    let Some(sweep) = volume.nearest_sweep(opts.moment, opts.elevation) else {
+        return Ok(None);
+    };
+    let image = render_sweep(&volume.header, sweep, opts.moment, &opts.table, &opts.ppi)?;
+    Ok(Some(LoopFrame {
+        time: sweep.start_time().unwrap_or_default(),
+        image,
+    }))
+}
+
+// Reads and renders each Archive II file in turn so only one volume is held
+// in memory at a time.
+pub fn render_loop(paths: &[String], opts: &LoopOptions) -> anyhow::Result<Vec<LoopFrame>> {
+    let mut site: Option<String> = None;
+    let mut frames = Vec::new();
+    for path in paths.iter() {
+        let volume =
+            read_volume(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+        let icao = volume.header.icao.trim().to_string();
+        match &site {
+            Some(s) if *s != icao => {
+                anyhow::bail!(
+                    "{} is from {}, expected {} for the whole loop",
+                    path,
+                    icao,
+                    s
+                )
+            }
+            _ => site = Some(icao),
+        }
+        if let Some(frame) = render_frame(&volume, opts)? {
+            frames.push(frame);
+        }
+    }
+    if frames.is_empty() {
+        anyhow::bail!("No volume has {} data to animate", opts.moment.block_name());
+    }
+    frames.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(frames)
+}
+
+// Regular files in `dir`, sorted by name.
+pub fn archive_files(dir: &str) -> anyhow::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            paths.push(entry.path().to_string_lossy().into_owned());
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+pub fn encode_gif(frames: &[LoopFrame], opts: &LoopOptions) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = frame_size(frames)?;
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        anyhow::bail!("Frames are too large for a GIF");
+    }
+    let mut out = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut out, width as u16, height as u16, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for (i, frame) in frames.iter().enumerate() {
+            let mut pixels = frame.image.pixels.concat();
+            // Each frame gets its own quantized palette.
+            let mut gif_frame =
+                gif::Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, 10);
+            // GIF delays are in hundredths of a second.
+            gif_frame.delay = (delay(frames, i, opts) * 100.0).round() as u16;
+            encoder.write_frame(&gif_frame)?;
+        }
+    }
+    Ok(out)
+}
+
+pub fn encode_apng(frames: &[LoopFrame], opts: &LoopOptions) -> anyhow::Result<Vec<u8>> {
+    let (width, height) = frame_size(frames)?;
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (i, frame) in frames.iter().enumerate() {
+        // Delays in milliseconds.
+        writer.set_frame_delay((delay(frames, i, opts) * 1000.0).round() as u16, 1000)?;
+        writer.write_image_data(&frame.image.pixels.concat())?;
+    }
+    writer.finish()?;
+    Ok(out)
+}
+
+// Renders the loop and writes it as a GIF, or as an APNG for .png/.apng paths.
+pub fn write_loop(paths: &[String], fp: &str, opts: &LoopOptions) -> anyhow::Result<()> {
+    let extension = fp
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let encode = match extension.as_str() {
+        "gif" => encode_gif,
+        "png" | "apng" => encode_apng,
+        _ => anyhow::bail!("Unknown loop format for {}; use .gif, .png or .apng", fp),
+    };
+    let frames = render_loop(paths, opts)?;
+    fs::write(fp, encode(&frames, opts)?)?;
+    Ok(())
+}
+
+fn frame_size(frames: &[LoopFrame]) -> anyhow::Result<(u32, u32)> {
+    let first = frames
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("No frames to animate"))?;
+    let size = (first.image.width, first.image.height);
+    if frames
+        .iter()
+        .any(|f| (f.image.width, f.image.height) != size)
+    {
+        anyhow::bail!("All loop frames must be the same size");
+    }
+    Ok(size)
+}
+
+fn delay(frames: &[LoopFrame], i: usize, opts: &LoopOptions) -> f32 {
+    if i + 1 == frames.len() {
+        opts.final_delay
+    } else {
+        opts.frame_delay
+    }
+}
@@ -0,0 +1,270 @@
+use crate::volume::Moment;
+
+// Piecewise color tables. Each stop colors values from its own value up to
+// the next stop: solid stops keep one color, the others blend from `color`
+// to `end` (or to the next stop's color when `end` is unset). Values below
+// the first stop are left transparent and values above the last take its
+// color. Stops, steps and legend labels are in table units, which are the
+// data units times `scale` plus `offset` (e.g. knots for a velocity table).
+
+pub type Rgba = [u8; 4];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorStop {
+    pub value: f32,
+    pub color: Rgba,
+    pub end: Option<Rgba>,
+    pub solid: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorTable {
+    pub name: String,
+    pub units: String,
+    // Stops in ascending order of value.
+    pub stops: Vec<ColorStop>,
+    pub range_folded: Option<Rgba>,
+    // Color for gates without data inside the sweep's coverage.
+    pub no_data: Option<Rgba>,
+    pub scale: f32,
+    pub offset: f32,
+    // Spacing of legend labels in table units.
+    pub step: f32,
+}
+
+impl ColorTable {
+    // Color for a data value in the field's own units.
+    pub fn color(&self, value: f32) -> Option<Rgba> {
+        self.color_of(value * self.scale + self.offset)
+    }
+
+    // Color for a value already in table units.
+    pub fn color_of(&self, value: f32) -> Option<Rgba> {
+        if !value.is_finite() {
+            return None;
+        }
+        let i = self.stops.iter().rposition(|s| s.value <= value)?;
+        let stop = &self.stops[i];
+        let Some(next) = self.stops.get(i + 1) else {
+            return Some(stop.color);
+        };
+        if stop.solid {
+            return Some(stop.color);
+        }
+        let end = stop.end.unwrap_or(next.color);
+        let t = ((value - stop.value) / (next.value - stop.value)).clamp(0.0, 1.0);
+        Some(blend(stop.color, end, t))
+    }
+
+    pub fn min_value(&self) -> f32 {
+        self.stops.first().map_or(0.0, |s| s.value)
+    }
+
+    pub fn max_value(&self) -> f32 {
+        self.stops.last().map_or(0.0, |s| s.value)
+    }
+
+    pub fn for_moment(moment: Moment) -> ColorTable {
+        match moment {
+            Moment::Reflectivity => ColorTable::reflectivity(),
+            Moment::Velocity => ColorTable::velocity(),
+            Moment::SpectrumWidth => ColorTable::spectrum_width(),
+            Moment::DifferentialReflectivity => ColorTable::differential_reflectivity(),
+            Moment::CorrelationCoefficient => ColorTable::correlation_coefficient(),
+            Moment::DifferentialPhase => ColorTable::differential_phase(),
+            Moment::ClutterFilterPower => ColorTable::clutter_filter_power(),
+        }
+    }
+
+    // The NWS 5 dBZ reflectivity steps.
+    pub fn reflectivity() -> ColorTable {
+        ColorTable::solid(
+            "Reflectivity",
+            "dBZ",
+            10.0,
+            &[
+                (5.0, [4, 233, 231]),
+                (10.0, [1, 159, 244]),
+                (15.0, [3, 0, 244]),
+                (20.0, [2, 253, 2]),
+                (25.0, [1, 197, 1]),
+                (30.0, [0, 142, 0]),
+                (35.0, [253, 248, 2]),
+                (40.0, [229, 188, 0]),
+                (45.0, [253, 149, 0]),
+                (50.0, [253, 0, 0]),
+                (55.0, [212, 0, 0]),
+                (60.0, [188, 0, 0]),
+                (65.0, [248, 0, 253]),
+                (70.0, [152, 84, 198]),
+                (75.0, [253, 253, 253]),
+                (80.0, [253, 253, 253]),
+            ],
+        )
+    }
+
+    // Inbound green, outbound red, darkest near zero.
+    pub fn velocity() -> ColorTable {
+        let mut table = ColorTable::gradient(
+            "Velocity",
+            "m/s",
+            10.0,
+            &[
+                (-64.0, [160, 255, 200]),
+                (-48.0, [0, 255, 0]),
+                (-32.0, [0, 200, 0]),
+                (-16.0, [0, 130, 0]),
+                (-4.0, [0, 80, 0]),
+                (-1.0, [110, 120, 110]),
+                (1.0, [80, 0, 0]),
+                (4.0, [110, 0, 0]),
+                (16.0, [170, 0, 0]),
+                (32.0, [230, 0, 0]),
+                (48.0, [255, 90, 90]),
+                (64.0, [255, 200, 160]),
+            ],
+        );
+        table.stops[5].solid = true;
+        table.range_folded = Some([119, 0, 125, 255]);
+        table
+    }
+
+    pub fn spectrum_width() -> ColorTable {
+        ColorTable::gradient(
+            "Spectrum Width",
+            "m/s",
+            2.0,
+            &[
+                (0.0, [40, 40, 40]),
+                (2.0, [120, 120, 120]),
+                (4.0, [190, 190, 190]),
+                (6.0, [230, 200, 0]),
+                (8.0, [255, 120, 0]),
+                (10.0, [220, 0, 0]),
+                (14.0, [255, 0, 255]),
+            ],
+        )
+    }
+
+    pub fn differential_reflectivity() -> ColorTable {
+        ColorTable::gradient(
+            "Differential Reflectivity",
+            "dB",
+            1.0,
+            &[
+                (-4.0, [0, 0, 0]),
+                (-1.0, [150, 150, 150]),
+                (0.0, [30, 30, 150]),
+                (1.0, [0, 160, 240]),
+                (2.0, [0, 200, 0]),
+                (3.0, [255, 255, 0]),
+                (4.0, [255, 130, 0]),
+                (5.0, [255, 0, 0]),
+                (6.0, [200, 0, 100]),
+                (8.0, [255, 255, 255]),
+            ],
+        )
+    }
+
+    pub fn correlation_coefficient() -> ColorTable {
+        ColorTable::gradient(
+            "Correlation Coefficient",
+            "",
+            0.1,
+            &[
+                (0.2, [20, 20, 120]),
+                (0.5, [100, 100, 230]),
+                (0.7, [0, 200, 230]),
+                (0.8, [0, 220, 0]),
+                (0.9, [255, 255, 0]),
+                (0.95, [255, 130, 0]),
+                (0.98, [220, 0, 0]),
+                (1.0, [150, 0, 60]),
+                (1.05, [255, 200, 255]),
+            ],
+        )
+    }
+
+    pub fn specific_differential_phase() -> ColorTable {
+        ColorTable::gradient(
+            "Specific Differential Phase",
+            "deg/km",
+            1.0,
+            &[
+                (-2.0, [100, 100, 100]),
+                (0.0, [200, 200, 200]),
+                (0.5, [0, 200, 255]),
+                (1.0, [0, 200, 0]),
+                (2.0, [255, 255, 0]),
+                (3.0, [255, 130, 0]),
+                (5.0, [255, 0, 0]),
+                (8.0, [255, 0, 255]),
+                (10.0, [255, 255, 255]),
+            ],
+        )
+    }
+
+    pub fn differential_phase() -> ColorTable {
+        ColorTable::gradient(
+            "Differential Phase",
+            "deg",
+            45.0,
+            &[
+                (0.0, [40, 0, 80]),
+                (90.0, [0, 90, 220]),
+                (180.0, [0, 200, 100]),
+                (270.0, [250, 220, 0]),
+                (360.0, [255, 60, 0]),
+            ],
+        )
+    }
+
+    pub fn clutter_filter_power() -> ColorTable {
+        ColorTable::gradient(
+            "Clutter Filter Power Removed",
+            "dB",
+            10.0,
+            &[
+                (0.0, [30, 30, 30]),
+                (20.0, [120, 120, 200]),
+                (40.0, [255, 255, 0]),
+                (60.0, [255, 0, 0]),
+            ],
+        )
+    }
+
+    fn solid(name: &str, units: &str, step: f32, stops: &[(f32, [u8; 3])]) -> ColorTable {
+        let mut table = ColorTable::gradient(name, units, step, stops);
+        table.stops.iter_mut().for_each(|s| s.solid = true);
+        table
+    }
+
+    fn gradient(name: &str, units: &str, step: f32, stops: &[(f32, [u8; 3])]) -> ColorTable {
+        ColorTable {
+            name: name.to_string(),
+            units: units.to_string(),
+            stops: stops
+                .iter()
+                .map(|&(value, [r, g, b])| ColorStop {
+                    value,
+                    color: [r, g, b, 255],
+                    end: None,
+                    solid: false,
+                })
+                .collect(),
+            range_folded: None,
+            no_data: None,
+            scale: 1.0,
+            offset: 0.0,
+            step,
+        }
+    }
+}
+
+pub fn blend(a: Rgba, b: Rgba, t: f32) -> Rgba {
+    let mut out = [0; 4];
+    for (o, (&x, &y)) in out.iter_mut().zip(a.iter().zip(b.iter())) {
+        *o = (x as f32 + (y as f32 - x as f32) * t).round() as u8;
+    }
+    out
+}
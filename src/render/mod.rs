@@ -0,0 +1,372 @@
+pub mod animation;
+pub mod colors;
+pub mod font;
+pub mod palette;
+
+use crate::export::iso8601;
+use crate::geometry::slant_range;
+use crate::grid::GriddedField;
+use crate::messages::VolumeHeader;
+use crate::render::colors::{blend, ColorTable, Rgba};
+use crate::render::font::{draw_text, text_width, GLYPH_HEIGHT};
+use crate::volume::{angle_difference, collection_time_to_epoch, Moment, PolarField, Sweep};
+
+// Quick-look imagery. A PPI is drawn by looking up, for every pixel, the
+// gate under it: the pixel's ground distance from the radar is converted to
+// slant range on the 4/3 earth beam and its bearing picks the nearest radial.
+
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    // Row-major RGBA pixels, top row first.
+    pub pixels: Vec<Rgba>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32, fill: Rgba) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![fill; (width * height) as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Rgba {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    // Blends `color` over the pixel at (x, y); points off the image are ignored.
+    pub fn put(&mut self, x: i64, y: i64, color: Rgba) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let pixel = &mut self.pixels[(y as u32 * self.width + x as u32) as usize];
+        if color[3] == 255 {
+            *pixel = color;
+        } else if color[3] > 0 {
+            let alpha = pixel[3].max(color[3]);
+            *pixel = blend(*pixel, color, color[3] as f32 / 255.0);
+            pixel[3] = alpha;
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i64, y: i64, width: u32, height: u32, color: Rgba) {
+        for dy in 0..height as i64 {
+            for dx in 0..width as i64 {
+                self.put(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    pub fn encode_png(&self) -> anyhow::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::new(&mut out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels.concat())?;
+        writer.finish()?;
+        Ok(out)
+    }
+
+    pub fn write_png(&self, fp: &str) -> anyhow::Result<()> {
+        std::fs::write(fp, self.encode_png()?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PpiOptions {
+    // Width and height of the square plot in pixels.
+    pub size: u32,
+    // Range at the plot edge in metres; defaults to the end of the field.
+    pub max_range: Option<f32>,
+    // Range ring spacing in metres; 0 disables the rings.
+    pub ring_spacing: f32,
+    pub legend: bool,
+    pub background: Rgba,
+    pub foreground: Rgba,
+    pub ring_color: Rgba,
+    pub text_scale: u32,
+}
+
+impl Default for PpiOptions {
+    fn default() -> Self {
+        PpiOptions {
+            size: 800,
+            max_range: None,
+            ring_spacing: 50_000.0,
+            legend: true,
+            background: [0, 0, 0, 255],
+            foreground: [255, 255, 255, 255],
+            ring_color: [128, 128, 128, 255],
+            text_scale: 2,
+        }
+    }
+}
+
+// Renders one moment of a sweep with its range-folded gates in the table's
+// range-folded color, titled with the site and volume time.
+pub fn render_sweep(
+    header: &VolumeHeader,
+    sweep: &Sweep,
+    moment: Moment,
+    table: &ColorTable,
+    opts: &PpiOptions,
+) -> anyhow::Result<Image> {
+    let field = sweep
+        .field(moment)
+        .ok_or_else(|| anyhow::anyhow!("Sweep has no {} data", moment.block_name()))?;
+    // Same radial order as Sweep::field.
+    let mut radials: Vec<_> = sweep
+        .radials
+        .iter()
+        .filter_map(|r| r.moment(moment).map(|m| (r.header.azimuth_angle, m)))
+        .collect();
+    radials.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let folded: Vec<Vec<bool>> = radials
+        .iter()
+        .map(|(_, m)| (0..m.gates.len()).map(|g| m.is_range_folded(g)).collect())
+        .collect();
+
+    let title = ppi_title(header, sweep.elevation_angle, table);
+    Ok(render(&field, Some(&folded), table, &title, opts))
+}
+
+// Renders any polar field, e.g. a derived product such as KDP. An empty
+// title leaves out the title bar.
+pub fn render_ppi(field: &PolarField, table: &ColorTable, title: &str, opts: &PpiOptions) -> Image {
+    render(field, None, table, title, opts)
+}
+
+// Renders a lat/lon grid at one pixel per cell on a transparent background,
+// suitable for draping over a map.
+pub fn render_grid(field: &GriddedField, table: &ColorTable) -> Image {
+    let spec = field.spec;
+    let mut image = Image::new(spec.n_cols as u32, spec.n_rows as u32, [0, 0, 0, 0]);
+    for (row, values) in field.data.iter().enumerate() {
+        for (col, &value) in values.iter().enumerate() {
+            if let Some(color) = table.color(value) {
+                image.put(col as i64, row as i64, color);
+            }
+        }
+    }
+    image
+}
+
+pub fn ppi_title(header: &VolumeHeader, elevation: f32, table: &ColorTable) -> String {
+    let time = iso8601(collection_time_to_epoch(header.date as i16, header.time));
+    let label = if table.units.is_empty() {
+        table.name.clone()
+    } else {
+        format!("{} ({})", table.name, table.units)
+    };
+    format!(
+        "{} {} {} UTC  {:.1}° {}",
+        header.icao.trim(),
+        &time[..10],
+        &time[11..19],
+        elevation,
+        label
+    )
+}
+
+fn render(
+    field: &PolarField,
+    folded: Option<&[Vec<bool>]>,
+    table: &ColorTable,
+    title: &str,
+    opts: &PpiOptions,
+) -> Image {
+    let scale = opts.text_scale.max(1);
+    let line = GLYPH_HEIGHT * scale;
+    let pad = 4 * scale;
+    let title_height = if title.is_empty() { 0 } else { line + 2 * pad };
+    let labels = legend_labels(table);
+    let legend_width = if opts.legend {
+        let widest = labels
+            .iter()
+            .map(|(_, text)| text_width(text, scale))
+            .chain([text_width(&table.units, scale)])
+            .max()
+            .unwrap_or(0);
+        3 * pad + 10 * scale + widest + pad
+    } else {
+        0
+    };
+    let size = opts.size;
+    let mut image = Image::new(size + legend_width, size + title_height, opts.background);
+
+    let max_range = opts
+        .max_range
+        .unwrap_or_else(|| field.gate_range(field.n_gates()) - field.gate_spacing / 2.0)
+        .max(1.0);
+    let metres_per_pixel = 2.0 * max_range as f64 / size as f64;
+    let half = size as f64 / 2.0;
+
+    // Nearest radial for every tenth of a degree.
+    let tolerance = 360.0 / field.n_radials().max(1) as f32;
+    let lookup: Vec<Option<usize>> = (0..3600)
+        .map(|i| {
+            let azimuth = i as f32 / 10.0;
+            field
+                .radial_at_azimuth(azimuth)
+                .filter(|&r| angle_difference(field.azimuths[r], azimuth).abs() <= tolerance)
+        })
+        .collect();
+
+    for py in 0..size {
+        for px in 0..size {
+            let x = (px as f64 + 0.5 - half) * metres_per_pixel;
+            let y = (half - py as f64 - 0.5) * metres_per_pixel;
+            let ground = x.hypot(y);
+            if ground > max_range as f64 {
+                continue;
+            }
+            let bearing = x.atan2(y).to_degrees().rem_euclid(360.0);
+            let Some(radial) = lookup[((bearing * 10.0).round() as usize) % 3600] else {
+                continue;
+            };
+            let range = slant_range(ground, field.elevation as f64) as f32;
+            let Some(gate) = field.gate_at_range(range) else {
+                continue;
+            };
+            let color = table.color(field.get(radial, gate)).or_else(|| {
+                let is_folded = folded
+                    .and_then(|f| f.get(radial))
+                    .and_then(|r| r.get(gate))
+                    .copied()
+                    .unwrap_or(false);
+                if is_folded {
+                    table.range_folded
+                } else {
+                    table.no_data
+                }
+            });
+            if let Some(color) = color {
+                image.put(px as i64, (py + title_height) as i64, color);
+            }
+        }
+    }
+
+    if opts.ring_spacing > 0.0 {
+        let centre = (half, half + title_height as f64);
+        let mut ring = opts.ring_spacing;
+        while ring <= max_range {
+            let radius = ring as f64 / metres_per_pixel;
+            let steps = (2.0 * std::f64::consts::PI * radius).ceil().max(8.0) as usize * 2;
+            for i in 0..steps {
+                let angle = i as f64 / steps as f64 * 2.0 * std::f64::consts::PI;
+                image.put(
+                    (centre.0 + radius * angle.sin()).floor() as i64,
+                    (centre.1 - radius * angle.cos()).floor() as i64,
+                    opts.ring_color,
+                );
+            }
+            let label = format!("{} km", (ring / 1000.0).round());
+            draw_text(
+                &mut image,
+                (centre.0 + pad as f64) as i64,
+                (centre.1 - radius) as i64 + pad as i64,
+                &label,
+                opts.ring_color,
+                scale,
+            );
+            ring += opts.ring_spacing;
+        }
+    }
+
+    if !title.is_empty() {
+        draw_text(
+            &mut image,
+            pad as i64,
+            pad as i64,
+            title,
+            opts.foreground,
+            scale,
+        );
+    }
+
+    if opts.legend {
+        let x = (size + 2 * pad) as i64;
+        let top = title_height + line + 2 * pad;
+        let swatch = if table.range_folded.is_some() {
+            line + 2 * pad
+        } else {
+            0
+        };
+        let bar_height = (size.saturating_sub(line + 3 * pad + swatch)).max(2);
+        let bar_width = 10 * scale;
+        draw_text(
+            &mut image,
+            x,
+            (title_height + pad) as i64,
+            &table.units,
+            opts.foreground,
+            scale,
+        );
+        let (min, max) = (table.min_value(), table.max_value());
+        let value_at = |row: u32| max - (max - min) * row as f32 / (bar_height - 1) as f32;
+        for row in 0..bar_height {
+            if let Some(color) = table.color_of(value_at(row)) {
+                image.fill_rect(x, (top + row) as i64, bar_width, 1, color);
+            }
+        }
+        // Labels run from the top of the bar down, skipping any that would
+        // overlap the one above.
+        let mut last_label: Option<i64> = None;
+        for (value, text) in labels.iter().rev() {
+            let row = ((max - value) / (max - min) * (bar_height - 1) as f32).round() as i64;
+            let y = top as i64 + row;
+            if last_label.is_some_and(|last| y - last < (line + scale) as i64) {
+                continue;
+            }
+            last_label = Some(y);
+            image.fill_rect(x + bar_width as i64, y, 2 * scale, 1, opts.foreground);
+            draw_text(
+                &mut image,
+                x + (bar_width + pad) as i64,
+                y - (line / 2) as i64,
+                text,
+                opts.foreground,
+                scale,
+            );
+        }
+        if let Some(color) = table.range_folded {
+            let y = (top + bar_height + pad) as i64;
+            image.fill_rect(x, y, bar_width, line, color);
+            draw_text(
+                &mut image,
+                x + (bar_width + pad) as i64,
+                y,
+                "RF",
+                opts.foreground,
+                scale,
+            );
+        }
+    }
+
+    image
+}
+
+// Legend labels at multiples of the table step.
+fn legend_labels(table: &ColorTable) -> Vec<(f32, String)> {
+    let (min, max) = (table.min_value(), table.max_value());
+    if table.step <= 0.0 || max <= min {
+        return Vec::new();
+    }
+    let decimals = if table.step >= 1.0 {
+        0
+    } else {
+        (-table.step.log10()).ceil() as usize
+    };
+    let first = (min / table.step).ceil() as i64;
+    let last = (max / table.step).floor() as i64;
+    (first..=last)
+        .map(|i| {
+            let value = i as f32 * table.step;
+            (value, format!("{:.*}", decimals, value))
+        })
+        .collect()
+}
@@ -0,0 +1,207 @@
+use std::fs;
+
+use crate::render::colors::{ColorStop, ColorTable, Rgba};
+
+// GRLevelX / GR2Analyst color table (.pal) files. Each line is `Key: values`
+// with `;` starting a comment:
+//
+//   Product: BR
+//   Units: DBZ
+//   Step: 5
+//   Scale: 1.9438   ; data multiplied by this before lookup, e.g. m/s to kt
+//   Offset: 0
+//   RF: 119 0 125
+//   ND: 0 0 0
+//   Color: 10 164 164 255 100 100 192
+//   Color4: 20 64 128 255 255
+//   SolidColor: 65 255 0 255
+//   SolidColor4: 75 255 255 255 255
+//
+// A Color entry blends from its color to its optional second color, or to
+// the next entry's color, over the interval up to the next entry;
+// SolidColor entries fill their interval with one color. The 4 variants add
+// alpha. Unrecognized keys are ignored so files written for newer viewers
+// still load.
+
+pub fn parse_pal(text: &str) -> anyhow::Result<ColorTable> {
+    let mut table = ColorTable {
+        name: String::new(),
+        units: String::new(),
+        stops: Vec::new(),
+        range_folded: None,
+        no_data: None,
+        scale: 1.0,
+        offset: 0.0,
+        step: 0.0,
+    };
+
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let numbers = || -> anyhow::Result<Vec<f32>> {
+            value
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|f| !f.is_empty())
+                .map(|f| {
+                    f.parse::<f32>()
+                        .map_err(|_| anyhow::anyhow!("Line {}: {:?} is not a number", n + 1, f))
+                })
+                .collect()
+        };
+        match key.as_str() {
+            "product" => table.name = value.to_string(),
+            "units" => table.units = value.to_string(),
+            "step" => table.step = scalar(&numbers()?, n)?,
+            "scale" => table.scale = scalar(&numbers()?, n)?,
+            "offset" => table.offset = scalar(&numbers()?, n)?,
+            "rf" => table.range_folded = Some(single_color(&numbers()?, n)?),
+            "nd" => table.no_data = Some(single_color(&numbers()?, n)?),
+            "color" | "color4" | "solidcolor" | "solidcolor4" => {
+                let alpha = key.ends_with('4');
+                let values = numbers()?;
+                let channels = if alpha { 4 } else { 3 };
+                let (value, colors) = values
+                    .split_first()
+                    .ok_or_else(|| anyhow::anyhow!("Line {}: empty {} entry", n + 1, key))?;
+                if colors.len() != channels && colors.len() != 2 * channels {
+                    anyhow::bail!(
+                        "Line {}: {} needs a value and {} or {} color components, got {}",
+                        n + 1,
+                        key,
+                        channels,
+                        2 * channels,
+                        colors.len()
+                    );
+                }
+                let mut rgba = colors.chunks(channels).map(to_rgba);
+                table.stops.push(ColorStop {
+                    value: *value,
+                    color: rgba.next().expect("at least one color was checked"),
+                    end: rgba.next(),
+                    solid: key.starts_with("solid"),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if table.stops.is_empty() {
+        anyhow::bail!("Color table has no Color or SolidColor entries");
+    }
+    table.stops.sort_by(|a, b| a.value.total_cmp(&b.value));
+    if table.step <= 0.0 {
+        // Roughly ten legend labels when the file does not set a step.
+        let span = table.max_value() - table.min_value();
+        table.step = if span > 0.0 {
+            10f32.powf((span / 10.0).log10().round())
+        } else {
+            1.0
+        };
+    }
+    Ok(table)
+}
+
+pub fn read_pal(fp: &str) -> anyhow::Result<ColorTable> {
+    parse_pal(&fs::read_to_string(fp)?)
+}
+
+fn scalar(values: &[f32], line: usize) -> anyhow::Result<f32> {
+    match values {
+        [value] => Ok(*value),
+        _ => anyhow::bail!("Line {}: expected a single number", line + 1),
+    }
+}
+
+fn single_color(values: &[f32], line: usize) -> anyhow::Result<Rgba> {
+    match values.len() {
+        3 | 4 => Ok(to_rgba(values)),
+        _ => anyhow::bail!("Line {}: expected 3 or 4 color components", line + 1),
+    }
+}
+
+fn to_rgba(components: &[f32]) -> Rgba {
+    let channel = |i: usize| {
+        components
+            .get(i)
+            .map_or(255, |c| c.round().clamp(0.0, 255.0) as u8)
+    };
+    [channel(0), channel(1), channel(2), channel(3)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAL: &str = "\
+; Base reflectivity in knots
+Product: BR
+Units: DBZ
+Step: 5
+Scale: 2   ; doubled before lookup
+Offset: -10
+RF: 119 0 125
+ND: 0 0 0 128
+Color: 10 100 100 100 200 200 200
+Color4: 20 0 0 255 0
+SolidColor: 40 255 0 255
+SolidColor4: 50 255 255 255 128
+Smoothing: true
+";
+
+    #[test]
+    fn entries_and_keys() {
+        let table = parse_pal(PAL).unwrap();
+        assert_eq!((table.name.as_str(), table.units.as_str()), ("BR", "DBZ"));
+        assert_eq!((table.step, table.scale, table.offset), (5.0, 2.0, -10.0));
+        assert_eq!(table.range_folded, Some([119, 0, 125, 255]));
+        assert_eq!(table.no_data, Some([0, 0, 0, 128]));
+
+        let stops: Vec<(f32, Rgba, Option<Rgba>, bool)> = table
+            .stops
+            .iter()
+            .map(|s| (s.value, s.color, s.end, s.solid))
+            .collect();
+        assert_eq!(
+            stops,
+            vec![
+                (
+                    10.0,
+                    [100, 100, 100, 255],
+                    Some([200, 200, 200, 255]),
+                    false
+                ),
+                (20.0, [0, 0, 255, 0], None, false),
+                (40.0, [255, 0, 255, 255], None, true),
+                (50.0, [255, 255, 255, 128], None, true),
+            ]
+        );
+
+        // The two-color entry blends to its own end color, not the next stop.
+        assert_eq!(table.color_of(15.0), Some([150, 150, 150, 255]));
+        // The single-color entry blends to the next stop, alpha included.
+        assert_eq!(table.color_of(30.0), Some([128, 0, 255, 128]));
+        assert_eq!(table.color_of(45.0), Some([255, 0, 255, 255]));
+        // Scale and offset apply to field values: 25 * 2 - 10 = 40.
+        assert_eq!(table.color(25.0), Some([255, 0, 255, 255]));
+        assert_eq!(table.color_of(5.0), None);
+    }
+
+    #[test]
+    fn malformed_entries_are_errors() {
+        for bad in [
+            "Color: 10 255 0",
+            "Color: 10 255 0 0 255",
+            "Color4: 10 255 0 0",
+            "SolidColor: 10 255 0 zero",
+            "Color: 10 255 0 0\nScale: fast",
+            "Color: 10 255 0 0\nRF: 1 2",
+            "Product: BR",
+        ] {
+            assert!(parse_pal(bad).is_err(), "{:?}", bad);
+        }
+    }
+}
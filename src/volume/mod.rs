@@ -0,0 +1,394 @@
+use serde::{Deserialize, Serialize};
+
+use crate::messages::{
+    DigitalRadarDataGenericFormatHeader, ElevationDataBlock, GenericMomentHeader, RadialDataBlock,
+    VolumeDataBlock, VolumeHeader,
+};
+
+#[cfg(test)]
+pub mod synthetic;
+
+// Raw gate codes shared by every moment block: 0 is below threshold and 1 is
+// range folded. Everything from 2 upward is scaled data.
+pub const GATE_BELOW_THRESHOLD: u16 = 0;
+pub const GATE_RANGE_FOLDED: u16 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Moment {
+    Reflectivity,
+    Velocity,
+    SpectrumWidth,
+    DifferentialReflectivity,
+    DifferentialPhase,
+    CorrelationCoefficient,
+    ClutterFilterPower,
+}
+
+impl Moment {
+    pub fn from_block_name(name: &str) -> Option<Moment> {
+        match name {
+            "REF" => Some(Moment::Reflectivity),
+            "VEL" => Some(Moment::Velocity),
+            "SW " => Some(Moment::SpectrumWidth),
+            "ZDR" => Some(Moment::DifferentialReflectivity),
+            "PHI" => Some(Moment::DifferentialPhase),
+            "RHO" => Some(Moment::CorrelationCoefficient),
+            "CFP" => Some(Moment::ClutterFilterPower),
+            _ => None,
+        }
+    }
+
+    pub fn block_name(&self) -> &'static str {
+        match self {
+            Moment::Reflectivity => "REF",
+            Moment::Velocity => "VEL",
+            Moment::SpectrumWidth => "SW ",
+            Moment::DifferentialReflectivity => "ZDR",
+            Moment::DifferentialPhase => "PHI",
+            Moment::CorrelationCoefficient => "RHO",
+            Moment::ClutterFilterPower => "CFP",
+        }
+    }
+
+    pub fn units(&self) -> &'static str {
+        match self {
+            Moment::Reflectivity => "dBZ",
+            Moment::Velocity | Moment::SpectrumWidth => "m/s",
+            Moment::DifferentialReflectivity => "dB",
+            Moment::DifferentialPhase => "degrees",
+            Moment::CorrelationCoefficient => "unitless",
+            Moment::ClutterFilterPower => "dB",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MomentData {
+    pub moment: Moment,
+    pub header: GenericMomentHeader,
+    pub gates: Vec<u16>,
+}
+
+impl MomentData {
+    pub fn value(&self, gate: usize) -> Option<f32> {
+        match self.gates.get(gate) {
+            Some(&raw) if raw > GATE_RANGE_FOLDED => {
+                Some((raw as f32 - self.header.offset) / self.header.scale)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_range_folded(&self, gate: usize) -> bool {
+        self.gates.get(gate) == Some(&GATE_RANGE_FOLDED)
+    }
+
+    // Decoded gate values with NaN standing in for thresholded or folded gates.
+    pub fn values(&self) -> Vec<f32> {
+        (0..self.gates.len())
+            .map(|g| self.value(g).unwrap_or(f32::NAN))
+            .collect()
+    }
+
+    pub fn first_gate_range(&self) -> f32 {
+        self.header.first_gate_range as f32
+    }
+
+    pub fn gate_spacing(&self) -> f32 {
+        self.header.gate_spacing as f32
+    }
+
+    // Index of the gate nearest `range` (metres), if the block reaches it.
+    pub fn gate_at_range(&self, range: f32) -> Option<usize> {
+        let g = ((range - self.first_gate_range()) / self.gate_spacing()).round();
+        if g < 0.0 || g as usize >= self.gates.len() {
+            return None;
+        }
+        Some(g as usize)
+    }
+
+    // Decoded value of the gate nearest `range`, so moments on different gate
+    // layouts can be read onto one range axis.
+    pub fn value_at_range(&self, range: f32) -> Option<f32> {
+        self.value(self.gate_at_range(range)?)
+    }
+
+    // Gates an axis starting at `first_gate` needs to reach past this block.
+    pub fn gates_on_axis(&self, first_gate: f32, gate_spacing: f32) -> usize {
+        let last = self.first_gate_range() + self.gate_spacing() * self.gates.len() as f32;
+        ((last - first_gate) / gate_spacing).ceil().max(0.0) as usize
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Radial {
+    pub header: DigitalRadarDataGenericFormatHeader,
+    pub volume: Option<VolumeDataBlock>,
+    pub elevation: Option<ElevationDataBlock>,
+    pub radial: Option<RadialDataBlock>,
+    pub moments: Vec<MomentData>,
+}
+
+impl Radial {
+    pub fn moment(&self, moment: Moment) -> Option<&MomentData> {
+        self.moments.iter().find(|m| m.moment == moment)
+    }
+
+    // Seconds since the Unix epoch; the modified Julian date counts 1 Jan 1970 as day 1.
+    pub fn time(&self) -> f64 {
+        collection_time_to_epoch(
+            self.header.modified_julian_date,
+            self.header.collection_time,
+        )
+    }
+
+    pub fn nyquist_velocity(&self) -> Option<f32> {
+        self.radial.as_ref().map(|r| r.nyquist_velocity)
+    }
+}
+
+pub fn collection_time_to_epoch(modified_julian_date: i16, ms_from_midnight: i32) -> f64 {
+    (modified_julian_date as f64 - 1.0) * 86400.0 + ms_from_midnight as f64 / 1000.0
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sweep {
+    pub elevation_number: i8,
+    pub elevation_angle: f32,
+    pub radials: Vec<Radial>,
+}
+
+impl Sweep {
+    pub fn has_moment(&self, moment: Moment) -> bool {
+        self.radials.iter().any(|r| r.moment(moment).is_some())
+    }
+
+    pub fn start_time(&self) -> Option<f64> {
+        self.radials.iter().map(|r| r.time()).reduce(f64::min)
+    }
+
+    pub fn end_time(&self) -> Option<f64> {
+        self.radials.iter().map(|r| r.time()).reduce(f64::max)
+    }
+
+    pub fn nyquist_velocity(&self) -> Option<f32> {
+        self.radials.iter().find_map(|r| r.nyquist_velocity())
+    }
+
+    // Collects one moment across the sweep into a rectangular field, padding
+    // short radials with NaN. Radials are ordered by azimuth.
+    pub fn field(&self, moment: Moment) -> Option<PolarField> {
+        let mut radials: Vec<(&Radial, &MomentData)> = self
+            .radials
+            .iter()
+            .filter_map(|r| r.moment(moment).map(|m| (r, m)))
+            .collect();
+        let (_, first) = radials.first()?;
+        let first_gate = first.first_gate_range();
+        let gate_spacing = first.gate_spacing();
+        radials.sort_by(|a, b| {
+            a.0.header
+                .azimuth_angle
+                .total_cmp(&b.0.header.azimuth_angle)
+        });
+
+        let n_gates = radials
+            .iter()
+            .map(|(_, m)| m.gates.len())
+            .max()
+            .unwrap_or(0);
+        let mut azimuths = Vec::with_capacity(radials.len());
+        let mut times = Vec::with_capacity(radials.len());
+        let mut data = Vec::with_capacity(radials.len());
+        for (radial, moment_data) in radials {
+            let mut values = moment_data.values();
+            values.resize(n_gates, f32::NAN);
+            azimuths.push(radial.header.azimuth_angle);
+            times.push(radial.time());
+            data.push(values);
+        }
+
+        Some(PolarField {
+            elevation: self.elevation_angle,
+            azimuths,
+            times,
+            first_gate,
+            gate_spacing,
+            data,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub header: VolumeHeader,
+    pub sweeps: Vec<Sweep>,
+}
+
+impl Volume {
+    pub fn site(&self) -> Option<&VolumeDataBlock> {
+        self.sweeps
+            .iter()
+            .flat_map(|s| s.radials.iter())
+            .find_map(|r| r.volume.as_ref())
+    }
+
+    // The site, for anything that has to place gates on the earth.
+    pub fn require_site(&self) -> anyhow::Result<&VolumeDataBlock> {
+        self.site()
+            .ok_or_else(|| anyhow::anyhow!("Volume has no VOL block for the site location"))
+    }
+
+    pub fn start_time(&self) -> Option<f64> {
+        self.sweeps
+            .iter()
+            .filter_map(|s| s.start_time())
+            .reduce(f64::min)
+    }
+
+    // The sweep carrying `moment` whose elevation is closest to `elevation`.
+    pub fn nearest_sweep(&self, moment: Moment, elevation: f32) -> Option<&Sweep> {
+        self.sweeps
+            .iter()
+            .filter(|s| s.has_moment(moment))
+            .min_by(|a, b| {
+                (a.elevation_angle - elevation)
+                    .abs()
+                    .total_cmp(&(b.elevation_angle - elevation).abs())
+            })
+    }
+
+    pub fn sweeps_with(&self, moment: Moment) -> impl Iterator<Item = &Sweep> {
+        self.sweeps.iter().filter(move |s| s.has_moment(moment))
+    }
+}
+
+// A single moment on a single sweep as [radial][gate], NaN where there is no data.
+// Ranges are in metres to the gate centre.
+#[derive(Debug, Clone)]
+pub struct PolarField {
+    pub elevation: f32,
+    pub azimuths: Vec<f32>,
+    pub times: Vec<f64>,
+    pub first_gate: f32,
+    pub gate_spacing: f32,
+    pub data: Vec<Vec<f32>>,
+}
+
+impl PolarField {
+    pub fn n_radials(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn n_gates(&self) -> usize {
+        self.data.first().map_or(0, |r| r.len())
+    }
+
+    pub fn gate_range(&self, gate: usize) -> f32 {
+        self.first_gate + gate as f32 * self.gate_spacing
+    }
+
+    pub fn gate_at_range(&self, range: f32) -> Option<usize> {
+        let g = ((range - self.first_gate) / self.gate_spacing).round();
+        if g < 0.0 || g as usize >= self.n_gates() {
+            return None;
+        }
+        Some(g as usize)
+    }
+
+    // Index of the radial nearest in azimuth, accounting for the 0/360 wrap.
+    pub fn radial_at_azimuth(&self, azimuth: f32) -> Option<usize> {
+        self.azimuths
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| {
+                angle_difference(**a, azimuth)
+                    .abs()
+                    .total_cmp(&angle_difference(**b, azimuth).abs())
+            })
+            .map(|(i, _)| i)
+    }
+
+    pub fn get(&self, radial: usize, gate: usize) -> f32 {
+        self.data
+            .get(radial)
+            .and_then(|r| r.get(gate))
+            .copied()
+            .unwrap_or(f32::NAN)
+    }
+
+    // A field with the same geometry as `self` but filled with `value`.
+    pub fn filled_like(&self, value: f32) -> PolarField {
+        PolarField {
+            data: vec![vec![value; self.n_gates()]; self.n_radials()],
+            ..self.clone()
+        }
+    }
+
+    // Resamples `self` onto the radials and gates of `other` by nearest
+    // azimuth and range, so fields with different gate counts line up.
+    pub fn aligned_to(&self, other: &PolarField) -> PolarField {
+        let data = other
+            .azimuths
+            .iter()
+            .map(|&az| {
+                let radial = self.radial_at_azimuth(az);
+                (0..other.n_gates())
+                    .map(
+                        |g| match (radial, self.gate_at_range(other.gate_range(g))) {
+                            (Some(r), Some(sg)) => self.get(r, sg),
+                            _ => f32::NAN,
+                        },
+                    )
+                    .collect()
+            })
+            .collect();
+        PolarField {
+            data,
+            ..other.clone()
+        }
+    }
+}
+
+// Signed difference a - b wrapped into [-180, 180).
+pub fn angle_difference(a: f32, b: f32) -> f32 {
+    (a - b + 540.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_by_range() {
+        let moment = synthetic::moment_data(
+            Moment::Reflectivity,
+            &[10.0, 20.0, f32::NAN, 40.0],
+            2_125,
+            500,
+        );
+        assert_eq!(moment.value_at_range(2_125.0), Some(10.0));
+        assert_eq!(moment.value_at_range(2_374.0), Some(10.0));
+        assert_eq!(moment.value_at_range(2_376.0), Some(20.0));
+        assert_eq!(moment.value_at_range(3_125.0), None);
+        assert_eq!(moment.value_at_range(3_874.0), Some(40.0));
+        assert_eq!(moment.value_at_range(3_876.0), None);
+        assert_eq!(moment.value_at_range(1_800.0), None);
+        assert_eq!(moment.gates_on_axis(2_125.0, 250.0), 8);
+        assert_eq!(moment.gates_on_axis(5_000.0, 250.0), 0);
+    }
+
+    #[test]
+    fn missing_site_is_an_error() {
+        let mut volume =
+            synthetic::volume(&[0.5], 10, 30.0, &[Moment::Reflectivity], |_, _, _, _| 20.0);
+        assert_eq!(
+            volume.require_site().unwrap().site_height,
+            synthetic::SITE_HEIGHT
+        );
+        for radial in volume.sweeps[0].radials.iter_mut() {
+            radial.volume = None;
+        }
+        assert!(volume.require_site().is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::messages::{
+    DigitalRadarDataGenericFormatHeader, DigitalRadarDataGenericFormatHeaderRaw,
+    GenericMomentHeader, RadialDataBlock, VolumeDataBlock, VolumeHeader,
+};
+use crate::volume::{Moment, MomentData, Radial, Sweep, Volume, GATE_RANGE_FOLDED};
+
+// Synthetic KTLX volumes for tests: 360 one-degree radials per sweep, 50 ms
+// apart, with gates every 250 m from 2.125 km and each moment encoded with
+// its usual WSR-88D scale and offset.
+
+pub const FIRST_GATE: i16 = 2_125;
+pub const GATE_SPACING: i16 = 250;
+pub const SITE_HEIGHT: i16 = 370;
+pub const FEEDHORN_HEIGHT: u16 = 20;
+
+// Encodes `values` into a moment block, NaN becoming below threshold.
+pub fn moment_data(
+    moment: Moment,
+    values: &[f32],
+    first_gate: i16,
+    gate_spacing: i16,
+) -> MomentData {
+    let (scale, offset, data_word_size) = match moment {
+        Moment::Reflectivity => (2.0, 66.0, 8),
+        Moment::Velocity | Moment::SpectrumWidth => (2.0, 129.0, 8),
+        Moment::DifferentialReflectivity => (16.0, 128.0, 8),
+        Moment::DifferentialPhase => (2.8361, 2.0, 16),
+        Moment::CorrelationCoefficient => (300.0, -60.5, 8),
+        Moment::ClutterFilterPower => (2.0, 8.0, 8),
+    };
+    let max_raw = if data_word_size == 16 { 1023.0 } else { 255.0 };
+    let gates = values
+        .iter()
+        .map(|&v| {
+            if v.is_finite() {
+                (v * scale + offset)
+                    .round()
+                    .clamp((GATE_RANGE_FOLDED + 1) as f32, max_raw) as u16
+            } else {
+                0
+            }
+        })
+        .collect();
+    MomentData {
+        moment,
+        header: GenericMomentHeader {
+            name: moment.block_name().to_string(),
+            n_gates: values.len() as u16,
+            first_gate_range: first_gate,
+            gate_spacing,
+            data_word_size,
+            scale,
+            offset,
+            ..GenericMomentHeader::default()
+        },
+        gates,
+    }
+}
+
+// A volume whose gate values are `value(moment, elevation, azimuth, range)`
+// for each requested moment, NaN leaving a gate empty.
+pub fn volume(
+    elevations: &[f32],
+    n_gates: usize,
+    nyquist: f32,
+    moments: &[Moment],
+    value: impl Fn(Moment, f32, f32, f32) -> f32,
+) -> Volume {
+    let sweeps = elevations
+        .iter()
+        .enumerate()
+        .map(|(i, &elevation)| Sweep {
+            elevation_number: i as i8 + 1,
+            elevation_angle: elevation,
+            radials: (0..360)
+                .map(|a| {
+                    let azimuth = a as f32 + 0.5;
+                    let mut header = DigitalRadarDataGenericFormatHeader::from(
+                        DigitalRadarDataGenericFormatHeaderRaw::new(),
+                    );
+                    header.radar_identifier = "KTLX".to_string();
+                    header.modified_julian_date = 20_000;
+                    header.collection_time = i as i32 * 20_000 + a * 50;
+                    header.azimuth_number = a as i16 + 1;
+                    header.azimuth_angle = azimuth;
+                    header.elevation_number = i as i8 + 1;
+                    header.elevation_angle = elevation;
+                    let moments = moments
+                        .iter()
+                        .map(|&moment| {
+                            let values: Vec<f32> = (0..n_gates)
+                                .map(|g| {
+                                    let range = FIRST_GATE as f32 + g as f32 * GATE_SPACING as f32;
+                                    value(moment, elevation, azimuth, range)
+                                })
+                                .collect();
+                            moment_data(moment, &values, FIRST_GATE, GATE_SPACING)
+                        })
+                        .collect();
+                    Radial {
+                        header,
+                        volume: Some(VolumeDataBlock {
+                            latitude: 35.333,
+                            longitude: -97.278,
+                            site_height: SITE_HEIGHT,
+                            feedhorn_height: FEEDHORN_HEIGHT,
+                            vcp_number: 212,
+                            ..VolumeDataBlock::default()
+                        }),
+                        elevation: None,
+                        radial: Some(RadialDataBlock {
+                            nyquist_velocity: nyquist,
+                            ..RadialDataBlock::default()
+                        }),
+                        moments,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    Volume {
+        header: VolumeHeader {
+            volumename: "AR2V0006.001".to_string(),
+            date: 20_000,
+            time: 0,
+            icao: "KTLX".to_string(),
+        },
+        sweeps,
+    }
+}